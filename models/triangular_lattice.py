@@ -327,27 +327,29 @@ if os.path.exists(os.path.join(rust_dir, "triangular_lattice_ext.h")):
     _lib = ffi.dlopen(os.path.join(rust_dir, "target", "release",
                                    "libtriangular_lattice_ext.so"))
 
-    class CoordMatrix:
-        """A class that encapsulates the matrix and provides methods that would
-        help memoery management across the FFI boundary
+    class CsrMatrix:
+        """A class that encapsulates a matrix built by Rust. Its arrays are
+        copied into numpy arrays allocated here, after which the matrix is
+        handed back to Rust to be freed
         """
 
-        def __init__(self, mat):
+        def __init__(self, mat, dtype=np.complex128):
             """Initializer
 
             Parameters
             --------------------
-            mat: CoordMatrix
+            mat: CsrHandle
+            dtype: np.complex128, np.complex64 or np.float64
+                the element type of the matrix returned by Rust
             """
-            self.__obj = mat  # the pointer to the pointers to the arrays
-            self.data = np.frombuffer(ffi.buffer(mat.data.ptr, mat.data.len * 16),
-                                      np.complex128)
-            self.col = np.frombuffer(ffi.buffer(mat.col.ptr, mat.col.len * 4),
-                                     np.int32)
-            self.row = np.frombuffer(ffi.buffer(mat.row.ptr, mat.row.len * 4),
-                                     np.int32)
-            self.ncols = mat.ncols
-            self.nrows = mat.nrows
+            self.__obj = mat
+            self.__dtype = np.dtype(dtype)
+            self.__dims, self.__fill, self.__free, self.__ctype = \
+                _csr_functions[self.__dtype]
+            dims = _call(self.__dims, ffi.addressof(mat), ctype="CsrDims")
+            self.ncols = dims.ncols
+            self.nrows = dims.nrows
+            self.nnz = dims.nnz
 
         def __enter__(self):
             """For use with context manager"""
@@ -355,23 +357,267 @@ if os.path.exists(os.path.join(rust_dir, "triangular_lattice_ext.h")):
 
         def __exit__(self, exc_type, exc_value, traceback):
             """For use with context manager"""
-            self.data = None
-            self.col = None
-            self.row = None
-            _lib.request_free(self.__obj)   # deallocates Rust object
+            self.__free(self.__obj)   # deallocates Rust object
             self.__obj = None
 
         def to_csc(self):
             """Returns a CSC matrix"""
-            return sparse.csc_matrix((self.data, (self.col, self.row)),
-                                     shape=(self.nrows, self.ncols))
+            return self.to_csr().tocsc()
 
         def to_csr(self):
-            """Returns a CSR matrix"""
-            return sparse.csr_matrix((self.data, (self.col, self.row)),
-                                     shape=(self.nrows, self.ncols))
+            """Returns a CSR matrix whose arrays are filled in by Rust"""
+            data = np.empty(self.nnz, self.__dtype)
+            # Rust writes unsigned indices, which are reinterpreted in the
+            # signed types scipy uses. They never exceed the signed range
+            indices = np.empty(self.nnz, np.uint32)
+            indptr = np.empty(self.nrows + 1, np.uint64)
+            _check(self.__fill(ffi.addressof(self.__obj),
+                               ffi.cast(self.__ctype, data.ctypes.data),
+                               len(data),
+                               ffi.cast("uint32_t *", indices.ctypes.data),
+                               len(indices),
+                               ffi.cast("uint64_t *", indptr.ctypes.data),
+                               len(indptr)))
+            return sparse.csr_matrix(
+                (data, indices.view(np.int32), indptr.view(np.int64)),
+                shape=(self.nrows, self.ncols))
+
+    # how the matrices of each element type are sized, copied out and freed
+    _csr_functions = {
+        np.dtype(np.complex128): (_lib.csr_dims, _lib.csr_fill,
+                                  _lib.request_free, "CComplex_f64 *"),
+        np.dtype(np.complex64): (_lib.csr_dims_f32, _lib.csr_fill_f32,
+                                 _lib.request_free_f32, "CComplex_f32 *"),
+        np.dtype(np.float64): (_lib.csr_dims_real, _lib.csr_fill_real,
+                               _lib.request_free_real, "double *"),
+    }
+
+    # exceptions raised for the status codes returned by Rust. See src/ffi.rs
+    _errors = {1: ValueError, 2: IOError, 3: RuntimeError, 4: RuntimeError}
+
+    def _check(status):
+        """Raises the error reported by Rust, if any"""
+        if status != 0:
+            msg = ffi.string(_lib.last_error_message()).decode()
+            raise _errors.get(status, RuntimeError)(msg)
+
+    def _call(f, *args, ctype="CsrHandle_CComplex_f64"):
+        """Calls a Rust function that writes its result to an output pointer
+        passed as the last argument, and returns the result
+        """
+        out = ffi.new(ctype + " *")
+        _check(f(*args, out))
+        return out[0]
+
+    def set_num_threads(n):
+        """set the number of threads used to construct bases and matrices
+
+        Parameters
+        --------------------
+        n: int
+            the number of threads. 0 restores the default of one thread per
+            logical core
+        """
+        _lib.set_num_threads(n)
+
+    class Sector:
+        """A momentum sector whose Bloch basis is built once by Rust and reused
+        for every operator constructed against it. Use as a context manager so
+        the basis is freed when done.
+        """
+
+        def __init__(self, Nx, Ny, kx, ky, nup=None):
+            """Initializer
+
+            Parameters
+            --------------------
+            Nx: int
+                lattice length in the x-direction
+            Ny: int
+                lattice length in the y-direction
+            kx: int
+                the x-component of lattice momentum * Nx / 2π in a [0, 2π)
+                Brillouin zone
+            ky: int
+                the y-component of lattice momentum * Nx / 2π in a [0, 2π)
+                Brillouin zone
+            nup: int or None
+                the total number of sites with a spin-up. None if total Sz is
+                not conserved
+            """
+            if nup is None:
+                self.__obj = _call(_lib.sector_k, Nx, Ny, kx, ky,
+                                   ctype="Sector *")
+            else:
+                self.__obj = _call(_lib.sector_ks, Nx, Ny, kx, ky, nup,
+                                   ctype="Sector *")
+            self.dim = _lib.sector_dim(self.__obj)
+
+        @classmethod
+        def _from_raw(cls, obj):
+            """Wraps a sector already built by Rust"""
+            sector = cls.__new__(cls)
+            sector.__obj = obj
+            sector.dim = _lib.sector_dim(obj)
+            return sector
+
+        def __enter__(self):
+            """For use with context manager"""
+            return self
+
+        def __exit__(self, exc_type, exc_value, traceback):
+            """For use with context manager"""
+            _lib.sector_free(self.__obj)   # deallocates Rust object
+            self.__obj = None
+
+        def save(self, path):
+            """Saves the basis to path in .npz format"""
+            path = ffi.new("char[]", os.fsencode(path))
+            _check(_lib.sector_save(self.__obj, path))
+
+        @classmethod
+        def load(cls, path):
+            """Loads a basis saved by Sector.save"""
+            path = ffi.new("char[]", os.fsencode(path))
+            return cls._from_raw(_call(_lib.sector_load, path,
+                                       ctype="Sector *"))
+
+        def inverse(self):
+            """Returns the Sector at momentum -k, built from this one"""
+            return Sector._from_raw(_call(_lib.sector_inverse, self.__obj,
+                                          ctype="Sector *"))
+
+        def invert(self, vectors):
+            """Returns the images of vectors under the inversion r -> -r in the
+            basis of inverse(). When the Hamiltonian commutes with inversion
+            its eigenvectors at k are carried onto those at -k with the same
+            energies, so the sector at -k need not be diagonalized
+
+            Parameters
+            --------------------
+            vectors: numpy.ndarray
+                an array of shape (n, dim) holding n vectors
+            """
+            vectors = np.ascontiguousarray(vectors, dtype=np.complex128)
+            inverted = np.empty_like(vectors)
+            _check(_lib.sector_invert(
+                self.__obj, ffi.cast("CComplex_f64 *", vectors.ctypes.data),
+                vectors.size // max(self.dim, 1),
+                ffi.cast("CComplex_f64 *", inverted.ctypes.data),
+                inverted.size))
+            return inverted
+
+        def __build(self, name, *args, single=False):
+            if single:
+                f, ctype, dtype = (getattr(_lib, name + "_f32"),
+                                   "CsrHandle_CComplex_f32", np.complex64)
+            else:
+                f, ctype, dtype = (getattr(_lib, name),
+                                   "CsrHandle_CComplex_f64", np.complex128)
+            with CsrMatrix(_call(f, self.__obj, *args, ctype=ctype),
+                           dtype) as csrmat:
+                return csrmat.to_csr()
+
+        # every operator could be built in single precision with single=True.
+        # The result shares the basis and the sparsity pattern of the double
+        # precision matrix, so vectors found with it make good starting points
+        # (v0 of eigsh) for refining in double precision
+
+        def h_ss_z(self, l, single=False):
+            return self.__build("sector_h_ss_z", l, single=single)
+
+        def h_ss_xy(self, l, single=False):
+            return self.__build("sector_h_ss_xy", l, single=single)
+
+        def h_ss_ppmm(self, l, single=False):
+            return self.__build("sector_h_ss_ppmm", l, single=single)
+
+        def h_ss_pmz(self, l, single=False):
+            return self.__build("sector_h_ss_pmz", l, single=single)
+
+        def h_sss_chi(self, single=False):
+            return self.__build("sector_h_sss_chi", single=single)
+
+        def h_ring(self, single=False):
+            return self.__build("sector_h_ring", single=single)
+
+        def h_ss_tensor(self, l, jmats, single=False):
+            """jmats: the 3x3 exchange matrices of the three bond directions"""
+            j = ffi.new("double[]", list(np.asarray(jmats, dtype=float).flatten()))
+            return self.__build("sector_h_ss_tensor", l, j, single=single)
+
+        def h_ss_dm(self, l, dvecs, single=False):
+            """dvecs: the D vectors of the three bond directions"""
+            d = ffi.new("double[]", list(np.asarray(dvecs, dtype=float).flatten()))
+            return self.__build("sector_h_ss_dm", l, d, single=single)
+
+        def ss_z(self, l, single=False):
+            return self.__build("sector_ss_z", l, single=single)
+
+        def ss_xy(self, l, single=False):
+            return self.__build("sector_ss_xy", l, single=single)
+
+        def chi_chi(self, dx, dy, o1, o2, single=False):
+            return self.__build("sector_chi_chi", dx, dy, o1, o2,
+                                single=single)
+
+        def chi_order(self, staggered=False, single=False):
+            return self.__build("sector_chi_order", staggered, single=single)
+
+    class Orbits:
+        """The translation orbits of the lattice, enumerated once by Rust and
+        shared by every momentum sector built from them. Use as a context
+        manager so the orbits are freed when done.
+        """
+
+        def __init__(self, Nx, Ny, nup=None):
+            """Initializer
+
+            Parameters
+            --------------------
+            Nx: int
+                lattice length in the x-direction
+            Ny: int
+                lattice length in the y-direction
+            nup: int or None
+                the total number of sites with a spin-up. None if total Sz is
+                not conserved
+            """
+            self.Nx = Nx
+            self.Ny = Ny
+            if nup is None:
+                self.__obj = _call(_lib.orbits_k, Nx, Ny, ctype="Orbits *")
+            else:
+                self.__obj = _call(_lib.orbits_ks, Nx, Ny, nup,
+                                   ctype="Orbits *")
+            self.norbits = _lib.orbits_len(self.__obj)
+
+        def __enter__(self):
+            """For use with context manager"""
+            return self
+
+        def __exit__(self, exc_type, exc_value, traceback):
+            """For use with context manager"""
+            _lib.orbits_free(self.__obj)   # deallocates Rust object
+            self.__obj = None
+
+        def sector(self, kx, ky):
+            """Returns the Sector with momentum (kx, ky)"""
+            obj = _call(_lib.orbits_sector, self.__obj, kx, ky,
+                        ctype="Sector *")
+            return Sector._from_raw(obj)
+
+        def sectors(self, momenta=None):
+            """Yields ((kx, ky), Sector) for each of the given momenta, or for
+            every momentum in the Brillouin zone if momenta is None
+            """
+            if momenta is None:
+                momenta = [(kx, ky) for kx in range(self.Nx)
+                           for ky in range(self.Ny)]
+            for kx, ky in momenta:
+                yield (kx, ky), self.sector(kx, ky)
 
-    def h_ss_z_consv_k(Nx, Ny, kx, ky, l):
+    def h_ss_z_consv_k(Nx, Ny, kx, ky, l, real=False):
         """construct the H_z matrix in the given momentum configuration
 
         Parameters
@@ -387,17 +633,26 @@ if os.path.exists(os.path.join(rust_dir, "triangular_lattice_ext.h")):
             the y-component of lattice momentum * Nx / 2π in a [0, 2π)
             Brillouin zone
         l:  int
+        real: bool
+            whether to return a real matrix. Only valid when the Bloch phases
+            are all real, i.e. 2 * kx % Nx == 0 and 2 * ky % Ny == 0
 
         Returns
         --------------------
         H: scipy.sparse.csr_matrix
         """
-        mat = _lib.k_h_ss_z(Nx, Ny, kx, ky, l)
-        with CoordMatrix(mat) as coordmat:
-            H = coordmat.to_csr()
+        if real:
+            mat = _call(_lib.k_h_ss_z_real, Nx, Ny, kx, ky, l,
+                        ctype="CsrHandle_f64")
+            dtype = np.float64
+        else:
+            mat = _call(_lib.k_h_ss_z, Nx, Ny, kx, ky, l)
+            dtype = np.complex128
+        with CsrMatrix(mat, dtype) as csrmat:
+            H = csrmat.to_csr()
         return H
 
-    def h_ss_xy_consv_k(Nx, Ny, kx, ky, l):
+    def h_ss_xy_consv_k(Nx, Ny, kx, ky, l, real=False):
         """construct the H_xy matrix in the given momentum configuration
 
         Parameters
@@ -413,14 +668,23 @@ if os.path.exists(os.path.join(rust_dir, "triangular_lattice_ext.h")):
             the y-component of lattice momentum * Nx / 2π in a [0, 2π)
             Brillouin zone
         l:  int
+        real: bool
+            whether to return a real matrix. Only valid when the Bloch phases
+            are all real, i.e. 2 * kx % Nx == 0 and 2 * ky % Ny == 0
 
         Returns
         --------------------
         H: scipy.sparse.csr_matrix
         """
-        mat = _lib.k_h_ss_xy(Nx, Ny, kx, ky, l)
-        with CoordMatrix(mat) as coordmat:
-            H = coordmat.to_csr()
+        if real:
+            mat = _call(_lib.k_h_ss_xy_real, Nx, Ny, kx, ky, l,
+                        ctype="CsrHandle_f64")
+            dtype = np.float64
+        else:
+            mat = _call(_lib.k_h_ss_xy, Nx, Ny, kx, ky, l)
+            dtype = np.complex128
+        with CsrMatrix(mat, dtype) as csrmat:
+            H = csrmat.to_csr()
         return H
 
     def h_ss_ppmm_consv_k(Nx, Ny, kx, ky, l):
@@ -444,9 +708,9 @@ if os.path.exists(os.path.join(rust_dir, "triangular_lattice_ext.h")):
         --------------------
         H: scipy.sparse.csr_matrix
         """
-        mat = _lib.k_h_ss_ppmm(Nx, Ny, kx, ky, l)
-        with CoordMatrix(mat) as coordmat:
-            H = coordmat.to_csr()
+        mat = _call(_lib.k_h_ss_ppmm, Nx, Ny, kx, ky, l)
+        with CsrMatrix(mat) as csrmat:
+            H = csrmat.to_csr()
         return H
 
     def h_ss_pmz_consv_k(Nx, Ny, kx, ky, l):
@@ -470,9 +734,9 @@ if os.path.exists(os.path.join(rust_dir, "triangular_lattice_ext.h")):
         --------------------
         H: scipy.sparse.csr_matrix
         """
-        mat = _lib.k_h_ss_pmz(Nx, Ny, kx, ky, l)
-        with CoordMatrix(mat) as coordmat:
-            H = coordmat.to_csr()
+        mat = _call(_lib.k_h_ss_pmz, Nx, Ny, kx, ky, l)
+        with CsrMatrix(mat) as csrmat:
+            H = csrmat.to_csr()
         return H
 
     def h_sss_chi_consv_k(Nx, Ny, kx, ky):
@@ -495,12 +759,113 @@ if os.path.exists(os.path.join(rust_dir, "triangular_lattice_ext.h")):
         --------------------
         H: scipy.sparse.csr_matrix
         """
-        mat = _lib.k_h_sss_chi(Nx, Ny, kx, ky)
-        with CoordMatrix(mat) as coordmat:
-            H = coordmat.to_csr()
+        mat = _call(_lib.k_h_sss_chi, Nx, Ny, kx, ky)
+        with CsrMatrix(mat) as csrmat:
+            H = csrmat.to_csr()
+        return H
+
+    def h_ring_consv_k(Nx, Ny, kx, ky, real=False):
+        """construct the four-spin ring exchange matrix Σ(P_ijkl + P_ijkl^-1)
+        summed over all elementary rhombi in the given momentum configuration
+
+        Parameters
+        --------------------
+        Nx: int
+            lattice length in the x-direction
+        Ny: int
+            lattice length in the y-direction
+        kx: int
+            the x-component of lattice momentum * Nx / 2π in a [0, 2π)
+            Brillouin zone
+        ky: int
+            the y-component of lattice momentum * Nx / 2π in a [0, 2π)
+            Brillouin zone
+        real: bool
+            whether to return a real matrix. Only valid when the Bloch phases
+            are all real, i.e. 2 * kx % Nx == 0 and 2 * ky % Ny == 0
+
+        Returns
+        --------------------
+        H: scipy.sparse.csr_matrix
+        """
+        if real:
+            mat = _call(_lib.k_h_ring_real, Nx, Ny, kx, ky,
+                        ctype="CsrHandle_f64")
+            dtype = np.float64
+        else:
+            mat = _call(_lib.k_h_ring, Nx, Ny, kx, ky)
+            dtype = np.complex128
+        with CsrMatrix(mat, dtype) as csrmat:
+            H = csrmat.to_csr()
+        return H
+
+    def h_ss_tensor_consv_k(Nx, Ny, kx, ky, l, jmats):
+        """construct the exchange matrix Σ_ij Σ_ab J_ij^ab * s^a_i * s^b_j with
+        a general 3x3 exchange matrix for each bond direction in the given
+        momentum configuration
+
+        Parameters
+        --------------------
+        Nx: int
+            lattice length in the x-direction
+        Ny: int
+            lattice length in the y-direction
+        kx: int
+            the x-component of lattice momentum * Nx / 2π in a [0, 2π)
+            Brillouin zone
+        ky: int
+            the y-component of lattice momentum * Nx / 2π in a [0, 2π)
+            Brillouin zone
+        l:  int
+        jmats: 3x3x3 array of floats
+            the exchange matrix J^ab (a, b = x, y, z) for each of the three
+            bond directions. The bond directions are the same as in
+            h_ss_dm_consv_k
+
+        Returns
+        --------------------
+        H: scipy.sparse.csr_matrix
+        """
+        j = ffi.new("double[]", list(np.asarray(jmats, dtype=float).flatten()))
+        mat = _call(_lib.k_h_ss_tensor, Nx, Ny, kx, ky, l, j)
+        with CsrMatrix(mat) as csrmat:
+            H = csrmat.to_csr()
+        return H
+
+    def h_ss_dm_consv_k(Nx, Ny, kx, ky, l, dvecs):
+        """construct the Dzyaloshinskii-Moriya matrix Σ D_ij · (S_i × S_j) in
+        the given momentum configuration
+
+        Parameters
+        --------------------
+        Nx: int
+            lattice length in the x-direction
+        Ny: int
+            lattice length in the y-direction
+        kx: int
+            the x-component of lattice momentum * Nx / 2π in a [0, 2π)
+            Brillouin zone
+        ky: int
+            the y-component of lattice momentum * Nx / 2π in a [0, 2π)
+            Brillouin zone
+        l:  int
+        dvecs: 3x3 array of floats
+            the vector (D_x, D_y, D_z) for each of the three bond directions.
+            The bonds point along a1, a2 and a3 (at 0, 2π/3 and 4π/3 from the
+            x-axis) for l = 1 and 3, and along b1, b2 and b3 (at π/6, 5π/6 and
+            3π/2) for l = 2
+
+        Returns
+        --------------------
+        H: scipy.sparse.csr_matrix
+        """
+        d = ffi.new("double[]", list(np.asarray(dvecs, dtype=float).flatten()))
+        mat = _call(_lib.k_h_ss_dm, Nx, Ny, kx, ky, l, d)
+        with CsrMatrix(mat) as csrmat:
+            H = csrmat.to_csr()
         return H
 
-    def h_ss_z_consv_k_s(Nx, Ny, kx, ky, nup, l):
+    def h_ss_z_consv_k_s(Nx, Ny, kx, ky, nup, l, real=False):
         """construct the H_z matrix in the given momentum configuration
 
         Parameters
@@ -518,17 +883,26 @@ if os.path.exists(os.path.join(rust_dir, "triangular_lattice_ext.h")):
         nup: int
             the total number of sites with a spin-up
         l:  int
+        real: bool
+            whether to return a real matrix. Only valid when the Bloch phases
+            are all real, i.e. 2 * kx % Nx == 0 and 2 * ky % Ny == 0
 
         Returns
         --------------------
         H: scipy.sparse.csr_matrix
         """
-        mat = _lib.ks_h_ss_z(Nx, Ny, kx, ky, nup, l)
-        with CoordMatrix(mat) as coordmat:
-            H = coordmat.to_csr()
+        if real:
+            mat = _call(_lib.ks_h_ss_z_real, Nx, Ny, kx, ky, nup, l,
+                        ctype="CsrHandle_f64")
+            dtype = np.float64
+        else:
+            mat = _call(_lib.ks_h_ss_z, Nx, Ny, kx, ky, nup, l)
+            dtype = np.complex128
+        with CsrMatrix(mat, dtype) as csrmat:
+            H = csrmat.to_csr()
         return H
 
-    def h_ss_xy_consv_k_s(Nx, Ny, kx, ky, nup, l):
+    def h_ss_xy_consv_k_s(Nx, Ny, kx, ky, nup, l, real=False):
         """construct the H_xy matrix in the given momentum configuration
 
         Parameters
@@ -546,14 +920,92 @@ if os.path.exists(os.path.join(rust_dir, "triangular_lattice_ext.h")):
         nup: int
             the total number of sites with a spin-up
         l:  int
+        real: bool
+            whether to return a real matrix. Only valid when the Bloch phases
+            are all real, i.e. 2 * kx % Nx == 0 and 2 * ky % Ny == 0
+
+        Returns
+        --------------------
+        H: scipy.sparse.csr_matrix
+        """
+        if real:
+            mat = _call(_lib.ks_h_ss_xy_real, Nx, Ny, kx, ky, nup, l,
+                        ctype="CsrHandle_f64")
+            dtype = np.float64
+        else:
+            mat = _call(_lib.ks_h_ss_xy, Nx, Ny, kx, ky, nup, l)
+            dtype = np.complex128
+        with CsrMatrix(mat, dtype) as csrmat:
+            H = csrmat.to_csr()
+        return H
+
+    def h_ring_consv_k_s(Nx, Ny, kx, ky, nup, real=False):
+        """construct the four-spin ring exchange matrix Σ(P_ijkl + P_ijkl^-1)
+        summed over all elementary rhombi in the given momentum configuration
+
+        Parameters
+        --------------------
+        Nx: int
+            lattice length in the x-direction
+        Ny: int
+            lattice length in the y-direction
+        kx: int
+            the x-component of lattice momentum * Nx / 2π in a [0, 2π)
+            Brillouin zone
+        ky: int
+            the y-component of lattice momentum * Nx / 2π in a [0, 2π)
+            Brillouin zone
+        nup: int
+            the total number of sites with a spin-up
+        real: bool
+            whether to return a real matrix. Only valid when the Bloch phases
+            are all real, i.e. 2 * kx % Nx == 0 and 2 * ky % Ny == 0
+
+        Returns
+        --------------------
+        H: scipy.sparse.csr_matrix
+        """
+        if real:
+            mat = _call(_lib.ks_h_ring_real, Nx, Ny, kx, ky, nup,
+                        ctype="CsrHandle_f64")
+            dtype = np.float64
+        else:
+            mat = _call(_lib.ks_h_ring, Nx, Ny, kx, ky, nup)
+            dtype = np.complex128
+        with CsrMatrix(mat, dtype) as csrmat:
+            H = csrmat.to_csr()
+        return H
+
+    def h_ss_dm_z_consv_k_s(Nx, Ny, kx, ky, nup, l, dz):
+        """construct the out-of-plane Dzyaloshinskii-Moriya matrix
+        Σ D^z_ij (S_i × S_j)^z in the given momentum configuration
+
+        Parameters
+        --------------------
+        Nx: int
+            lattice length in the x-direction
+        Ny: int
+            lattice length in the y-direction
+        kx: int
+            the x-component of lattice momentum * Nx / 2π in a [0, 2π)
+            Brillouin zone
+        ky: int
+            the y-component of lattice momentum * Nx / 2π in a [0, 2π)
+            Brillouin zone
+        nup: int
+            the total number of sites with a spin-up
+        l:  int
+        dz: list of floats
+            D_z for each of the three bond directions. See h_ss_dm_consv_k
 
         Returns
         --------------------
         H: scipy.sparse.csr_matrix
         """
-        mat = _lib.ks_h_ss_xy(Nx, Ny, kx, ky, nup, l)
-        with CoordMatrix(mat) as coordmat:
-            H = coordmat.to_csr()
+        d = ffi.new("double[]", list(dz))
+        mat = _call(_lib.ks_h_ss_dm_z, Nx, Ny, kx, ky, nup, l, d)
+        with CsrMatrix(mat) as csrmat:
+            H = csrmat.to_csr()
         return H
 
     def h_sss_chi_consv_k_s(Nx, Ny, kx, ky, nup):
@@ -577,12 +1029,99 @@ if os.path.exists(os.path.join(rust_dir, "triangular_lattice_ext.h")):
         --------------------
         H: scipy.sparse.csr_matrix
         """
-        mat = _lib.ks_h_sss_chi(Nx, Ny, kx, ky, nup)
-        with CoordMatrix(mat) as coordmat:
-            H = coordmat.to_csr()
+        mat = _call(_lib.ks_h_sss_chi, Nx, Ny, kx, ky, nup)
+        with CsrMatrix(mat) as csrmat:
+            H = csrmat.to_csr()
+        return H
+
+    def h_ss_z_consv_k_p(Nx, Ny, kx, ky, parity, l):
+        """construct the H_z matrix in the given momentum configuration
+        and parity of the number of spins pointing up
+
+        Parameters
+        --------------------
+        Nx: int
+            lattice length in the x-direction
+        Ny: int
+            lattice length in the y-direction
+        kx: int
+            the x-component of lattice momentum * Nx / 2π in a [0, 2π)
+            Brillouin zone
+        ky: int
+            the y-component of lattice momentum * Nx / 2π in a [0, 2π)
+            Brillouin zone
+        parity: int
+            0 if the number of sites with a spin-up is even, 1 if odd
+        l:  int
+
+        Returns
+        --------------------
+        H: scipy.sparse.csr_matrix
+        """
+        mat = _call(_lib.kp_h_ss_z, Nx, Ny, kx, ky, parity, l)
+        with CsrMatrix(mat) as csrmat:
+            H = csrmat.to_csr()
+        return H
+
+    def h_ss_xy_consv_k_p(Nx, Ny, kx, ky, parity, l):
+        """construct the H_xy matrix in the given momentum configuration
+        and parity of the number of spins pointing up
+
+        Parameters
+        --------------------
+        Nx: int
+            lattice length in the x-direction
+        Ny: int
+            lattice length in the y-direction
+        kx: int
+            the x-component of lattice momentum * Nx / 2π in a [0, 2π)
+            Brillouin zone
+        ky: int
+            the y-component of lattice momentum * Nx / 2π in a [0, 2π)
+            Brillouin zone
+        parity: int
+            0 if the number of sites with a spin-up is even, 1 if odd
+        l:  int
+
+        Returns
+        --------------------
+        H: scipy.sparse.csr_matrix
+        """
+        mat = _call(_lib.kp_h_ss_xy, Nx, Ny, kx, ky, parity, l)
+        with CsrMatrix(mat) as csrmat:
+            H = csrmat.to_csr()
+        return H
+
+    def h_ss_ppmm_consv_k_p(Nx, Ny, kx, ky, parity, l):
+        """construct the H_ppmm matrix in the given momentum configuration
+        and parity of the number of spins pointing up
+
+        Parameters
+        --------------------
+        Nx: int
+            lattice length in the x-direction
+        Ny: int
+            lattice length in the y-direction
+        kx: int
+            the x-component of lattice momentum * Nx / 2π in a [0, 2π)
+            Brillouin zone
+        ky: int
+            the y-component of lattice momentum * Nx / 2π in a [0, 2π)
+            Brillouin zone
+        parity: int
+            0 if the number of sites with a spin-up is even, 1 if odd
+        l:  int
+
+        Returns
+        --------------------
+        H: scipy.sparse.csr_matrix
+        """
+        mat = _call(_lib.kp_h_ss_ppmm, Nx, Ny, kx, ky, parity, l)
+        with CsrMatrix(mat) as csrmat:
+            H = csrmat.to_csr()
         return H
 
-    def ss_z_consv_k(Nx, Ny, kx, ky, l):
+    def ss_z_consv_k(Nx, Ny, kx, ky, l, real=False):
         """construct the Σsz_i * sz_j operators with the given separation
         with translational symmetry taken into account
 
@@ -600,17 +1139,26 @@ if os.path.exists(os.path.join(rust_dir, "triangular_lattice_ext.h")):
             Brillouin zone
         l:  int
             the separation between sites: |i - j|
+        real: bool
+            whether to return a real matrix. Only valid when the Bloch phases
+            are all real, i.e. 2 * kx % Nx == 0 and 2 * ky % Ny == 0
 
         Returns
         --------------------
         ss_z: scipy.sparse.csr_matrix
         """
-        mat = _lib.k_ss_z(Nx, Ny, kx, ky, l)
-        with CoordMatrix(mat) as coordmat:
-            op = coordmat.to_csr()
+        if real:
+            mat = _call(_lib.k_ss_z_real, Nx, Ny, kx, ky, l,
+                        ctype="CsrHandle_f64")
+            dtype = np.float64
+        else:
+            mat = _call(_lib.k_ss_z, Nx, Ny, kx, ky, l)
+            dtype = np.complex128
+        with CsrMatrix(mat, dtype) as csrmat:
+            op = csrmat.to_csr()
         return op
 
-    def ss_xy_consv_k(Nx, Ny, kx, ky, l):
+    def ss_xy_consv_k(Nx, Ny, kx, ky, l, real=False):
         """construct the Σ(sx_i * sx_j + sy_i * sy_j) operators with the given
         separation with translational symmetry taken into account
 
@@ -628,17 +1176,26 @@ if os.path.exists(os.path.join(rust_dir, "triangular_lattice_ext.h")):
             Brillouin zone
         l:  int
             the separation between sites: |i - j|
+        real: bool
+            whether to return a real matrix. Only valid when the Bloch phases
+            are all real, i.e. 2 * kx % Nx == 0 and 2 * ky % Ny == 0
 
         Returns
         --------------------
         ss_xy: scipy.sparse.csr_matrix
         """
-        mat = _lib.k_ss_xy(Nx, Ny, kx, ky, l)
-        with CoordMatrix(mat) as coordmat:
-            op = coordmat.to_csr()
+        if real:
+            mat = _call(_lib.k_ss_xy_real, Nx, Ny, kx, ky, l,
+                        ctype="CsrHandle_f64")
+            dtype = np.float64
+        else:
+            mat = _call(_lib.k_ss_xy, Nx, Ny, kx, ky, l)
+            dtype = np.complex128
+        with CsrMatrix(mat, dtype) as csrmat:
+            op = csrmat.to_csr()
         return op
 
-    def ss_z_consv_k_s(Nx, Ny, kx, ky, nup, l):
+    def ss_z_consv_k_s(Nx, Ny, kx, ky, nup, l, real=False):
         """construct the Σsz_i * sz_j operators with the given separation
         with translational symmetry taken into account
 
@@ -658,17 +1215,26 @@ if os.path.exists(os.path.join(rust_dir, "triangular_lattice_ext.h")):
             the total number of sites with a spin-up
         l:  int
             the separation between sites: |i - j|
+        real: bool
+            whether to return a real matrix. Only valid when the Bloch phases
+            are all real, i.e. 2 * kx % Nx == 0 and 2 * ky % Ny == 0
 
         Returns
         --------------------
         ss_z: scipy.sparse.csr_matrix
         """
-        mat = _lib.ks_ss_z(Nx, Ny, kx, ky, nup, l)
-        with CoordMatrix(mat) as coordmat:
-            op = coordmat.to_csr()
+        if real:
+            mat = _call(_lib.ks_ss_z_real, Nx, Ny, kx, ky, nup, l,
+                        ctype="CsrHandle_f64")
+            dtype = np.float64
+        else:
+            mat = _call(_lib.ks_ss_z, Nx, Ny, kx, ky, nup, l)
+            dtype = np.complex128
+        with CsrMatrix(mat, dtype) as csrmat:
+            op = csrmat.to_csr()
         return op
 
-    def ss_xy_consv_k_s(Nx, Ny, kx, ky, nup, l):
+    def ss_xy_consv_k_s(Nx, Ny, kx, ky, nup, l, real=False):
         """construct the Σ(sx_i * sx_j + sy_i * sy_j) operators with the given
         separation with translational symmetry taken into account
 
@@ -688,38 +1254,811 @@ if os.path.exists(os.path.join(rust_dir, "triangular_lattice_ext.h")):
             the total number of sites with a spin-up
         l:  int
             the separation between sites: |i - j|
+        real: bool
+            whether to return a real matrix. Only valid when the Bloch phases
+            are all real, i.e. 2 * kx % Nx == 0 and 2 * ky % Ny == 0
 
         Returns
         --------------------
         ss_xy: scipy.sparse.csr_matrix
         """
-        mat = _lib.ks_ss_xy(Nx, Ny, kx, ky, nup, l)
-        with CoordMatrix(mat) as coordmat:
-            op = coordmat.to_csr()
+        if real:
+            mat = _call(_lib.ks_ss_xy_real, Nx, Ny, kx, ky, nup, l,
+                        ctype="CsrHandle_f64")
+            dtype = np.float64
+        else:
+            mat = _call(_lib.ks_ss_xy, Nx, Ny, kx, ky, nup, l)
+            dtype = np.complex128
+        with CsrMatrix(mat, dtype) as csrmat:
+            op = csrmat.to_csr()
         return op
 
-    def min_necessary_ks(Nx, Ny):
-        """Returns the momentum that we absolutely need to compute
+    def chi_chi_consv_k(Nx, Ny, kx, ky, dx, dy, o1, o2):
+        """construct the chirality correlator Σ_r χ_Δ(r) * χ_Δ'(r + d) with
+        translational symmetry taken into account
 
         Parameters
         --------------------
         Nx: int
+            lattice length in the x-direction
         Ny: int
+            lattice length in the y-direction
+        kx: int
+            the x-component of lattice momentum * Nx / 2π in a [0, 2π)
+            Brillouin zone
+        ky: int
+            the y-component of lattice momentum * Ny / 2π in a [0, 2π)
+            Brillouin zone
+        dx: int
+            the x-component of the displacement d between the triangles
+        dy: int
+            the y-component of the displacement d between the triangles
+        o1: int
+            orientation of the first triangle. 0 for upright and 1 for
+            inverted
+        o2: int
+            orientation of the second triangle
 
         Returns
         --------------------
-        list of ints
+        chi_chi: scipy.sparse.csr_matrix
         """
-        ks = []
-        arrs = []
-        for kx in range(Nx):
-            for ky in range(Ny):
-                arr = np.outer(np.exp(2j * np.pi * kx * np.arange(Nx) / Nx),
-                               np.exp(2j * np.pi * ky * np.arange(Ny) / Ny))
-                for arr0 in arrs:
-                    if np.allclose(arr0, arr) or np.allclose(arr0, arr.conjugate()):
-                        break
-                else:
-                    ks.append((kx, ky))
-                    arrs.append(arr)
-        return ks
+        mat = _call(_lib.k_chi_chi, Nx, Ny, kx, ky, dx, dy, o1, o2)
+        with CsrMatrix(mat) as csrmat:
+            op = csrmat.to_csr()
+        return op
+
+    def chi_order_consv_k(Nx, Ny, kx, ky, staggered=False):
+        """construct the chiral order parameter Σ_Δ χ_Δ with translational
+        symmetry taken into account
+
+        Parameters
+        --------------------
+        Nx: int
+            lattice length in the x-direction
+        Ny: int
+            lattice length in the y-direction
+        kx: int
+            the x-component of lattice momentum * Nx / 2π in a [0, 2π)
+            Brillouin zone
+        ky: int
+            the y-component of lattice momentum * Ny / 2π in a [0, 2π)
+            Brillouin zone
+        staggered: bool
+            flip the sign of the contributions from inverted triangles
+
+        Returns
+        --------------------
+        chi: scipy.sparse.csr_matrix
+        """
+        mat = _call(_lib.k_chi_order, Nx, Ny, kx, ky, staggered)
+        with CsrMatrix(mat) as csrmat:
+            op = csrmat.to_csr()
+        return op
+
+    def chi_chi_consv_k_s(Nx, Ny, kx, ky, nup, dx, dy, o1, o2):
+        """construct the chirality correlator Σ_r χ_Δ(r) * χ_Δ'(r + d) with
+        translational symmetry and total Sz taken into account
+
+        Parameters
+        --------------------
+        Nx: int
+            lattice length in the x-direction
+        Ny: int
+            lattice length in the y-direction
+        kx: int
+            the x-component of lattice momentum * Nx / 2π in a [0, 2π)
+            Brillouin zone
+        ky: int
+            the y-component of lattice momentum * Ny / 2π in a [0, 2π)
+            Brillouin zone
+        nup: int
+            the total number of sites with a spin-up
+        dx: int
+            the x-component of the displacement d between the triangles
+        dy: int
+            the y-component of the displacement d between the triangles
+        o1: int
+            orientation of the first triangle. 0 for upright and 1 for
+            inverted
+        o2: int
+            orientation of the second triangle
+
+        Returns
+        --------------------
+        chi_chi: scipy.sparse.csr_matrix
+        """
+        mat = _call(_lib.ks_chi_chi, Nx, Ny, kx, ky, nup, dx, dy, o1, o2)
+        with CsrMatrix(mat) as csrmat:
+            op = csrmat.to_csr()
+        return op
+
+    def chi_order_consv_k_s(Nx, Ny, kx, ky, nup, staggered=False):
+        """construct the chiral order parameter Σ_Δ χ_Δ with translational
+        symmetry and total Sz taken into account
+
+        Parameters
+        --------------------
+        Nx: int
+            lattice length in the x-direction
+        Ny: int
+            lattice length in the y-direction
+        kx: int
+            the x-component of lattice momentum * Nx / 2π in a [0, 2π)
+            Brillouin zone
+        ky: int
+            the y-component of lattice momentum * Ny / 2π in a [0, 2π)
+            Brillouin zone
+        nup: int
+            the total number of sites with a spin-up
+        staggered: bool
+            flip the sign of the contributions from inverted triangles
+
+        Returns
+        --------------------
+        chi: scipy.sparse.csr_matrix
+        """
+        mat = _call(_lib.ks_chi_order, Nx, Ny, kx, ky, nup, staggered)
+        with CsrMatrix(mat) as csrmat:
+            op = csrmat.to_csr()
+        return op
+
+    def h_ss_z_consv_kr(Nx, Ny, t1, t2, kx, ky, l):
+        """construct the H_z matrix in the given momentum configuration
+        of a subgroup of the lattice translations
+
+        Parameters
+        --------------------
+        Nx: int
+            lattice length in the x-direction
+        Ny: int
+            lattice length in the y-direction
+        t1: tuple of ints
+            first generator of the translation subgroup in lattice spacings,
+            e.g. (1, 1) for the √3×√3 superlattice
+        t2: tuple of ints
+            second generator of the translation subgroup, e.g. (-1, 2) for the
+            √3×√3 superlattice
+        kx: int
+            the x-component of lattice momentum * Nx / 2π in a [0, 2π)
+            Brillouin zone
+        ky: int
+            the y-component of lattice momentum * Ny / 2π in a [0, 2π)
+            Brillouin zone
+        l:  int
+
+        Returns
+        --------------------
+        H: scipy.sparse.csr_matrix
+        """
+        mat = _call(_lib.kr_h_ss_z, Nx, Ny, t1[0], t1[1], t2[0], t2[1], kx, ky,
+                    l)
+        with CsrMatrix(mat) as csrmat:
+            H = csrmat.to_csr()
+        return H
+
+    def h_ss_xy_consv_kr(Nx, Ny, t1, t2, kx, ky, l):
+        """construct the H_xy matrix in the given momentum configuration
+        of a subgroup of the lattice translations
+
+        Parameters
+        --------------------
+        Nx: int
+            lattice length in the x-direction
+        Ny: int
+            lattice length in the y-direction
+        t1: tuple of ints
+            first generator of the translation subgroup in lattice spacings,
+            e.g. (1, 1) for the √3×√3 superlattice
+        t2: tuple of ints
+            second generator of the translation subgroup, e.g. (-1, 2) for the
+            √3×√3 superlattice
+        kx: int
+            the x-component of lattice momentum * Nx / 2π in a [0, 2π)
+            Brillouin zone
+        ky: int
+            the y-component of lattice momentum * Ny / 2π in a [0, 2π)
+            Brillouin zone
+        l:  int
+
+        Returns
+        --------------------
+        H: scipy.sparse.csr_matrix
+        """
+        mat = _call(_lib.kr_h_ss_xy, Nx, Ny, t1[0], t1[1], t2[0], t2[1], kx,
+                    ky, l)
+        with CsrMatrix(mat) as csrmat:
+            H = csrmat.to_csr()
+        return H
+
+    def h_ss_ppmm_consv_kr(Nx, Ny, t1, t2, kx, ky, l):
+        """construct the H_ppmm matrix in the given momentum configuration
+        of a subgroup of the lattice translations
+
+        Parameters
+        --------------------
+        Nx: int
+            lattice length in the x-direction
+        Ny: int
+            lattice length in the y-direction
+        t1: tuple of ints
+            first generator of the translation subgroup in lattice spacings,
+            e.g. (1, 1) for the √3×√3 superlattice
+        t2: tuple of ints
+            second generator of the translation subgroup, e.g. (-1, 2) for the
+            √3×√3 superlattice
+        kx: int
+            the x-component of lattice momentum * Nx / 2π in a [0, 2π)
+            Brillouin zone
+        ky: int
+            the y-component of lattice momentum * Ny / 2π in a [0, 2π)
+            Brillouin zone
+        l:  int
+
+        Returns
+        --------------------
+        H: scipy.sparse.csr_matrix
+        """
+        mat = _call(_lib.kr_h_ss_ppmm, Nx, Ny, t1[0], t1[1], t2[0], t2[1], kx,
+                    ky, l)
+        with CsrMatrix(mat) as csrmat:
+            H = csrmat.to_csr()
+        return H
+
+    def h_ss_pmz_consv_kr(Nx, Ny, t1, t2, kx, ky, l):
+        """construct the H_pmz matrix in the given momentum configuration
+        of a subgroup of the lattice translations
+
+        Parameters
+        --------------------
+        Nx: int
+            lattice length in the x-direction
+        Ny: int
+            lattice length in the y-direction
+        t1: tuple of ints
+            first generator of the translation subgroup in lattice spacings,
+            e.g. (1, 1) for the √3×√3 superlattice
+        t2: tuple of ints
+            second generator of the translation subgroup, e.g. (-1, 2) for the
+            √3×√3 superlattice
+        kx: int
+            the x-component of lattice momentum * Nx / 2π in a [0, 2π)
+            Brillouin zone
+        ky: int
+            the y-component of lattice momentum * Ny / 2π in a [0, 2π)
+            Brillouin zone
+        l:  int
+
+        Returns
+        --------------------
+        H: scipy.sparse.csr_matrix
+        """
+        mat = _call(_lib.kr_h_ss_pmz, Nx, Ny, t1[0], t1[1], t2[0], t2[1], kx,
+                    ky, l)
+        with CsrMatrix(mat) as csrmat:
+            H = csrmat.to_csr()
+        return H
+
+    def h_sss_chi_consv_kr(Nx, Ny, t1, t2, kx, ky):
+        """construct the H_chi matrix in the given momentum configuration
+        of a subgroup of the lattice translations
+
+        Parameters
+        --------------------
+        Nx: int
+            lattice length in the x-direction
+        Ny: int
+            lattice length in the y-direction
+        t1: tuple of ints
+            first generator of the translation subgroup in lattice spacings,
+            e.g. (1, 1) for the √3×√3 superlattice
+        t2: tuple of ints
+            second generator of the translation subgroup, e.g. (-1, 2) for the
+            √3×√3 superlattice
+        kx: int
+            the x-component of lattice momentum * Nx / 2π in a [0, 2π)
+            Brillouin zone
+        ky: int
+            the y-component of lattice momentum * Ny / 2π in a [0, 2π)
+            Brillouin zone
+
+        Returns
+        --------------------
+        H: scipy.sparse.csr_matrix
+        """
+        mat = _call(_lib.kr_h_sss_chi, Nx, Ny, t1[0], t1[1], t2[0], t2[1], kx,
+                    ky)
+        with CsrMatrix(mat) as csrmat:
+            H = csrmat.to_csr()
+        return H
+
+    def h_s_z_consv_kr(Nx, Ny, t1, t2, kx, ky, fields):
+        """construct the Σh_r * sz_r matrix with one h per sublattice of the
+        translation subgroup
+
+        Parameters
+        --------------------
+        Nx: int
+            lattice length in the x-direction
+        Ny: int
+            lattice length in the y-direction
+        t1: tuple of ints
+            first generator of the translation subgroup in lattice spacings,
+            e.g. (1, 1) for the √3×√3 superlattice
+        t2: tuple of ints
+            second generator of the translation subgroup, e.g. (-1, 2) for the
+            √3×√3 superlattice
+        kx: int
+            the x-component of lattice momentum * Nx / 2π in a [0, 2π)
+            Brillouin zone
+        ky: int
+            the y-component of lattice momentum * Ny / 2π in a [0, 2π)
+            Brillouin zone
+        fields: list of floats
+            the field h along z on each sublattice. Sublattices are labeled in
+            order of the lowest site index they contain
+
+        Returns
+        --------------------
+        H: scipy.sparse.csr_matrix
+        """
+        hs = ffi.new("double[]", list(fields))
+        mat = _call(_lib.kr_h_s_z, Nx, Ny, t1[0], t1[1], t2[0], t2[1], kx, ky,
+                    hs, len(fields))
+        with CsrMatrix(mat) as csrmat:
+            H = csrmat.to_csr()
+        return H
+
+    def h_s_xy_consv_kr(Nx, Ny, t1, t2, kx, ky, fields):
+        """construct the Σ(hx_r * sx_r + hy_r * sy_r) matrix with one (hx, hy)
+        per sublattice of the translation subgroup
+
+        Parameters
+        --------------------
+        Nx: int
+            lattice length in the x-direction
+        Ny: int
+            lattice length in the y-direction
+        t1: tuple of ints
+            first generator of the translation subgroup in lattice spacings,
+            e.g. (1, 1) for the √3×√3 superlattice
+        t2: tuple of ints
+            second generator of the translation subgroup, e.g. (-1, 2) for the
+            √3×√3 superlattice
+        kx: int
+            the x-component of lattice momentum * Nx / 2π in a [0, 2π)
+            Brillouin zone
+        ky: int
+            the y-component of lattice momentum * Ny / 2π in a [0, 2π)
+            Brillouin zone
+        fields: list of tuples of floats
+            the in-plane field (hx, hy) on each sublattice. Sublattices are
+            labeled in order of the lowest site index they contain
+
+        Returns
+        --------------------
+        H: scipy.sparse.csr_matrix
+        """
+        hs = ffi.new("double[]", [h for pair in fields for h in pair])
+        mat = _call(_lib.kr_h_s_xy, Nx, Ny, t1[0], t1[1], t2[0], t2[1], kx,
+                    ky, hs, len(fields))
+        with CsrMatrix(mat) as csrmat:
+            H = csrmat.to_csr()
+        return H
+
+    def h_ss_z_consv_kr_s(Nx, Ny, t1, t2, kx, ky, nup, l):
+        """construct the H_z matrix in the given momentum configuration
+        of a subgroup of the lattice translations and total Sz
+
+        Parameters
+        --------------------
+        Nx: int
+            lattice length in the x-direction
+        Ny: int
+            lattice length in the y-direction
+        t1: tuple of ints
+            first generator of the translation subgroup in lattice spacings,
+            e.g. (1, 1) for the √3×√3 superlattice
+        t2: tuple of ints
+            second generator of the translation subgroup, e.g. (-1, 2) for the
+            √3×√3 superlattice
+        kx: int
+            the x-component of lattice momentum * Nx / 2π in a [0, 2π)
+            Brillouin zone
+        ky: int
+            the y-component of lattice momentum * Ny / 2π in a [0, 2π)
+            Brillouin zone
+        nup: int
+            the total number of sites with a spin-up
+        l:  int
+
+        Returns
+        --------------------
+        H: scipy.sparse.csr_matrix
+        """
+        mat = _call(_lib.ksr_h_ss_z, Nx, Ny, t1[0], t1[1], t2[0], t2[1], kx,
+                    ky, nup, l)
+        with CsrMatrix(mat) as csrmat:
+            H = csrmat.to_csr()
+        return H
+
+    def h_ss_xy_consv_kr_s(Nx, Ny, t1, t2, kx, ky, nup, l):
+        """construct the H_xy matrix in the given momentum configuration
+        of a subgroup of the lattice translations and total Sz
+
+        Parameters
+        --------------------
+        Nx: int
+            lattice length in the x-direction
+        Ny: int
+            lattice length in the y-direction
+        t1: tuple of ints
+            first generator of the translation subgroup in lattice spacings,
+            e.g. (1, 1) for the √3×√3 superlattice
+        t2: tuple of ints
+            second generator of the translation subgroup, e.g. (-1, 2) for the
+            √3×√3 superlattice
+        kx: int
+            the x-component of lattice momentum * Nx / 2π in a [0, 2π)
+            Brillouin zone
+        ky: int
+            the y-component of lattice momentum * Ny / 2π in a [0, 2π)
+            Brillouin zone
+        nup: int
+            the total number of sites with a spin-up
+        l:  int
+
+        Returns
+        --------------------
+        H: scipy.sparse.csr_matrix
+        """
+        mat = _call(_lib.ksr_h_ss_xy, Nx, Ny, t1[0], t1[1], t2[0], t2[1], kx,
+                    ky, nup, l)
+        with CsrMatrix(mat) as csrmat:
+            H = csrmat.to_csr()
+        return H
+
+    def h_sss_chi_consv_kr_s(Nx, Ny, t1, t2, kx, ky, nup):
+        """construct the H_chi matrix in the given momentum configuration
+        of a subgroup of the lattice translations and total Sz
+
+        Parameters
+        --------------------
+        Nx: int
+            lattice length in the x-direction
+        Ny: int
+            lattice length in the y-direction
+        t1: tuple of ints
+            first generator of the translation subgroup in lattice spacings,
+            e.g. (1, 1) for the √3×√3 superlattice
+        t2: tuple of ints
+            second generator of the translation subgroup, e.g. (-1, 2) for the
+            √3×√3 superlattice
+        kx: int
+            the x-component of lattice momentum * Nx / 2π in a [0, 2π)
+            Brillouin zone
+        ky: int
+            the y-component of lattice momentum * Ny / 2π in a [0, 2π)
+            Brillouin zone
+        nup: int
+            the total number of sites with a spin-up
+
+        Returns
+        --------------------
+        H: scipy.sparse.csr_matrix
+        """
+        mat = _call(_lib.ksr_h_sss_chi, Nx, Ny, t1[0], t1[1], t2[0], t2[1], kx,
+                    ky, nup)
+        with CsrMatrix(mat) as csrmat:
+            H = csrmat.to_csr()
+        return H
+
+    def h_s_z_consv_kr_s(Nx, Ny, t1, t2, kx, ky, nup, fields):
+        """construct the Σh_r * sz_r matrix with one h per sublattice of the
+        translation subgroup and total Sz conserved
+
+        Parameters
+        --------------------
+        Nx: int
+            lattice length in the x-direction
+        Ny: int
+            lattice length in the y-direction
+        t1: tuple of ints
+            first generator of the translation subgroup in lattice spacings,
+            e.g. (1, 1) for the √3×√3 superlattice
+        t2: tuple of ints
+            second generator of the translation subgroup, e.g. (-1, 2) for the
+            √3×√3 superlattice
+        kx: int
+            the x-component of lattice momentum * Nx / 2π in a [0, 2π)
+            Brillouin zone
+        ky: int
+            the y-component of lattice momentum * Ny / 2π in a [0, 2π)
+            Brillouin zone
+        nup: int
+            the total number of sites with a spin-up
+        fields: list of floats
+            the field h along z on each sublattice. Sublattices are labeled in
+            order of the lowest site index they contain
+
+        Returns
+        --------------------
+        H: scipy.sparse.csr_matrix
+        """
+        hs = ffi.new("double[]", list(fields))
+        mat = _call(_lib.ksr_h_s_z, Nx, Ny, t1[0], t1[1], t2[0], t2[1], kx,
+                    ky, nup, hs, len(fields))
+        with CsrMatrix(mat) as csrmat:
+            H = csrmat.to_csr()
+        return H
+
+    def h_ss_z_consv_s(Nx, Ny, nup, l):
+        """construct the H_z matrix with only total Sz conserved, in the basis
+        of all states with nup spins up in increasing order
+
+        Parameters
+        --------------------
+        Nx: int
+            lattice length in the x-direction
+        Ny: int
+            lattice length in the y-direction
+        nup: int
+            the total number of sites with a spin-up
+        l:  int
+
+        Returns
+        --------------------
+        H: scipy.sparse.csr_matrix
+        """
+        mat = _call(_lib.s_h_ss_z, Nx, Ny, nup, l)
+        with CsrMatrix(mat) as csrmat:
+            H = csrmat.to_csr()
+        return H
+
+    def h_ss_xy_consv_s(Nx, Ny, nup, l):
+        """construct the H_xy matrix with only total Sz conserved. See
+        h_ss_z_consv_s
+
+        Parameters
+        --------------------
+        Nx: int
+            lattice length in the x-direction
+        Ny: int
+            lattice length in the y-direction
+        nup: int
+            the total number of sites with a spin-up
+        l:  int
+
+        Returns
+        --------------------
+        H: scipy.sparse.csr_matrix
+        """
+        mat = _call(_lib.s_h_ss_xy, Nx, Ny, nup, l)
+        with CsrMatrix(mat) as csrmat:
+            H = csrmat.to_csr()
+        return H
+
+    def h_sss_chi_consv_s(Nx, Ny, nup):
+        """construct the H_chi matrix with only total Sz conserved. See
+        h_ss_z_consv_s
+
+        Parameters
+        --------------------
+        Nx: int
+            lattice length in the x-direction
+        Ny: int
+            lattice length in the y-direction
+        nup: int
+            the total number of sites with a spin-up
+
+        Returns
+        --------------------
+        H: scipy.sparse.csr_matrix
+        """
+        mat = _call(_lib.s_h_sss_chi, Nx, Ny, nup)
+        with CsrMatrix(mat) as csrmat:
+            H = csrmat.to_csr()
+        return H
+
+    def _bonds(bonds, nsites):
+        """flattens a list of bonds (i, j, ..., J) into arrays of the sites
+        and of the couplings
+        """
+        sites = ffi.new("uint32_t[]", [s for b in bonds for s in b[:nsites]])
+        couplings = ffi.new("double[]", [b[nsites] for b in bonds])
+        return sites, couplings
+
+    def h_bonds_ss_z_consv_s(Nx, Ny, nup, bonds):
+        """construct the ΣJ_ij * sz_i * sz_j matrix over explicitly given
+        bonds with only total Sz conserved. See h_ss_z_consv_s
+
+        Parameters
+        --------------------
+        Nx: int
+            lattice length in the x-direction
+        Ny: int
+            lattice length in the y-direction
+        nup: int
+            the total number of sites with a spin-up
+        bonds: list of tuples
+            the bonds (i, j, J_ij) where i and j are site indices y * Nx + x
+
+        Returns
+        --------------------
+        H: scipy.sparse.csr_matrix
+        """
+        sites, couplings = _bonds(bonds, 2)
+        mat = _call(_lib.s_bonds_ss_z, Nx, Ny, nup, sites, couplings,
+                    len(bonds))
+        with CsrMatrix(mat) as csrmat:
+            H = csrmat.to_csr()
+        return H
+
+    def h_bonds_ss_xy_consv_s(Nx, Ny, nup, bonds):
+        """construct the ΣJ_ij * (sx_i * sx_j + sy_i * sy_j) matrix over
+        explicitly given bonds with only total Sz conserved. See
+        h_ss_z_consv_s
+
+        Parameters
+        --------------------
+        Nx: int
+            lattice length in the x-direction
+        Ny: int
+            lattice length in the y-direction
+        nup: int
+            the total number of sites with a spin-up
+        bonds: list of tuples
+            the bonds (i, j, J_ij) where i and j are site indices y * Nx + x
+
+        Returns
+        --------------------
+        H: scipy.sparse.csr_matrix
+        """
+        sites, couplings = _bonds(bonds, 2)
+        mat = _call(_lib.s_bonds_ss_xy, Nx, Ny, nup, sites, couplings,
+                    len(bonds))
+        with CsrMatrix(mat) as csrmat:
+            H = csrmat.to_csr()
+        return H
+
+    def h_triangles_sss_chi_consv_s(Nx, Ny, nup, triangles):
+        """construct the Σχ_ijk * S_i · (S_j × S_k) matrix over explicitly
+        given triangles with only total Sz conserved. See h_ss_z_consv_s
+
+        Parameters
+        --------------------
+        Nx: int
+            lattice length in the x-direction
+        Ny: int
+            lattice length in the y-direction
+        nup: int
+            the total number of sites with a spin-up
+        triangles: list of tuples
+            the triangles (i, j, k, χ_ijk) where i, j and k are site indices
+            y * Nx + x
+
+        Returns
+        --------------------
+        H: scipy.sparse.csr_matrix
+        """
+        sites, couplings = _bonds(triangles, 3)
+        mat = _call(_lib.s_triangles_sss_chi, Nx, Ny, nup, sites, couplings,
+                    len(triangles))
+        with CsrMatrix(mat) as csrmat:
+            H = csrmat.to_csr()
+        return H
+
+    def h_s_z_consv_s(Nx, Ny, nup, fields):
+        """construct the Σh_r * sz_r matrix with one h per site and only total
+        Sz conserved. See h_ss_z_consv_s
+
+        Parameters
+        --------------------
+        Nx: int
+            lattice length in the x-direction
+        Ny: int
+            lattice length in the y-direction
+        nup: int
+            the total number of sites with a spin-up
+        fields: list of floats
+            the field h along z on each site y * Nx + x
+
+        Returns
+        --------------------
+        H: scipy.sparse.csr_matrix
+        """
+        hs = ffi.new("double[]", list(fields))
+        mat = _call(_lib.s_h_s_z, Nx, Ny, nup, hs, len(fields))
+        with CsrMatrix(mat) as csrmat:
+            H = csrmat.to_csr()
+        return H
+
+    def irreducible_momenta(Nx, Ny):
+        """Returns one momentum out of each pair k, -k along with the number
+        of momenta it stands for. Sectors at k and -k share the same spectrum
+        whenever the Hamiltonian commutes with inversion, which all terms but
+        the scalar chirality do
+
+        Parameters
+        --------------------
+        Nx: int
+        Ny: int
+
+        Returns
+        --------------------
+        list of (kx, ky, multiplicity)
+        """
+        kx = np.empty(Nx * Ny, np.uint32)
+        ky = np.empty(Nx * Ny, np.uint32)
+        mult = np.empty(Nx * Ny, np.uint32)
+        n = _call(_lib.irreducible_momenta, Nx, Ny,
+                  ffi.cast("uint32_t *", kx.ctypes.data),
+                  ffi.cast("uint32_t *", ky.ctypes.data),
+                  ffi.cast("uint32_t *", mult.ctypes.data), Nx * Ny,
+                  ctype="size_t")
+        return [(int(kx[i]), int(ky[i]), int(mult[i])) for i in range(n)]
+
+    def min_necessary_ks(Nx, Ny):
+        """Returns the momentum that we absolutely need to compute
+
+        Parameters
+        --------------------
+        Nx: int
+        Ny: int
+
+        Returns
+        --------------------
+        list of ints
+        """
+        return [(kx, ky) for kx, ky, _ in irreducible_momenta(Nx, Ny)]
+
+
+# The native module built from rust/triangular_lattice_py takes the place of
+# the cffi wrappers above when it is installed. Everything it returns is freed
+# by the garbage collector, so its context managers are no-ops.
+try:
+    from triangular_lattice_py import (Sector, Orbits, set_num_threads,
+                                       irreducible_momenta)
+except ImportError:
+    pass
+
+def save_sector_matrix(path, H, Nx, Ny, kx, ky, nup=None):
+    """Saves a matrix built in a momentum sector in .npz format. The layout
+    is that of scipy.sparse.save_npz with the quantum numbers of the sector
+    stored alongside, and is the same as what the Rust side writes
+
+    Parameters
+    --------------------
+    path: str
+    H: scipy.sparse matrix
+    Nx: int
+    Ny: int
+    kx: int
+    ky: int
+    nup: int or None
+        None if total Sz is not conserved
+    """
+    H = H.tocsr()
+    np.savez(path, format=b"csr", shape=np.array(H.shape, dtype=np.int64),
+             data=H.data, indices=H.indices, indptr=H.indptr,
+             nx=np.int64(Nx), ny=np.int64(Ny), kx=np.int64(kx),
+             ky=np.int64(ky), nup=np.int64(-1 if nup is None else nup))
+
+
+def load_sector_matrix(path):
+    """Loads a matrix saved by save_sector_matrix or by the Rust side
+
+    Returns
+    --------------------
+    H: scipy.sparse.csr_matrix
+    meta: dict
+        the quantum numbers 'Nx', 'Ny', 'kx', 'ky' and 'nup' of the sector.
+        'nup' is None if total Sz is not conserved
+    """
+    with np.load(path) as f:
+        H = sparse.csr_matrix((f["data"], f["indices"], f["indptr"]),
+                              shape=tuple(f["shape"]))
+        nup = int(f["nup"])
+        meta = {"Nx": int(f["nx"]), "Ny": int(f["ny"]), "kx": int(f["kx"]),
+                "ky": int(f["ky"]), "nup": None if nup < 0 else nup}
+    return H, meta
@@ -0,0 +1,81 @@
+// Compares the shift and mask translations in `common` against the arithmetic
+// implementation they replaced. Run with `cargo bench`.
+extern crate triangular_lattice_ext;
+
+use std::time::{Duration, Instant};
+use triangular_lattice_ext::common::*;
+
+// the original digit-by-digit implementation of translate_x
+fn arith_translate_x(dec: BinaryBasis, nx: Dim, ny: Dim) -> BinaryBasis {
+    let n = (0..ny.raw_int()).map(|x| x * nx.raw_int())
+                             .collect::<Vec<u32>>();
+    let s = n.iter()
+             .map(|&x| dec % POW2[(x + nx.raw_int()) as usize] / POW2[x as usize])
+             .map(|x| {
+                      (x * BinaryBasis(2)) % POW2[nx.raw_int() as usize]
+                      + x / POW2[nx.raw_int() as usize - 1]
+                  });
+
+    n.iter().map(|&x| POW2[x as usize])
+     .zip(s)
+     .map(|(a, b)| a * b)
+     .fold(BinaryBasis(0), |acc, x| x + acc)
+}
+
+// the original implementation of translate_y
+fn arith_translate_y(dec: BinaryBasis, nx: Dim, ny: Dim) -> BinaryBasis {
+    let xdim = POW2[nx.raw_int() as usize];
+    let pred_totdim = POW2[nx.raw_int() as usize * (ny.raw_int() - 1) as usize];
+    let tail = dec % xdim;
+    dec / xdim + tail * pred_totdim
+}
+
+// sweeps every translation of each configuration, the way orbits are built
+fn time<F>(name: &str, decs: &[BinaryBasis], nx: Dim, ny: Dim, f: F) -> Duration
+    where F: Fn(BinaryBasis) -> BinaryBasis
+{
+    let start = Instant::now();
+    let mut checksum = 0;
+    for &dec in decs.iter() {
+        let mut new_dec = dec;
+        for _ in 0..nx.raw_int() * ny.raw_int() {
+            new_dec = f(new_dec);
+            checksum = new_dec.raw_int().wrapping_add(checksum);
+        }
+    }
+    let elapsed = start.elapsed();
+    println!("{:<24} {:>10.3} ms  (checksum {})",
+             name,
+             elapsed.as_secs() as f64 * 1e3 + elapsed.subsec_nanos() as f64 * 1e-6,
+             checksum);
+    elapsed
+}
+
+fn main() {
+    for &(nx, ny) in [(Dim(4), Dim(4)), (Dim(6), Dim(4)), (Dim(6), Dim(6))].iter() {
+        println!("{} x {} cluster", nx.raw_int(), ny.raw_int());
+        // a fixed pseudo-random sample of configurations
+        let mask = (1_u64 << (nx * ny).raw_int()) - 1;
+        let decs = (0..100_000_u64).map(|i| i.wrapping_mul(0x9e37_79b9_7f4a_7c15))
+                                   .map(|i| BinaryBasis(i & mask))
+                                   .collect::<Vec<_>>();
+        let trans = Translations::new(nx, ny);
+        let decs = &decs;
+
+        time("arithmetic translate_x",
+             decs,
+             nx,
+             ny,
+             |d| arith_translate_x(d, nx, ny));
+        time("translate_x", decs, nx, ny, |d| translate_x(d, nx, ny));
+        time("Translations::translate_x", decs, nx, ny, |d| trans.translate_x(d));
+        time("arithmetic translate_y",
+             decs,
+             nx,
+             ny,
+             |d| arith_translate_y(d, nx, ny));
+        time("translate_y", decs, nx, ny, |d| translate_y(d, nx, ny));
+        time("Translations::translate_y", decs, nx, ny, |d| trans.translate_y(d));
+        println!();
+    }
+}
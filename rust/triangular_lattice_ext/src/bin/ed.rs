@@ -0,0 +1,324 @@
+//! Exact diagonalization of triangular lattice models from the command line.
+//! Builds the Hamiltonian in each requested momentum sector, finds its lowest
+//! eigenvalues and writes them as a table with one row per eigenvalue. The run
+//! is described either by options or by a model specification file, see
+//! `triangular_lattice_ext::spec`, which may also sweep couplings over a grid.
+extern crate num_complex;
+extern crate triangular_lattice_ext;
+
+use num_complex::Complex;
+use std::{
+    env,
+    fs::File,
+    io::{self, Write},
+    process
+};
+
+use triangular_lattice_ext::{
+    checkpoint::{self, Checkpoint, Outcome, Task},
+    common::{self, Dim, InputError, K},
+    model::{Components, Model, Term},
+    spec::{Point, Spec}
+};
+
+const USAGE: &str = "\
+Usage: triangular_lattice_ed --nx NX --ny NY --term TERM=J [--term TERM=J ...]
+                             (--sector KX,KY[,NUP] ... | --all) [options]
+       triangular_lattice_ed --spec FILE [options]
+
+Terms are ss_xy:L, ss_z:L, ss_ppmm:L and ss_pmz:L with the bond range L = 1, 2
+or 3, sss_chi and ring. NUP, the number of spins pointing up, is given if and
+only if every term conserves total Sz, and observables must then conserve it
+too. --all picks one sector out of each set related by inversion or a global
+spin flip. Options given along with --spec take precedence over the file. Each
+sector is built once for all the points of a sweep. With --checkpoint, every
+finished sector and point is recorded as it is done, and a run started again
+with the same checkpoint and model skips it.
+
+Options:
+    --nev N          eigenvalues per sector [default: 1]
+    --tol TOL        convergence tolerance of the eigenvalues [default: 1e-10]
+    --max-iter N     maximum number of Lanczos steps per sector [default: 500]
+    --threads N      worker threads, 0 for one per core [default: 0]
+    --observable T   a term to measure in every eigenstate, may be repeated
+    --warm-start     start each point of a sweep from the previous ground state
+    --checkpoint F   where finished work is recorded and resumed from
+    -o FILE          where to write the table [default: standard output]";
+
+struct Args {
+    spec:       Spec,
+    checkpoint: Option<String>,
+    output:     Option<String>
+}
+
+fn parse<T: ::std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value.parse()
+         .map_err(|_| format!("invalid value \"{}\" for {}", value, flag))
+}
+
+fn parse_term(value: &str) -> Result<(Term, f64), String> {
+    let mut parts = value.splitn(2, '=');
+    let term = parts.next().unwrap_or("");
+    let j = parts.next()
+                 .ok_or_else(|| format!("expected TERM=J but got \"{}\"", value))?;
+    let term = term.parse::<Term>().map_err(|e| e.to_string())?;
+    Ok((term, parse("--term", j)?))
+}
+
+fn parse_sector(value: &str) -> Result<(K, K, Option<u32>), String> {
+    let fields = value.split(',')
+                      .map(|x| parse::<u32>("--sector", x))
+                      .collect::<Result<Vec<_>, _>>()?;
+    match fields[..] {
+        [kx, ky] => Ok((K(kx), K(ky), None)),
+        [kx, ky, nup] => Ok((K(kx), K(ky), Some(nup))),
+        _ => Err(format!("expected KX,KY[,NUP] but got \"{}\"", value))
+    }
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Args, String> {
+    let mut spec_file = None;
+    let (mut nx, mut ny) = (None, None);
+    let mut terms = Vec::new();
+    let mut sectors = Vec::new();
+    let (mut all, mut warm_start) = (false, false);
+    let (mut nev, mut tol, mut max_iter, mut threads) = (None, None, None, None);
+    let mut observables = Vec::new();
+    let (mut checkpoint, mut output) = (None, None);
+    while let Some(flag) = args.next() {
+        match &*flag {
+            "--all" => {
+                all = true;
+                continue;
+            }
+            "--warm-start" => {
+                warm_start = true;
+                continue;
+            }
+            _ => ()
+        }
+        let value = args.next()
+                        .ok_or_else(|| format!("{} expects a value", flag))?;
+        match &*flag {
+            "--spec" => spec_file = Some(value),
+            "--nx" => nx = Some(parse(&flag, &value)?),
+            "--ny" => ny = Some(parse(&flag, &value)?),
+            "--term" => terms.push(parse_term(&value)?),
+            "--sector" => sectors.push(parse_sector(&value)?),
+            "--nev" => nev = Some(parse(&flag, &value)?),
+            "--tol" => tol = Some(parse(&flag, &value)?),
+            "--max-iter" => max_iter = Some(parse(&flag, &value)?),
+            "--threads" => threads = Some(parse(&flag, &value)?),
+            "--observable" => {
+                observables.push(value.parse::<Term>().map_err(|e| e.to_string())?)
+            }
+            "--checkpoint" => checkpoint = Some(value),
+            "-o" => output = Some(value),
+            _ => return Err(format!("unknown option {}", flag))
+        }
+    }
+
+    let mut spec = match spec_file {
+        Some(path) => {
+            let model_given = nx.is_some() || ny.is_some() || !terms.is_empty();
+            if model_given || !sectors.is_empty() || all {
+                let msg = "--spec replaces --nx, --ny, --term, --sector and --all";
+                return Err(msg.to_string());
+            }
+            Spec::load(&path).map_err(|e| format!("{}: {}", path, e))?
+        }
+        None => {
+            let (nx, ny) = match (nx, ny) {
+                (Some(nx), Some(ny)) => (Dim(nx), Dim(ny)),
+                _ => return Err("--nx and --ny are required".to_string())
+            };
+            if terms.is_empty() {
+                return Err("at least one --term is required".to_string());
+            }
+            let model = Model { nx, ny, terms };
+            model.check().map_err(|e| e.to_string())?;
+            let sectors = match (all, sectors.is_empty()) {
+                (true, true) => None,
+                (false, false) => {
+                    for &(kx, ky, nup) in sectors.iter() {
+                        model.check_sector(kx, ky, nup)
+                             .map_err(|e| e.to_string())?;
+                    }
+                    Some(sectors)
+                }
+                _ => return Err("give either --sector or --all".to_string())
+            };
+            Spec::from_model(model, sectors)
+        }
+    };
+    let solver = &mut spec.solver;
+    solver.nev = nev.unwrap_or(solver.nev);
+    solver.tol = tol.unwrap_or(solver.tol);
+    solver.max_iter = max_iter.unwrap_or(solver.max_iter);
+    solver.threads = threads.unwrap_or(solver.threads);
+    solver.warm_start |= warm_start;
+    let model = spec.sector_model();
+    for &t in observables.iter() {
+        model.check_observable(t).map_err(|e| e.to_string())?;
+    }
+    spec.observables.extend(observables);
+    Ok(Args { spec, checkpoint, output })
+}
+
+/// Finds the lowest levels at one point of the sweep and measures the
+/// observables in them. With a warm start the iteration starts from
+/// ground_state, which is then replaced by the new ground state.
+fn solve(spec: &Spec, components: &Components, point: &Point,
+         ground_state: &mut Option<Vec<Complex<f64>>>)
+         -> Outcome {
+    let solver = &spec.solver;
+    let h = components.hamiltonian(&point.model.terms);
+    // eigenvectors are only worth their memory when something is measured or
+    // they seed the next point
+    if spec.observables.is_empty() && !solver.warm_start {
+        let energies = h.lowest_eigenvalues(solver.nev, solver.tol, solver.max_iter);
+        return Outcome { dim: h.dim(), energies, observed: Vec::new() };
+    }
+    let start = ground_state.as_ref().map(|v| &v[..]);
+    let (energies, mut vectors) =
+        h.lowest_eigenpairs(start, solver.nev, solver.tol, solver.max_iter);
+    let observed = spec.observables
+                       .iter()
+                       .map(|&t| components.expectation_values(t, &vectors))
+                       .collect();
+    if solver.warm_start && !vectors.is_empty() {
+        *ground_state = Some(vectors.swap_remove(0));
+    }
+    Outcome { dim: h.dim(), energies, observed }
+}
+
+/// One row per level, led by the values of the swept couplings
+fn write_rows(out: &mut dyn Write, values: &[f64], task: &Task, outcome: &Outcome)
+              -> io::Result<()> {
+    // nup is written as -1 when total Sz is not conserved, as in saved sectors
+    let nup = task.nup.map(|n| n as i64).unwrap_or(-1);
+    for (level, e) in outcome.energies.iter().enumerate() {
+        for value in values.iter() {
+            write!(out, "{}\t", value)?;
+        }
+        write!(out,
+               "{}\t{}\t{}\t{}\t{}\t{:.12}",
+               task.kx.raw_int(),
+               task.ky.raw_int(),
+               nup,
+               outcome.dim,
+               level,
+               e)?;
+        for values in outcome.observed.iter() {
+            write!(out, "\t{:.12}", values[level])?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+fn invalid_input(e: InputError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, e.to_string())
+}
+
+fn run(args: Args) -> io::Result<()> {
+    let spec = &args.spec;
+    let solver = &spec.solver;
+    common::set_num_threads(solver.threads);
+    let sector_model = spec.sector_model();
+    let sectors = match spec.sectors {
+        Some(ref sectors) => sectors.clone(),
+        None => sector_model.irreducible_sectors()
+    };
+    // sectors listed up front are checked when the spec is read, but those
+    // found here may still be too large to build
+    for &(kx, ky, nup) in sectors.iter() {
+        sector_model.check_sector(kx, ky, nup).map_err(invalid_input)?;
+    }
+    let mut terms = sector_model.nonzero_terms();
+    terms.extend(spec.observables.iter().cloned());
+    let mut checkpoint = match args.checkpoint {
+        Some(ref path) => {
+            Some(Checkpoint::open(path, checkpoint::fingerprint(spec))?)
+        }
+        None => None
+    };
+    if let Some(ref checkpoint) = checkpoint {
+        eprintln!("resuming with {} tasks done", checkpoint.len());
+    }
+    let mut out: Box<dyn Write> = match args.output {
+        Some(ref path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout())
+    };
+
+    let model = &spec.model;
+    writeln!(out, "# nx = {}, ny = {}", model.nx.raw_int(), model.ny.raw_int())?;
+    for &(term, j) in model.terms.iter() {
+        writeln!(out, "# {} = {}", term, j)?;
+    }
+    for axis in spec.sweep.iter() {
+        writeln!(out, "# {} is swept over {} values", axis.name, axis.values.len())?;
+    }
+    for axis in spec.sweep.iter() {
+        write!(out, "{}\t", axis.name)?;
+    }
+    write!(out, "kx\tky\tnup\tdim\tlevel\tenergy")?;
+    for term in spec.observables.iter() {
+        write!(out, "\t<{}>", term)?;
+    }
+    writeln!(out)?;
+    for &(kx, ky, nup) in sectors.iter() {
+        // the sector is only built if some point is left to do
+        let mut components = None;
+        // a resumed sweep starts cold from the first point it has not done
+        let mut ground_state = None;
+        let mut dim = 0;
+        for (p, point) in spec.points.iter().enumerate() {
+            let task = Task { point: p, kx, ky, nup };
+            let done = checkpoint.as_ref().and_then(|c| c.get(&task)).cloned();
+            let outcome = match done {
+                Some(outcome) => outcome,
+                None => {
+                    let components = match components {
+                        Some(ref components) => components,
+                        None => {
+                            let sector = sector_model.sector(kx, ky, nup);
+                            let built = Components::new(&terms, &sector);
+                            components.insert(built.map_err(invalid_input)?)
+                        }
+                    };
+                    let outcome = solve(spec, components, point, &mut ground_state);
+                    if let Some(ref mut checkpoint) = checkpoint {
+                        checkpoint.record(task, outcome.clone())?;
+                    }
+                    outcome
+                }
+            };
+            write_rows(&mut out, &point.values, &task, &outcome)?;
+            dim = outcome.dim;
+        }
+        // rows are flushed sector by sector so an interrupted run keeps what
+        // it has done
+        out.flush()?;
+        eprintln!("kx = {}, ky = {}, nup = {}: dim = {}",
+                  kx.raw_int(),
+                  ky.raw_int(),
+                  nup.map(|n| n as i64).unwrap_or(-1),
+                  dim);
+    }
+    Ok(())
+}
+
+fn main() {
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(msg) => {
+            eprintln!("error: {}\n\n{}", msg, USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(e) = run(args) {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
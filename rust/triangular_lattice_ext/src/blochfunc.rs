@@ -4,39 +4,42 @@ use std::cmp::Ordering;
 
 use common::{BinaryBasis, Dim};
 
+/// A Bloch function built from the translations of the configuration "lead".
+/// The coefficients are complex in general but could be real for momenta where
+/// all phases are ±1.
 #[derive(Clone, Debug)]
-pub struct BlochFunc {
+pub struct BlochFunc<P = Complex<f64>> {
     pub lead: BinaryBasis,
-    pub decs: FnvHashMap<BinaryBasis, Complex<f64>>,
+    pub decs: FnvHashMap<BinaryBasis, P>,
     pub norm: f64
 }
 
-impl Ord for BlochFunc {
-    fn cmp(&self, other: &BlochFunc) -> Ordering { self.lead.cmp(&other.lead) }
+impl<P> Ord for BlochFunc<P> {
+    fn cmp(&self, other: &BlochFunc<P>) -> Ordering { self.lead.cmp(&other.lead) }
 }
 
-impl PartialOrd for BlochFunc {
-    fn partial_cmp(&self, other: &BlochFunc) -> Option<Ordering> {
+impl<P> PartialOrd for BlochFunc<P> {
+    fn partial_cmp(&self, other: &BlochFunc<P>) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
 
-impl PartialEq for BlochFunc {
-    fn eq(&self, other: &BlochFunc) -> bool { self.lead == other.lead }
+impl<P> PartialEq for BlochFunc<P> {
+    fn eq(&self, other: &BlochFunc<P>) -> bool { self.lead == other.lead }
 }
 
-impl Eq for BlochFunc {}
+impl<P> Eq for BlochFunc<P> {}
 
 #[derive(Clone, Debug)]
-pub struct BlochFuncSet {
-    pub data:    Vec<BlochFunc>,
+pub struct BlochFuncSet<P = Complex<f64>> {
+    pub data:    Vec<BlochFunc<P>>,
     pub nonzero: u32,
     pub nx:      Dim,
     pub ny:      Dim
 }
 
-impl<'a> BlochFuncSet {
-    pub fn create(nx: Dim, ny: Dim, bfuncs: Vec<BlochFunc>) -> BlochFuncSet {
+impl<P> BlochFuncSet<P> {
+    pub fn create(nx: Dim, ny: Dim, bfuncs: Vec<BlochFunc<P>>) -> BlochFuncSet<P> {
         let data = bfuncs;
         let nonzero = data.len() as u32;
         BlochFuncSet { data,
@@ -47,12 +50,12 @@ impl<'a> BlochFuncSet {
 
     pub fn sort(&mut self) { self.data.sort(); }
 
-    pub fn iter(&self) -> BlochFuncSetIterator {
+    pub fn iter(&self) -> BlochFuncSetIterator<'_, P> {
         BlochFuncSetIterator::new(&self.data)
     }
 
-    pub fn build_dict(bfuncs: &BlochFuncSet)
-                      -> FnvHashMap<&BinaryBasis, &BlochFunc> {
+    pub fn build_dict(bfuncs: &BlochFuncSet<P>)
+                      -> FnvHashMap<&BinaryBasis, &BlochFunc<P>> {
         let mut hashtable = FnvHashMap::default();
         for bfunc in bfuncs.data.iter() {
             for dec in bfunc.decs.keys() {
@@ -63,22 +66,22 @@ impl<'a> BlochFuncSet {
     }
 }
 
-pub struct BlochFuncSetIterator<'a> {
+pub struct BlochFuncSetIterator<'a, P: 'a = Complex<f64>> {
     pub ptr:  usize,
     pub len:  usize,
-    pub data: &'a Vec<BlochFunc>
+    pub data: &'a Vec<BlochFunc<P>>
 }
 
-impl<'a> BlochFuncSetIterator<'a> {
-    pub fn new(data: &'a Vec<BlochFunc>) -> BlochFuncSetIterator<'a> {
+impl<'a, P> BlochFuncSetIterator<'a, P> {
+    pub fn new(data: &'a Vec<BlochFunc<P>>) -> BlochFuncSetIterator<'a, P> {
         let ptr = 0;
         let len = data.len();
         BlochFuncSetIterator { ptr, len, data }
     }
 }
 
-impl<'a> Iterator for BlochFuncSetIterator<'a> {
-    type Item = &'a BlochFunc;
+impl<'a, P> Iterator for BlochFuncSetIterator<'a, P> {
+    type Item = &'a BlochFunc<P>;
 
     fn next(&mut self) -> Option<Self::Item> {
         self.ptr += 1;
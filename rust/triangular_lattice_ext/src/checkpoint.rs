@@ -0,0 +1,223 @@
+/// Checkpoints of long scans over sectors and sweep points. Every finished task
+/// is appended to a text file as soon as it is done, so a scan that is cut
+/// short could pick up where it stopped. The file starts with a fingerprint of
+/// the spec it was written for, and one that does not match is refused rather
+/// than mixed with results of another model.
+///
+/// Each task takes one tab separated line: the point index, kx, ky, nup (-1
+/// when total Sz is not conserved), the dimension, the number of levels and of
+/// observables, then the energies and the expectation values observable by
+/// observable. Numbers are written so that they read back exactly.
+use fnv::{FnvHashMap, FnvHasher};
+use std::{
+    fs::{File, OpenOptions},
+    hash::Hasher,
+    io::{self, BufRead, BufReader, Write},
+    path::Path
+};
+
+use common::K;
+use spec::Spec;
+
+const MAGIC: &str = "spinsys checkpoint 1";
+
+/// One sector at one point of a sweep
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Task {
+    /// Index into `Spec::points`
+    pub point: usize,
+    pub kx:    K,
+    pub ky:    K,
+    pub nup:   Option<u32>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Outcome {
+    pub dim:      usize,
+    /// The lowest eigenvalues in ascending order
+    pub energies: Vec<f64>,
+    /// For each observable, its expectation value in each eigenstate
+    pub observed: Vec<Vec<f64>>
+}
+
+/// Identifies the work a spec describes. Everything that changes the tasks or
+/// their outcomes goes in, but not the number of threads.
+pub fn fingerprint(spec: &Spec) -> u64 {
+    let mut hasher = FnvHasher::default();
+    let solver = &spec.solver;
+    let description = format!("{:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?}",
+                              spec.points,
+                              spec.sectors,
+                              spec.boundary,
+                              spec.observables,
+                              solver.nev,
+                              solver.tol,
+                              solver.max_iter,
+                              solver.warm_start);
+    hasher.write(description.as_bytes());
+    hasher.finish()
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn format_line(task: &Task, outcome: &Outcome) -> String {
+    let nup = task.nup.map(|n| n as i64).unwrap_or(-1);
+    let mut fields = vec![task.point.to_string(),
+                          task.kx.raw_int().to_string(),
+                          task.ky.raw_int().to_string(),
+                          nup.to_string(),
+                          outcome.dim.to_string(),
+                          outcome.energies.len().to_string(),
+                          outcome.observed.len().to_string()];
+    fields.extend(outcome.energies.iter().map(|e| format!("{:?}", e)));
+    for values in outcome.observed.iter() {
+        fields.extend(values.iter().map(|v| format!("{:?}", v)));
+    }
+    fields.join("\t")
+}
+
+fn parse_line(line: &str) -> Option<(Task, Outcome)> {
+    let fields = line.split('\t').collect::<Vec<_>>();
+    if fields.len() < 7 {
+        return None;
+    }
+    let nup = fields[3].parse::<i64>().ok()?;
+    let task = Task { point: fields[0].parse().ok()?,
+                      kx:    K(fields[1].parse().ok()?),
+                      ky:    K(fields[2].parse().ok()?),
+                      nup:   if nup < 0 { None } else { Some(nup as u32) } };
+    let dim = fields[4].parse().ok()?;
+    let nlevels = fields[5].parse::<usize>().ok()?;
+    let nobs = fields[6].parse::<usize>().ok()?;
+    let numbers = fields[7..].iter()
+                             .map(|x| x.parse::<f64>())
+                             .collect::<Result<Vec<_>, _>>()
+                             .ok()?;
+    if numbers.len() != nlevels * (1 + nobs) {
+        return None;
+    }
+    let energies = numbers[..nlevels].to_vec();
+    let observed = numbers[nlevels..].chunks(nlevels.max(1))
+                                     .take(nobs)
+                                     .map(|c| c.to_vec())
+                                     .collect();
+    Some((task, Outcome { dim, energies, observed }))
+}
+
+pub struct Checkpoint {
+    file: File,
+    done: FnvHashMap<Task, Outcome>
+}
+
+impl Checkpoint {
+    /// Opens the checkpoint at path for the spec with the given fingerprint,
+    /// creating it if it does not exist. A line left unfinished when the scan
+    /// was interrupted is dropped.
+    pub fn open<P: AsRef<Path>>(path: P, fingerprint: u64)
+                                -> io::Result<Checkpoint> {
+        let path = path.as_ref();
+        let header = format!("{}\nspec {:016x}\n", MAGIC, fingerprint);
+        let mut done = FnvHashMap::default();
+        let mut file = OpenOptions::new().read(true)
+                                         .write(true)
+                                         .create(true)
+                                         .truncate(false)
+                                         .open(path)?;
+        let mut lines = Vec::new();
+        for line in BufReader::new(&file).split(b'\n') {
+            lines.push(line?);
+        }
+        if lines.is_empty() {
+            file.write_all(header.as_bytes())?;
+            file.sync_data()?;
+            return Ok(Checkpoint { file, done });
+        }
+
+        if lines.len() < 2 || lines[0] != MAGIC.as_bytes() {
+            return Err(invalid(format!("{} is not a checkpoint", path.display())));
+        }
+        if lines[1] != format!("spec {:016x}", fingerprint).as_bytes() {
+            return Err(invalid(format!("{} was written for a different model \
+                                        specification",
+                                       path.display())));
+        }
+        // the byte length of the lines that are kept, newlines included
+        let mut kept = lines[0].len() + lines[1].len() + 2;
+        let complete = file.metadata()?.len() as usize;
+        for (i, line) in lines.iter().enumerate().skip(2) {
+            let end = kept + line.len() + 1;
+            match parse_line(&String::from_utf8_lossy(line)) {
+                Some((task, outcome)) if end <= complete => {
+                    done.insert(task, outcome);
+                    kept = end;
+                }
+                // only the last line could have been cut short
+                _ if i + 1 == lines.len() => break,
+                _ => {
+                    return Err(invalid(format!("{} is corrupt at line {}",
+                                               path.display(),
+                                               i + 1)))
+                }
+            }
+        }
+        file.set_len(kept as u64)?;
+        let file = OpenOptions::new().append(true).open(path)?;
+        Ok(Checkpoint { file, done })
+    }
+
+    pub fn get(&self, task: &Task) -> Option<&Outcome> { self.done.get(task) }
+
+    /// The number of finished tasks
+    pub fn len(&self) -> usize { self.done.len() }
+
+    pub fn is_empty(&self) -> bool { self.done.is_empty() }
+
+    /// Records a finished task, making sure it reaches the disk before
+    /// returning
+    pub fn record(&mut self, task: Task, outcome: Outcome) -> io::Result<()> {
+        writeln!(self.file, "{}", format_line(&task, &outcome))?;
+        self.file.sync_data()?;
+        self.done.insert(task, outcome);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    #[test]
+    fn checkpoint_test() {
+        let path = env::temp_dir().join(format!("spinsys_checkpoint_{}",
+                                                ::std::process::id()));
+        let _ = fs::remove_file(&path);
+        let task = |point| Task { point, kx: K(1), ky: K(0), nup: Some(3) };
+        let outcome = Outcome { dim:      12,
+                                energies: vec![-1.25, 0.1 + 0.2],
+                                observed: vec![vec![0.5, -0.5], vec![1e-300, 2.]] };
+        {
+            let mut cp = Checkpoint::open(&path, 42).unwrap();
+            assert!(cp.is_empty());
+            cp.record(task(0), outcome.clone()).unwrap();
+            cp.record(task(1), outcome.clone()).unwrap();
+        }
+        // a line cut short by an interruption is dropped
+        let mut f = OpenOptions::new().append(true).open(&path).unwrap();
+        f.write_all(b"2\t1\t0\t3\t12\t2\t0\t-1.2").unwrap();
+        drop(f);
+
+        let mut cp = Checkpoint::open(&path, 42).unwrap();
+        assert_eq!(cp.len(), 2);
+        assert_eq!(cp.get(&task(1)), Some(&outcome));
+        assert_eq!(cp.get(&task(2)), None);
+        cp.record(task(2), outcome.clone()).unwrap();
+        drop(cp);
+        assert_eq!(Checkpoint::open(&path, 42).unwrap().len(), 3);
+
+        assert!(Checkpoint::open(&path, 43).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,13 +1,17 @@
 use fnv::FnvHashMap;
-use libc::size_t;
 use num_bigint::*;
 use num_complex::Complex;
+use rayon::{ThreadPool, ThreadPoolBuilder};
 use std::{
     cmp::Ordering,
     collections::VecDeque,
-    fmt::Debug,
+    error::Error,
+    fmt::{self, Debug},
     iter::FromIterator,
-    mem,
+    sync::{
+        atomic::{self, AtomicUsize},
+        Arc, Mutex
+    },
     ops::{
         Add, AddAssign, BitAnd, BitAndAssign, BitOr, BitOrAssign, Div, DivAssign,
         Mul, MulAssign, Neg, Rem, RemAssign, Sub, SubAssign
@@ -17,6 +21,14 @@ use std::{
 use blochfunc::{BlochFunc, BlochFuncSet};
 use sitevector::SiteVector;
 
+// the number of worker threads used to build bases and matrices. 0 leaves the
+// choice to rayon, which defaults to one thread per logical core
+static NUM_THREADS: AtomicUsize = AtomicUsize::new(0);
+
+// the pool last built by `with_thread_pool` and the thread count it was built
+// for, kept so threads are only spawned again when the count changes
+static POOL: Mutex<Option<(usize, Arc<ThreadPool>)>> = Mutex::new(None);
+
 pub const PI: f64 = 3.1415926535897932384626433832795028841971;
 pub const POW2: [BinaryBasis; 63] = [BinaryBasis(1),
                                      BinaryBasis(2),
@@ -127,6 +139,7 @@ impl Div<Dim> for I {
 
 // c compatible complex type for export to numpy at the end
 #[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct CComplex<T> {
     pub re: T,
     pub im: T
@@ -140,49 +153,336 @@ impl<T> CComplex<T> {
     }
 }
 
-#[repr(C)]
-pub struct Vector<T> {
-    pub ptr: *mut T,
-    pub len: size_t
+/// Scalars that could serve as coefficients of Bloch functions. Complex<f64>
+/// works for all momenta while f64 only works when all phases are ±1
+pub trait Phase: Copy + Send + Sync + Add<Output = Self> + Mul<Output = Self>
+    where Self: Mul<f64, Output = Self>
+{
+    fn from_angle(ang: f64) -> Self;
+    fn from_real(x: f64) -> Self;
+    fn conj(&self) -> Self;
+    fn norm(&self) -> f64;
 }
 
-impl<T> Vector<T> {
-    fn new(ptr: *mut T, len: size_t) -> Vector<T> { Vector { ptr, len } }
+impl Phase for Complex<f64> {
+    fn from_angle(ang: f64) -> Self { Complex::from_polar(&1., &ang) }
+
+    fn from_real(x: f64) -> Self { Complex::new(x, 0.) }
+
+    fn conj(&self) -> Self { Complex::conj(self) }
+
+    fn norm(&self) -> f64 { Complex::norm(self) }
 }
 
-#[repr(C)]
-pub struct CoordMatrix<T> {
-    pub data:  Vector<T>,
-    pub col:   Vector<u32>,
-    pub row:   Vector<u32>,
+impl Phase for f64 {
+    // only ever called with angles that are multiples of π
+    fn from_angle(ang: f64) -> Self { ang.cos().round() }
+
+    fn from_real(x: f64) -> Self { x }
+
+    fn conj(&self) -> Self { *self }
+
+    fn norm(&self) -> f64 { self.abs() }
+}
+
+/// Types that matrix elements computed with coefficients of type P are
+/// exported as. Elements are always computed in double precision, so the single
+/// precision type only rounds each of them once as it is stored. Only complex
+/// matrices come in single precision.
+pub trait Element<P>: Send {
+    fn from_phase(p: P) -> Self;
+}
+
+impl Element<Complex<f64>> for CComplex<f64> {
+    fn from_phase(p: Complex<f64>) -> Self { CComplex::from_num_complex(p) }
+}
+
+impl Element<Complex<f64>> for CComplex<f32> {
+    fn from_phase(p: Complex<f64>) -> Self {
+        CComplex { re: p.re as f32, im: p.im as f32 }
+    }
+}
+
+impl Element<f64> for f64 {
+    fn from_phase(p: f64) -> Self { p }
+}
+
+/// Whether all Bloch phases at the given momentum are ±1, i.e. whether 2k is a
+/// reciprocal lattice vector
+pub fn is_real_momentum(nx: Dim, ny: Dim, kx: K, ky: K) -> bool {
+    // k and -k only coincide at k = 0 and, on an even side, at k = n / 2
+    let real = |k: K, n: Dim| {
+        let (k, n) = (k.raw_int() % n.raw_int(), n.raw_int());
+        k == 0 || 2 * k == n
+    };
+    real(kx, nx) && real(ky, ny)
+}
+
+/// The momentum -k folded back into [0, nx) × [0, ny)
+pub fn inverse_momentum(nx: Dim, ny: Dim, kx: K, ky: K) -> (K, K) {
+    let (nx, ny) = (nx.raw_int(), ny.raw_int());
+    (K((nx - kx.raw_int()) % nx), K((ny - ky.raw_int()) % ny))
+}
+
+/// One momentum out of each pair k, -k together with the number of momenta it
+/// stands for, 1 when k is its own inverse and 2 otherwise. Inversion maps the
+/// sector at k onto the one at -k, so only these need to be diagonalized. Each
+/// pair is represented by whichever of k and -k comes first with kx running
+/// slowest.
+pub fn irreducible_momenta(nx: Dim, ny: Dim) -> Vec<(K, K, u32)> {
+    let mut momenta = Vec::new();
+    for kx in 0..nx.raw_int() {
+        for ky in 0..ny.raw_int() {
+            let (kx, ky) = (K(kx), K(ky));
+            let inverse = inverse_momentum(nx, ny, kx, ky);
+            if (kx, ky) == inverse {
+                momenta.push((kx, ky, 1));
+            } else if (kx, ky) < inverse {
+                momenta.push((kx, ky, 2));
+            }
+        }
+    }
+    momenta
+}
+
+/// The ways parameters handed to the basis and operator builders could be out
+/// of range. Builders assume valid input and may panic otherwise, so anything
+/// coming from outside of Rust should be checked against these first.
+#[derive(Clone, Debug, PartialEq)]
+pub enum InputError {
+    /// The lattice has no sites, or more than the basis could hold. Holds the
+    /// largest number of sites allowed
+    LatticeSize { nx: u32, ny: u32, max: u32 },
+    /// A momentum outside of the nx × ny grid of the Brillouin zone
+    Momentum { kx: u32, ky: u32 },
+    /// A real matrix was asked for at a momentum with complex Bloch phases
+    ComplexMomentum { kx: u32, ky: u32 },
+    /// More spins pointing up than there are sites
+    SpinUpCount { nup: u32, nsites: u32 },
+    /// The parity of the number of spins pointing up is neither 0 nor 1
+    Parity(u32),
+    /// Only first, second and third nearest neighbor bonds exist
+    BondRange(i32),
+    /// A site index given by the caller that does not exist on the lattice
+    Site { site: u32, nsites: u32 },
+    /// The number of fields does not match the number of sublattices
+    FieldCount { expected: usize, found: usize },
+    /// A buffer handed in to be filled is shorter than the array copied into it
+    BufferSize { array: &'static str, needed: usize, found: usize },
+    /// A Hamiltonian term that is not spelled like any of `model::Term`
+    UnknownTerm(String),
+    /// nup was given for a model that does not conserve total Sz or left out
+    /// for one that does. Holds whether the model conserves it
+    SzConservation(bool),
+    /// A triangle orientation that is neither 0 (up) nor 1 (down)
+    Orientation(u32),
+    /// More vectors of a sector than could be addressed in memory
+    VectorCount { count: usize, dim: usize }
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InputError::LatticeSize { nx, ny, max } => {
+                write!(f,
+                       "a {}x{} lattice must have between 1 and {} sites",
+                       nx, ny, max)
+            }
+            InputError::Momentum { kx, ky } => {
+                write!(f, "momentum ({}, {}) lies outside of the lattice", kx, ky)
+            }
+            InputError::ComplexMomentum { kx, ky } => {
+                write!(f, "Bloch functions at momentum ({}, {}) are complex", kx, ky)
+            }
+            InputError::SpinUpCount { nup, nsites } => {
+                write!(f, "{} spins cannot point up on {} sites", nup, nsites)
+            }
+            InputError::Parity(p) => {
+                write!(f, "the parity of nup must be 0 or 1 but {} was given", p)
+            }
+            InputError::BondRange(l) => {
+                write!(f, "l must be 1, 2 or 3 but {} was given", l)
+            }
+            InputError::Site { site, nsites } => {
+                write!(f,
+                       "there is no site {} on a lattice of {} sites",
+                       site, nsites)
+            }
+            InputError::FieldCount { expected, found } => {
+                write!(f, "expected {} fields but {} were given", expected, found)
+            }
+            InputError::BufferSize { array, needed, found } => {
+                write!(f,
+                       "{} needs a buffer of length {} but got {}",
+                       array, needed, found)
+            }
+            InputError::UnknownTerm(ref t) => write!(f, "unknown term \"{}\"", t),
+            InputError::SzConservation(true) => {
+                write!(f, "total Sz is conserved so nup must be given")
+            }
+            InputError::SzConservation(false) => {
+                write!(f, "total Sz is not conserved so nup cannot be given")
+            }
+            InputError::Orientation(o) => {
+                write!(f, "orientation {} is neither 0 (up) nor 1 (down)", o)
+            }
+            InputError::VectorCount { count, dim } => {
+                write!(f, "{} vectors of length {} do not fit in memory", count, dim)
+            }
+        }
+    }
+}
+
+impl Error for InputError {}
+
+pub fn check_lattice(nx: Dim, ny: Dim) -> Result<(), InputError> {
+    check_lattice_max(nx, ny, POW2.len() as u32)
+}
+
+/// Checks the lattice for bases that sieve through all 2^n states, which may
+/// have at most 31 sites
+pub fn check_full_lattice(nx: Dim, ny: Dim) -> Result<(), InputError> {
+    check_lattice_max(nx, ny, 31)
+}
+
+fn check_lattice_max(nx: Dim, ny: Dim, max: u32) -> Result<(), InputError> {
+    let n = nx.raw_int() as u64 * ny.raw_int() as u64;
+    if n == 0 || n > max as u64 {
+        return Err(InputError::LatticeSize { nx: nx.raw_int(),
+                                             ny: ny.raw_int(),
+                                             max });
+    }
+    Ok(())
+}
+
+pub fn check_momentum(nx: Dim, ny: Dim, kx: K, ky: K) -> Result<(), InputError> {
+    if kx.raw_int() >= nx.raw_int() || ky.raw_int() >= ny.raw_int() {
+        return Err(InputError::Momentum { kx: kx.raw_int(), ky: ky.raw_int() });
+    }
+    Ok(())
+}
+
+pub fn check_real_momentum(nx: Dim, ny: Dim, kx: K, ky: K)
+                           -> Result<(), InputError> {
+    check_momentum(nx, ny, kx, ky)?;
+    if !is_real_momentum(nx, ny, kx, ky) {
+        return Err(InputError::ComplexMomentum { kx: kx.raw_int(),
+                                                 ky: ky.raw_int() });
+    }
+    Ok(())
+}
+
+pub fn check_nup(nx: Dim, ny: Dim, nup: u32) -> Result<(), InputError> {
+    let nsites = (nx * ny).raw_int();
+    if nup > nsites {
+        return Err(InputError::SpinUpCount { nup, nsites });
+    }
+    Ok(())
+}
+
+/// Checks that every one of the site indices lies on the lattice
+pub fn check_sites<It>(nx: Dim, ny: Dim, sites: It) -> Result<(), InputError>
+    where It: IntoIterator<Item = u32>
+{
+    let nsites = (nx * ny).raw_int();
+    match sites.into_iter().find(|&site| site >= nsites) {
+        Some(site) => Err(InputError::Site { site, nsites }),
+        None => Ok(())
+    }
+}
+
+pub fn check_parity(parity: u32) -> Result<(), InputError> {
+    match parity {
+        0 | 1 => Ok(()),
+        p => Err(InputError::Parity(p))
+    }
+}
+
+pub fn check_bond(l: I) -> Result<(), InputError> {
+    match l.raw_int() {
+        1..=3 => Ok(()),
+        l => Err(InputError::BondRange(l))
+    }
+}
+
+/// A sparse matrix in compressed sparse row format. Column indices within each
+/// row are sorted and unique, so the arrays could be handed to scipy as is.
+/// Matrices cross the FFI as an `ffi::RawCsrMatrix`.
+pub struct CsrMatrix<T> {
+    data:      Vec<T>,
+    indices:   Vec<u32>,
+    indptr:    Vec<u64>,
     pub ncols: u32,
     pub nrows: u32
 }
 
-impl<T> CoordMatrix<T> {
-    pub fn new(mut data: Vec<T>, mut col: Vec<u32>, mut row: Vec<u32>,
-               ncols: u32, nrows: u32)
-               -> CoordMatrix<T> {
-        let data_ptr = data.as_mut_ptr();
-        let data_len = data.len() as size_t;
+impl<T> CsrMatrix<T> {
+    /// Assembles a CSR matrix from entries given as (row, col, value) triplets
+    /// in any order. No (row, col) pair may appear more than once.
+    pub fn from_triplets(data: Vec<T>, rows: Vec<u32>, cols: Vec<u32>, nrows: u32,
+                         ncols: u32)
+                         -> CsrMatrix<T> {
+        // a stable counting sort by row. The columns in each row are then sorted
+        // separately, which is close to free when they are already in order
+        let mut indptr = vec![0_u64; nrows as usize + 1];
+        for &r in rows.iter() {
+            indptr[r as usize + 1] += 1;
+        }
+        for r in 0..nrows as usize {
+            indptr[r + 1] += indptr[r];
+        }
+        let mut next = indptr.clone();
+        let mut order = vec![0_usize; data.len()];
+        for (e, &r) in rows.iter().enumerate() {
+            order[next[r as usize] as usize] = e;
+            next[r as usize] += 1;
+        }
+        for r in 0..nrows as usize {
+            let (start, end) = (indptr[r] as usize, indptr[r + 1] as usize);
+            order[start..end].sort_by_key(|&e| cols[e]);
+            debug_assert!(order[start..end].windows(2)
+                                           .all(|w| cols[w[0]] != cols[w[1]]));
+        }
+
+        let indices = order.iter().map(|&e| cols[e]).collect::<Vec<u32>>();
+        let mut slots = data.into_iter().map(Some).collect::<Vec<Option<T>>>();
+        let data = order.iter()
+                        .map(|&e| slots[e].take().unwrap())
+                        .collect::<Vec<T>>();
+        CsrMatrix::from_parts(data, indices, indptr, nrows, ncols)
+    }
+
+    /// Assembles a CSR matrix from arrays that are already in CSR form
+    pub fn from_parts(data: Vec<T>, indices: Vec<u32>, indptr: Vec<u64>, nrows: u32,
+                      ncols: u32)
+                      -> CsrMatrix<T> {
+        CsrMatrix { data, indices, indptr, ncols, nrows }
+    }
 
-        let col_ptr = col.as_mut_ptr();
-        let col_len = col.len() as size_t;
+    /// Takes the data, indices and indptr arrays out of the matrix
+    pub fn into_parts(self) -> (Vec<T>, Vec<u32>, Vec<u64>) {
+        (self.data, self.indices, self.indptr)
+    }
 
-        let row_ptr = row.as_mut_ptr();
-        let row_len = row.len() as size_t;
+    /// The data, indices and indptr arrays
+    pub fn as_slices(&self) -> (&[T], &[u32], &[u64]) {
+        (&self.data, &self.indices, &self.indptr)
+    }
 
-        mem::forget(data);
-        mem::forget(col);
-        mem::forget(row);
-        let data = Vector::new(data_ptr, data_len);
-        let col = Vector::new(col_ptr, col_len);
-        let row = Vector::new(row_ptr, row_len);
-        CoordMatrix { data,
-                      col,
-                      row,
-                      ncols,
-                      nrows }
+    /// The number of stored entries
+    pub fn nnz(&self) -> usize { self.data.len() }
+
+    /// The stored entries as (row, col, value) triplets in row-major order
+    #[cfg(test)]
+    pub fn entries(&self) -> Vec<(u32, u32, &T)> {
+        let (data, indices, indptr) = self.as_slices();
+        (0..self.nrows as usize).flat_map(|r| {
+                                    (indptr[r]..indptr[r + 1]).map(move |e| {
+                                        let e = e as usize;
+                                        (r as u32, indices[e], &data[e])
+                                    })
+                                })
+                                .collect()
     }
 }
 
@@ -300,27 +600,197 @@ pub fn sz_basis(n: Dim, nup: u32) -> Vec<BinaryBasis> {
     acc
 }
 
+// site (x, y) of a configuration lives in bit y * nx + x, so shifting a row by
+// one site is a shift of the whole word by one bit, with the sites in the last
+// column wrapping around to the first, and shifting by one row is a rotation of
+// the whole word by nx bits
 pub fn translate_x(dec: BinaryBasis, nx: Dim, ny: Dim) -> BinaryBasis {
-    let n = (0..ny.raw_int()).map(|x| x * nx.raw_int())
-                             .collect::<Vec<u32>>();
-    let s = n.iter()
-             .map(|&x| dec % POW2[(x + nx.raw_int()) as usize] / POW2[x as usize])
-             .map(|x| {
-                      (x * BinaryBasis(2)) % POW2[nx.raw_int() as usize]
-                      + x / POW2[nx.raw_int() as usize - 1]
-                  });
-
-    n.iter().map(|&x| POW2[x as usize])
-     .zip(s)
-     .map(|(a, b)| a * b)  // basically a dot product here
-     .fold(BinaryBasis(0), |acc, x| x + acc) // sum over vector
+    let nx = nx.raw_int();
+    let last = (0..ny.raw_int()).fold(0, |acc, r| acc | 1 << (r * nx + nx - 1));
+    let d = dec.raw_int();
+    BinaryBasis((d & !last) << 1 | (d & last) >> (nx - 1))
 }
 
 pub fn translate_y(dec: BinaryBasis, nx: Dim, ny: Dim) -> BinaryBasis {
-    let xdim = POW2[nx.raw_int() as usize];
-    let pred_totdim = POW2[nx.raw_int() as usize * (ny.raw_int() - 1) as usize];
-    let tail = dec % xdim;
-    dec / xdim + tail * pred_totdim
+    let (nx, n) = (nx.raw_int(), (nx * ny).raw_int());
+    let d = dec.raw_int();
+    BinaryBasis(d >> nx | (d & ((1 << nx) - 1)) << (n - nx))
+}
+
+/// Shift every site of a configuration by (x, y) lattice spacings. A
+/// translation (x, y) corresponds to x applications of `translate_x` followed by
+/// (-y mod ny) applications of `translate_y`, so momentum labels agree with
+/// those used in `consv::k` and `consv::ks`.
+pub fn translate(dec: BinaryBasis, x: u32, y: u32, nx: Dim, ny: Dim) -> BinaryBasis {
+    Translations::new(nx, ny).translate(dec, x, y)
+}
+
+/// Moves the spin on every site (x, y) of a configuration to (-x, -y)
+pub fn invert(dec: BinaryBasis, nx: Dim, ny: Dim) -> BinaryBasis {
+    let (nx, ny) = (nx.raw_int(), ny.raw_int());
+    let d = dec.raw_int();
+    let mut inverted = 0;
+    for y in 0..ny {
+        for x in 0..nx {
+            if d >> (y * nx + x) & 1 == 1 {
+                inverted |= 1 << ((ny - y) % ny * nx + (nx - x) % nx);
+            }
+        }
+    }
+    BinaryBasis(inverted)
+}
+
+/// The masks needed to apply any translation of an nx × ny cluster with a
+/// couple of shifts, built once so inner loops over many configurations do not
+/// redo them
+#[derive(Clone, Debug)]
+pub struct Translations {
+    pub nx: Dim,
+    pub ny: Dim,
+    // lo[x] marks the sites that stay within their row when shifted by x sites.
+    // The remaining sites wrap around to the start of the row
+    lo:     Vec<u64>,
+    full:   u64
+}
+
+impl Translations {
+    pub fn new(nx: Dim, ny: Dim) -> Translations {
+        let (x, y) = (nx.raw_int(), ny.raw_int());
+        let lo = (0..x).map(|s| {
+                           let row = (1_u64 << (x - s)) - 1;
+                           (0..y).fold(0, |acc, r| acc | row << (r * x))
+                       })
+                       .collect();
+        let full = (1_u64 << (x * y - 1) << 1).wrapping_sub(1);
+        Translations { nx, ny, lo, full }
+    }
+
+    /// Same as `translate_x`
+    pub fn translate_x(&self, dec: BinaryBasis) -> BinaryBasis {
+        let nx = self.nx.raw_int();
+        // a single column is carried onto itself
+        if nx == 1 {
+            return dec;
+        }
+        let d = dec.raw_int();
+        BinaryBasis((d & self.lo[1]) << 1 | (d & !self.lo[1]) >> (nx - 1))
+    }
+
+    /// Same as `translate_y`
+    pub fn translate_y(&self, dec: BinaryBasis) -> BinaryBasis {
+        let (nx, n) = (self.nx.raw_int(), (self.nx * self.ny).raw_int());
+        let d = dec.raw_int();
+        BinaryBasis((d >> nx | d << (n - nx)) & self.full)
+    }
+
+    /// Same as `translate`
+    pub fn translate(&self, dec: BinaryBasis, x: u32, y: u32) -> BinaryBasis {
+        let (nx, ny) = (self.nx.raw_int(), self.ny.raw_int());
+        let (x, y) = (x % nx, y % ny);
+        let d = dec.raw_int();
+        let lo = self.lo[x as usize];
+        let d = if x == 0 {
+            d
+        } else {
+            (d & lo) << x | (d & !lo) >> (nx - x)
+        };
+        // (-y mod ny) applications of translate_y add up to a rotation of the
+        // word towards the high bits by y rows
+        let s = y * nx;
+        let d = if s == 0 {
+            d
+        } else {
+            (d << s | d >> (nx * ny - s)) & self.full
+        };
+        BinaryBasis(d)
+    }
+}
+
+/// Enumerate all elements, as (x, y) site displacements, of the subgroup of
+/// lattice translations generated by t1 and t2. t1 = (1, 0) and t2 = (0, 1)
+/// generate the full translation group while t1 = (1, 1) and t2 = (-1, 2)
+/// generate the translations of the √3×√3 superlattice.
+pub fn translation_subgroup(nx: Dim, ny: Dim, t1: (I, I), t2: (I, I))
+                            -> Vec<(u32, u32)> {
+    let wrap = |a: i32, n: Dim| {
+        let n = n.raw_int() as i32;
+        (((a % n) + n) % n) as u32
+    };
+    let mut group = vec![(0, 0)];
+    let mut i = 0;
+    while i < group.len() {
+        let (x, y) = group[i];
+        for &(tx, ty) in [t1, t2].iter() {
+            let new_x = wrap(x as i32 + tx.raw_int(), nx);
+            let new_y = wrap(y as i32 + ty.raw_int(), ny);
+            if !group.contains(&(new_x, new_y)) {
+                group.push((new_x, new_y));
+            }
+        }
+        i += 1;
+    }
+    group.sort();
+    group
+}
+
+/// Label every site by the orbit it belongs to under the given group of
+/// translations. Labels are handed out in order of the lattice index of the
+/// first site in each orbit, e.g. sites 0, 1 and 2 belong to sublattices 0, 1
+/// and 2 under the √3×√3 translations when nx is a multiple of 3.
+pub fn sublattices(nx: Dim, ny: Dim, group: &[(u32, u32)]) -> Vec<u32> {
+    let n = (nx * ny).raw_int() as usize;
+    let mut labels: Vec<Option<u32>> = vec![None; n];
+    let mut nlabels = 0;
+    for site in 0..n {
+        if labels[site].is_some() {
+            continue;
+        }
+        let vec = SiteVector::from_index(I(site as i32), nx, ny);
+        for &(x, y) in group.iter() {
+            let s = vec.xhop(I(x as i32)).yhop(I(y as i32)).lattice_index();
+            labels[s.raw_int() as usize] = Some(nlabels);
+        }
+        nlabels += 1;
+    }
+    labels.into_iter().map(|x| x.unwrap()).collect()
+}
+
+/// Attach the field given for each sublattice to all sites that belong to it
+pub fn sublattice_fields<T: Copy>(labels: &[u32], fields: &[T])
+                                  -> Vec<(BinaryBasis, T)> {
+    labels.iter()
+          .enumerate()
+          .map(|(i, &l)| (POW2[i], fields[l as usize]))
+          .collect()
+}
+
+/// Find the momenta that label distinct representations of the given group of
+/// translations. Momenta that differ by a reciprocal vector of the superlattice
+/// give identical Bloch functions and only the first one is kept.
+pub fn reduced_momenta(nx: Dim, ny: Dim, group: &[(u32, u32)]) -> Vec<(K, K)> {
+    let (nx, ny) = (nx.raw_int() as u64, ny.raw_int() as u64);
+    // the phase of a translation (x, y) in units of 2π / (nx * ny), following
+    // the sign convention of `translate`
+    let character = |kx: u64, ky: u64| {
+        group.iter()
+             .map(|&(x, y)| {
+                      let j = (ny - y as u64 % ny) % ny;
+                      (kx * x as u64 * ny + ky * j * nx) % (nx * ny)
+                  })
+             .collect::<Vec<u64>>()
+    };
+    let mut ks = Vec::new();
+    let mut characters = Vec::new();
+    for kx in 0..nx {
+        for ky in 0..ny {
+            let c = character(kx, ky);
+            if !characters.contains(&c) {
+                characters.push(c);
+                ks.push((K(kx as u32), K(ky as u32)));
+            }
+        }
+    }
+    ks
 }
 
 pub fn exchange_spin_flips(dec: BinaryBasis, s1: BinaryBasis, s2: BinaryBasis)
@@ -389,6 +859,52 @@ pub fn interacting_sites(nx: Dim, ny: Dim, l: I)
     (f(site1), f(site2))
 }
 
+pub type DirectedBonds = (Vec<BinaryBasis>, Vec<BinaryBasis>, Vec<usize>);
+
+/// Generate all pairs of interacting sites like `interacting_sites`, except
+/// that every bond points along the direction of the hop that connects its two
+/// sites and comes with the index of that direction: 0, 1 and 2 for a1, a2 and
+/// a3 when l = 1 or 3, and for b1, b2 and b3 when l = 2. l must pass
+/// `check_bond`.
+pub fn directed_interacting_sites(nx: Dim, ny: Dim, l: I) -> DirectedBonds {
+    type Hop = fn(&SiteVector, I) -> Option<SiteVector>;
+    let a_hops: [Hop; 3] =
+        [SiteVector::a1_hop, SiteVector::a2_hop, SiteVector::a3_hop];
+    let b_hops: [Hop; 3] =
+        [SiteVector::b1_hop, SiteVector::b2_hop, SiteVector::b3_hop];
+    let (stride, funcs) = match l.raw_int() {
+        1 => (I(1), a_hops),
+        2 => (I(1), b_hops),
+        3 => (I(2), a_hops),
+        l => unreachable!("bond range {} was not checked", l)
+    };
+    let mut site1 = Vec::new();
+    let mut site2 = Vec::new();
+    let mut dirs = Vec::new();
+    let mut vec = SiteVector::new((I(0), I(0)), nx, ny);
+    let n = nx * ny;
+    for _ in 0..n.raw_int() {
+        for (d, &func) in funcs.iter().enumerate() {
+            if let Some(neighbor) = func(&vec, stride) {
+                site1.push(POW2[vec.lattice_index().raw_int() as usize]);
+                site2.push(POW2[neighbor.lattice_index().raw_int() as usize]);
+                dirs.push(d);
+            }
+        }
+        vec = vec.next_site();
+    }
+    (site1, site2, dirs)
+}
+
+/// The angles the three bond directions used in `directed_interacting_sites`
+/// make with a1
+pub fn bond_angles(l: I) -> [f64; 3] {
+    match l.raw_int() {
+        2 => [PI / 6., 5. * PI / 6., 3. * PI / 2.],
+        _ => [0., 2. * PI / 3., 4. * PI / 3.]
+    }
+}
+
 pub fn triangular_vert_sites(
     nx: Dim, ny: Dim)
     -> (Vec<BinaryBasis>, Vec<BinaryBasis>, Vec<BinaryBasis>) {
@@ -427,6 +943,119 @@ pub fn triangular_vert_sites(
     (f(site1), f(site2), f(site3))
 }
 
+pub type RhombusSites =
+    (Vec<BinaryBasis>, Vec<BinaryBasis>, Vec<BinaryBasis>, Vec<BinaryBasis>);
+
+/// Generate the sites of all elementary rhombi, i.e. pairs of triangles that
+/// share an edge, with the four sites of each rhombus in cyclic order. There
+/// are three rhombi anchored at every site, one for each bond direction.
+pub fn rhombus_sites(nx: Dim, ny: Dim) -> RhombusSites {
+    let mut site1 = Vec::new();
+    let mut site2 = Vec::new();
+    let mut site3 = Vec::new();
+    let mut site4 = Vec::new();
+    let mut vec = SiteVector::new((I(0), I(0)), nx, ny);
+    let i = I(1);
+    let n = nx * ny;
+
+    for _ in 0..n.raw_int() {
+        let rhombi = [// around the bond along x
+                      [vec.clone(),
+                       vec.xhop(i).yhop(-i),
+                       vec.xhop(i),
+                       vec.yhop(i)],
+                      // around the bond along y
+                      [vec.clone(),
+                       vec.xhop(i),
+                       vec.yhop(i),
+                       vec.xhop(-i).yhop(i)],
+                      // around the bond between r + x and r + y
+                      [vec.clone(),
+                       vec.xhop(i),
+                       vec.xhop(i).yhop(i),
+                       vec.yhop(i)]];
+        for rhombus in rhombi.iter() {
+            site1.push(rhombus[0].lattice_index());
+            site2.push(rhombus[1].lattice_index());
+            site3.push(rhombus[2].lattice_index());
+            site4.push(rhombus[3].lattice_index());
+        }
+        vec = vec.next_site();
+    }
+
+    let f = |s: Vec<I>| {
+        s.into_iter().map(|s| POW2[s.raw_int() as usize])
+         .collect::<Vec<BinaryBasis>>()
+    };
+
+    (f(site1), f(site2), f(site3), f(site4))
+}
+
+pub type Triangle = (BinaryBasis, BinaryBasis, BinaryBasis);
+
+/// Orientation of an elementary triangle on the lattice
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Orientation {
+    Up,
+    Down
+}
+
+impl Orientation {
+    /// 0 for upright triangles and 1 for inverted ones
+    pub fn from_raw(o: u32) -> Result<Orientation, InputError> {
+        match o {
+            0 => Ok(Orientation::Up),
+            1 => Ok(Orientation::Down),
+            _ => Err(InputError::Orientation(o))
+        }
+    }
+}
+
+/// Split the output of `triangular_vert_sites` into upright and inverted
+/// triangles. Both are ordered by the lattice index of the site they are
+/// anchored to.
+pub fn oriented_triangles(nx: Dim, ny: Dim) -> (Vec<Triangle>, Vec<Triangle>) {
+    let (site1, site2, site3) = triangular_vert_sites(nx, ny);
+    let mut up = Vec::new();
+    let mut down = Vec::new();
+    let zip3 = site1.into_iter()
+                    .zip(site2)
+                    .zip(site3)
+                    .map(|((x, y), z)| (x, y, z));
+    for (i, triangle) in zip3.enumerate() {
+        if i % 2 == 0 {
+            up.push(triangle);
+        } else {
+            down.push(triangle);
+        }
+    }
+    (up, down)
+}
+
+/// Generate all pairs of triangles where the first triangle of orientation o1
+/// is anchored at site r and the second of orientation o2 at r + (dx, dy)
+pub fn triangle_pairs(nx: Dim, ny: Dim, dx: I, dy: I, o1: Orientation,
+                      o2: Orientation)
+                      -> (Vec<Triangle>, Vec<Triangle>) {
+    let (up, down) = oriented_triangles(nx, ny);
+    let pick = |o: Orientation| match o {
+        Orientation::Up => &up,
+        Orientation::Down => &down
+    };
+    let mut tri1 = Vec::new();
+    let mut tri2 = Vec::new();
+    let mut vec = SiteVector::new((I(0), I(0)), nx, ny);
+    let n = nx * ny;
+    for _ in 0..n.raw_int() {
+        let r = vec.lattice_index().raw_int() as usize;
+        let rp = vec.xhop(dx).yhop(dy).lattice_index().raw_int() as usize;
+        tri1.push(pick(o1)[r]);
+        tri2.push(pick(o2)[rp]);
+        vec = vec.next_site();
+    }
+    (tri1, tri2)
+}
+
 /// Generate all permutations of the combination of any two sites on the lattice
 /// where l = |i - j| for sites i and j
 pub fn all_sites(nx: Dim, ny: Dim, l: I) -> (Vec<BinaryBasis>, Vec<BinaryBasis>) {
@@ -454,25 +1083,117 @@ pub fn all_sites(nx: Dim, ny: Dim, l: I) -> (Vec<BinaryBasis>, Vec<BinaryBasis>)
     (f(site1), f(site2))
 }
 
-pub fn find_leading_state<'a>(dec: BinaryBasis,
-                              hashtable: &'a FnvHashMap<&BinaryBasis, &BlochFunc>)
-                              -> Option<(&'a BlochFunc, Complex<f64>)> {
+pub fn set_num_threads(n: usize) { NUM_THREADS.store(n, atomic::Ordering::SeqCst); }
+
+pub fn num_threads() -> usize { NUM_THREADS.load(atomic::Ordering::SeqCst) }
+
+/// Runs f in a thread pool with as many threads as set by `set_num_threads`.
+/// The pool is built on first use and reused until the thread count changes.
+/// Should the pool fail to build, f runs in rayon's global pool instead.
+pub fn with_thread_pool<F, R>(f: F) -> R
+    where F: FnOnce() -> R + Send,
+          R: Send
+{
+    let nthreads = num_threads();
+    let pool = {
+        // the lock is released before f runs, so nested calls do not block
+        let mut cached = POOL.lock().unwrap_or_else(|e| e.into_inner());
+        match *cached {
+            Some((n, ref pool)) if n == nthreads => Some(pool.clone()),
+            _ => {
+                let pool = ThreadPoolBuilder::new().num_threads(nthreads)
+                                                   .build()
+                                                   .ok()
+                                                   .map(Arc::new);
+                *cached = pool.clone().map(|p| (nthreads, p));
+                pool
+            }
+        }
+    };
+    match pool {
+        Some(pool) => pool.install(f),
+        None => f()
+    }
+}
+
+/// Whether dec is the smallest state in its orbit under translations. Leading
+/// states are picked this way so every orbit is generated exactly once no
+/// matter which thread gets to it.
+pub fn is_orbit_lead(dec: BinaryBasis, trans: &Translations) -> bool {
+    let mut new_dec = dec;
+    for _ in 0..trans.ny.raw_int() {
+        for _ in 0..trans.nx.raw_int() {
+            if new_dec < dec {
+                return false;
+            }
+            new_dec = trans.translate_x(new_dec);
+        }
+        new_dec = trans.translate_y(new_dec);
+    }
+    true
+}
+
+/// Builds the Bloch function with momentum (kx, ky) led by dec, which must
+/// satisfy `is_orbit_lead`. Returns None when the Bloch function vanishes at
+/// this momentum.
+pub fn bloch_func<P: Phase>(dec: BinaryBasis, trans: &Translations, kx: K, ky: K)
+                            -> Option<BlochFunc<P>> {
+    let (nx, ny) = (trans.nx, trans.ny);
+    let phase = |i, j| {
+        let ang1 = 2. * PI * (i * kx.raw_int()) as f64 / nx.raw_int() as f64;
+        let ang2 = 2. * PI * (j * ky.raw_int()) as f64 / ny.raw_int() as f64;
+        P::from_angle(ang1 + ang2)
+    };
+
+    // "decs" is a hashtable that holds vectors whose entries correspond to
+    // Bloch function constituent configurations which are mapped to single
+    // decimals that represent the leading states.
+    let mut decs: FnvHashMap<BinaryBasis, P> = FnvHashMap::default();
+    // "new_dec" represents the configuration we are currently iterating over.
+    let mut new_dec = dec;
+    for j in 0..ny.raw_int() {
+        for i in 0..nx.raw_int() {
+            let new_p = match decs.get(&new_dec) {
+                Some(&p) => p + phase(i, j),
+                None => phase(i, j)
+            };
+            decs.insert(new_dec, new_p);
+            new_dec = trans.translate_x(new_dec);
+        }
+        new_dec = trans.translate_y(new_dec);
+    }
+
+    let norm = decs.values()
+                   .map(|&x| x.norm() * x.norm())
+                   .sum::<f64>()
+                   .sqrt();
+
+    if norm > 1e-8 {
+        Some(BlochFunc { lead: dec, decs, norm })
+    } else {
+        None
+    }
+}
+
+pub fn find_leading_state<'a, P: Phase>(
+    dec: BinaryBasis,
+    hashtable: &'a FnvHashMap<&BinaryBasis, &BlochFunc<P>>)
+    -> Option<(&'a BlochFunc<P>, P)> {
     match hashtable.get(&dec) {
         None => None,
         Some(&cntd_state) => match cntd_state.decs.get(&dec) {
             None => None,
             Some(&p) => {
-                let mut phase = p.conj();
-                phase /= phase.norm();
+                let phase = p.conj() * (1. / p.norm());
                 Some((cntd_state, phase))
             }
         }
     }
 }
 
-pub fn gen_ind_dec_conv_dicts<'a>(
-    bfuncs: &'a BlochFuncSet)
-    -> (FnvHashMap<u32, &'a BlochFunc>, FnvHashMap<BinaryBasis, u32>) {
+pub fn gen_ind_dec_conv_dicts<P>(
+    bfuncs: &BlochFuncSet<P>)
+    -> (FnvHashMap<u32, &BlochFunc<P>>, FnvHashMap<BinaryBasis, u32>) {
     let dec = bfuncs.iter().map(|x| x.lead).collect::<Vec<_>>();
     let nstates = dec.len();
     let inds = (0..nstates as u32).collect::<Vec<u32>>();
@@ -483,12 +1204,12 @@ pub fn gen_ind_dec_conv_dicts<'a>(
                         .collect::<FnvHashMap<BinaryBasis, u32>>();
     let ind_to_dec = inds.into_iter()
                          .zip(bfuncs.iter())
-                         .collect::<FnvHashMap<u32, &BlochFunc>>();
+                         .collect::<FnvHashMap<u32, &BlochFunc<P>>>();
 
     (ind_to_dec, dec_to_ind)
 }
 
-pub fn coeff(orig_state: &BlochFunc, cntd_state: &BlochFunc) -> f64 {
+pub fn coeff<P>(orig_state: &BlochFunc<P>, cntd_state: &BlochFunc<P>) -> f64 {
     cntd_state.norm / orig_state.norm
 }
 
@@ -550,6 +1271,25 @@ mod tests {
         assert_eq!(sz_basis(n, nup).len(), 20);
     }
 
+    #[test]
+    fn csr_from_triplets_test() {
+        let data = vec![1., 2., 3., 4., 5.];
+        let rows = vec![2, 0, 2, 1, 0];
+        let cols = vec![1, 2, 0, 1, 0];
+        let mat = CsrMatrix::from_triplets(data, rows, cols, 3, 3);
+        let entries = mat.entries()
+                         .into_iter()
+                         .map(|(r, c, &d)| (r, c, d))
+                         .collect::<Vec<_>>();
+        let ans = vec![(0, 0, 5.), (0, 2, 2.), (1, 1, 4.), (2, 0, 3.), (2, 1, 1.)];
+        assert_eq!(entries, ans);
+
+        let (data, indices, indptr) = mat.into_parts();
+        assert_eq!(data, vec![5., 2., 4., 3., 1.]);
+        assert_eq!(indices, vec![0, 2, 1, 0, 1]);
+        assert_eq!(indptr, vec![0, 2, 3, 5]);
+    }
+
     #[test]
     fn translate_x_test() {
         let d1 = BinaryBasis(10);
@@ -568,6 +1308,113 @@ mod tests {
         assert_eq!(translate_y(d1, nx, ny), d2);
     }
 
+    #[test]
+    fn input_check_test() {
+        assert!(check_lattice(Dim(7), Dim(9)).is_ok());
+        assert_eq!(check_lattice(Dim(8), Dim(8)),
+                   Err(InputError::LatticeSize { nx: 8, ny: 8, max: 63 }));
+        assert!(check_lattice(Dim(0), Dim(4)).is_err());
+        assert!(check_full_lattice(Dim(5), Dim(6)).is_ok());
+        assert_eq!(check_full_lattice(Dim(4), Dim(8)),
+                   Err(InputError::LatticeSize { nx: 4, ny: 8, max: 31 }));
+        assert!(check_momentum(Dim(4), Dim(3), K(3), K(2)).is_ok());
+        assert_eq!(check_momentum(Dim(4), Dim(3), K(1), K(3)),
+                   Err(InputError::Momentum { kx: 1, ky: 3 }));
+        assert!(check_real_momentum(Dim(4), Dim(3), K(2), K(0)).is_ok());
+        assert!(check_real_momentum(Dim(4), Dim(3), K(1), K(0)).is_err());
+        assert!(check_nup(Dim(4), Dim(3), 12).is_ok());
+        assert!(check_nup(Dim(4), Dim(3), 13).is_err());
+        assert!(check_bond(I(3)).is_ok());
+        assert_eq!(check_bond(I(0)), Err(InputError::BondRange(0)));
+        assert!(check_bond(I(4)).is_err());
+    }
+
+    #[test]
+    fn translations_test() {
+        // every translation agrees with stepping site by site
+        let (nx, ny) = (Dim(5), Dim(3));
+        let trans = Translations::new(nx, ny);
+        for dec in (0..1 << 15).step_by(7).map(BinaryBasis) {
+            for x in 0..5 {
+                for y in 0..3 {
+                    let mut ans = dec;
+                    for _ in 0..x {
+                        ans = translate_x(ans, nx, ny);
+                    }
+                    for _ in 0..(3 - y) % 3 {
+                        ans = translate_y(ans, nx, ny);
+                    }
+                    assert_eq!(trans.translate(dec, x, y), ans);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn translations_step_test() {
+        // including the single rows and columns that wrap onto themselves
+        for &(nx, ny) in [(1, 4), (1, 5), (4, 1), (5, 1), (2, 3)].iter() {
+            let (nx, ny) = (Dim(nx), Dim(ny));
+            let trans = Translations::new(nx, ny);
+            for dec in (0..1 << (nx * ny).raw_int()).map(BinaryBasis) {
+                assert_eq!(trans.translate_x(dec), translate_x(dec, nx, ny));
+                assert_eq!(trans.translate_y(dec), translate_y(dec, nx, ny));
+            }
+        }
+    }
+
+    #[test]
+    fn translate_test() {
+        let dec = BinaryBasis(2);
+        let nx = Dim(4);
+        let ny = Dim(4);
+        assert_eq!(translate(dec, 0, 3, nx, ny), translate_y(dec, nx, ny));
+        assert_eq!(translate(dec, 2, 0, nx, ny), BinaryBasis(8));
+    }
+
+    #[test]
+    fn invert_test() {
+        let (nx, ny) = (Dim(4), Dim(3));
+        // (1, 0) goes to (3, 0) and (2, 1) to (2, 2)
+        assert_eq!(invert(BinaryBasis(0b10), nx, ny), BinaryBasis(0b1000));
+        assert_eq!(invert(BinaryBasis(1 << 6), nx, ny), BinaryBasis(1 << 10));
+        let dec = BinaryBasis(0b1011_0010_0111);
+        assert_eq!(invert(invert(dec, nx, ny), nx, ny), dec);
+        // inversion reverses translations
+        assert_eq!(invert(translate(dec, 1, 2, nx, ny), nx, ny),
+                   translate(invert(dec, nx, ny), 3, 1, nx, ny));
+    }
+
+    #[test]
+    fn irreducible_momenta_test() {
+        let momenta = irreducible_momenta(Dim(4), Dim(3));
+        assert_eq!(momenta,
+                   vec![(K(0), K(0), 1),
+                        (K(0), K(1), 2),
+                        (K(1), K(0), 2),
+                        (K(1), K(1), 2),
+                        (K(1), K(2), 2),
+                        (K(2), K(0), 1),
+                        (K(2), K(1), 2)]);
+        let total = momenta.iter().map(|&(_, _, m)| m).sum::<u32>();
+        assert_eq!(total, 12);
+        assert_eq!(inverse_momentum(Dim(4), Dim(3), K(1), K(2)), (K(3), K(1)));
+    }
+
+    #[test]
+    fn translation_subgroup_test() {
+        let nx = Dim(6);
+        let ny = Dim(6);
+        let full = translation_subgroup(nx, ny, (I(1), I(0)), (I(0), I(1)));
+        let sqrt3 = translation_subgroup(nx, ny, (I(1), I(1)), (I(-1), I(2)));
+        assert_eq!(full.len(), 36);
+        assert_eq!(sqrt3.len(), 12);
+        assert_eq!(reduced_momenta(nx, ny, &sqrt3).len(), 12);
+
+        let labels = sublattices(nx, ny, &sqrt3);
+        assert_eq!(labels[0..7].to_vec(), vec![0, 1, 2, 0, 1, 2, 2]);
+    }
+
     #[test]
     fn exchange_spin_flips_test1() {
         let dec = BinaryBasis(10);
@@ -624,6 +1471,18 @@ mod tests {
         assert_eq!(bonds[2].len(), 108);
     }
 
+    #[test]
+    fn directed_interacting_sites_test() {
+        let nx = Dim(4);
+        let ny = Dim(6);
+        let (site1, site2, dirs) = directed_interacting_sites(nx, ny, I(1));
+        let (undirected, _) = interacting_sites(nx, ny, I(1));
+        assert_eq!(site1.len(), undirected.len());
+        // a1, a2 and a3 hops from site 5 = (1, 1)
+        assert_eq!(site2[15..18].to_vec(), vec![POW2[6], POW2[8], POW2[1]]);
+        assert_eq!(dirs[15..18].to_vec(), vec![0, 1, 2]);
+    }
+
     #[test]
     fn gamma_test() {
         let nx = Dim(4);
@@ -660,4 +1519,32 @@ mod tests {
         assert_eq!(site2, site2_target);
         assert_eq!(site3, site3_target);
     }
+
+    #[test]
+    fn rhombus_sites_test() {
+        let nx = Dim(3);
+        let ny = Dim(3);
+        let (site1, site2, site3, site4) = rhombus_sites(nx, ny);
+        assert_eq!(site1.len(), 27);
+        // rhombi anchored at site 4 = (1, 1)
+        let rhombi = (12..15).map(|i| (site1[i], site2[i], site3[i], site4[i]))
+                             .collect::<Vec<_>>();
+        assert_eq!(rhombi,
+                   vec![(POW2[4], POW2[2], POW2[5], POW2[7]),
+                        (POW2[4], POW2[5], POW2[7], POW2[6]),
+                        (POW2[4], POW2[5], POW2[8], POW2[7])]);
+    }
+
+    #[test]
+    fn triangle_pairs_test() {
+        let nx = Dim(3);
+        let ny = Dim(3);
+        let (tri1, tri2) =
+            triangle_pairs(nx, ny, I(1), I(2), Orientation::Up, Orientation::Down);
+        assert_eq!(tri1.len(), 9);
+        // the upright triangle at site 4 pairs with the inverted triangle at
+        // site 4 + (1, 2) -> (2, 0)
+        assert_eq!(tri1[4], (POW2[4], POW2[5], POW2[7]));
+        assert_eq!(tri2[4], (POW2[2], POW2[0], POW2[6]));
+    }
 }
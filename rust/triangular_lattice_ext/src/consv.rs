@@ -1,69 +1,52 @@
 /// This module contains the following sub-modules:
 ///     k
 ///     ks
+///     kp
 ///     ksl
+///     kr
+///     ksr
+///     none
+///     s
 
 /// This module contains functions that work under the assumption that lattice
 /// momentum is conserved.
 pub mod k {
-    use fnv::FnvHashMap;
-    use num_complex::Complex;
+    use rayon::prelude::*;
 
     use blochfunc::{BlochFunc, BlochFuncSet};
     use common::*;
     use ops;
 
-    fn bloch_states<'a>(nx: Dim, ny: Dim, kx: K, ky: K) -> BlochFuncSet {
-        let n = nx * ny;
-        let mut sieve = vec![true; 2_usize.pow(n.raw_int())];
-        let mut bfuncs: Vec<BlochFunc> = Vec::new();
-        let phase = |i, j| {
-            let r = 1.;
-            let ang1 = 2. * PI * (i * kx.raw_int()) as f64 / nx.raw_int() as f64;
-            let ang2 = 2. * PI * (j * ky.raw_int()) as f64 / ny.raw_int() as f64;
-            Complex::from_polar(&r, &(ang1 + ang2))
-        };
-
-        for dec in 0..2_usize.pow(n.raw_int()) {
-            if sieve[dec] {
-                // if the corresponding entry of dec in "sieve" is not false,
-                // we find all translations of dec and put them in a BlochFunc
-                // then mark all corresponding entries in "sieve" as false.
+    /// Checks the lattice and momentum every function in this module takes
+    pub fn check(nx: Dim, ny: Dim, kx: K, ky: K) -> Result<(), InputError> {
+        check_full_lattice(nx, ny)?;
+        check_momentum(nx, ny, kx, ky)
+    }
 
-                // "decs" is a hashtable that holds vectors whose entries
-                // correspond to Bloch function constituent configurations which
-                // are mapped to single decimals that represent the leading states.
-                let mut decs: FnvHashMap<BinaryBasis, Complex<f64>> =
-                    FnvHashMap::default();
-                // "new_dec" represents the configuration we are currently iterating
-                // over.
-                let mut new_dec = BinaryBasis(dec as u64);
-                for j in 0..ny.raw_int() {
-                    for i in 0..nx.raw_int() {
-                        sieve[new_dec.raw_int() as usize] = false;
-                        let new_p = match decs.get(&new_dec) {
-                            Some(&p) => p + phase(i, j),
-                            None => phase(i, j)
-                        };
-                        decs.insert(new_dec, new_p);
-                        new_dec = translate_x(new_dec, nx, ny);
-                    }
-                    new_dec = translate_y(new_dec, nx, ny);
-                }
+    pub fn bloch_states(nx: Dim, ny: Dim, kx: K, ky: K) -> BlochFuncSet {
+        build_bloch_states(nx, ny, kx, ky)
+    }
 
-                let lead = BinaryBasis(dec as u64);
-                let norm = decs.values()
-                               .into_iter()
-                               .map(|&x| x.norm_sqr())
-                               .sum::<f64>()
-                               .sqrt();
+    /// Bloch functions with real coefficients. Only valid for momenta where
+    /// `is_real_momentum` holds.
+    fn real_bloch_states(nx: Dim, ny: Dim, kx: K, ky: K) -> BlochFuncSet<f64> {
+        assert!(is_real_momentum(nx, ny, kx, ky));
+        build_bloch_states(nx, ny, kx, ky)
+    }
 
-                if norm > 1e-8 {
-                    let mut bfunc = BlochFunc { lead, decs, norm };
-                    bfuncs.push(bfunc);
-                }
-            }
-        }
+    fn build_bloch_states<P: Phase>(nx: Dim, ny: Dim, kx: K, ky: K)
+                                    -> BlochFuncSet<P> {
+        let n = nx * ny;
+        let nstates = 2_usize.pow(n.raw_int());
+        let trans = Translations::new(nx, ny);
+        // every state is tried as a leading state in parallel
+        let bfuncs = with_thread_pool(|| {
+            (0..nstates).into_par_iter()
+                        .map(|dec| BinaryBasis(dec as u64))
+                        .filter(|&dec| is_orbit_lead(dec, &trans))
+                        .filter_map(|dec| bloch_func(dec, &trans, kx, ky))
+                        .collect::<Vec<BlochFunc<P>>>()
+        });
 
         let mut table = BlochFuncSet::create(nx, ny, bfuncs);
         table.sort();
@@ -71,55 +54,128 @@ pub mod k {
     }
 
     pub fn h_ss_z(nx: Dim, ny: Dim, kx: K, ky: K, l: I)
-                  -> CoordMatrix<CComplex<f64>> {
+                  -> CsrMatrix<CComplex<f64>> {
         let bfuncs = bloch_states(nx, ny, kx, ky);
         let sites = interacting_sites(nx, ny, l);
         ops::ss_z(&sites, &bfuncs)
     }
 
     pub fn h_ss_xy(nx: Dim, ny: Dim, kx: K, ky: K, l: I)
-                   -> CoordMatrix<CComplex<f64>> {
+                   -> CsrMatrix<CComplex<f64>> {
         let bfuncs = bloch_states(nx, ny, kx, ky);
         let sites = interacting_sites(nx, ny, l);
         ops::ss_xy(&sites, &bfuncs)
     }
 
     pub fn h_ss_ppmm(nx: Dim, ny: Dim, kx: K, ky: K, l: I)
-                     -> CoordMatrix<CComplex<f64>> {
+                     -> CsrMatrix<CComplex<f64>> {
         let bfuncs = bloch_states(nx, ny, kx, ky);
         let sites = interacting_sites(nx, ny, l);
         ops::ss_ppmm(&sites, &bfuncs)
     }
 
     pub fn h_ss_pmz(nx: Dim, ny: Dim, kx: K, ky: K, l: I)
-                    -> CoordMatrix<CComplex<f64>> {
+                    -> CsrMatrix<CComplex<f64>> {
         let bfuncs = bloch_states(nx, ny, kx, ky);
         let sites = interacting_sites(nx, ny, l);
         ops::ss_pmz(&sites, &bfuncs)
     }
 
-    pub fn h_sss_chi(nx: Dim, ny: Dim, kx: K, ky: K) -> CoordMatrix<CComplex<f64>> {
+    pub fn h_sss_chi(nx: Dim, ny: Dim, kx: K, ky: K) -> CsrMatrix<CComplex<f64>> {
         let bfuncs = bloch_states(nx, ny, kx, ky);
         let sites = triangular_vert_sites(nx, ny);
         ops::sss_chi(&sites, &bfuncs)
     }
 
-    pub fn ss_z(nx: Dim, ny: Dim, kx: K, ky: K, l: I) -> CoordMatrix<CComplex<f64>> {
+    pub fn h_ring(nx: Dim, ny: Dim, kx: K, ky: K) -> CsrMatrix<CComplex<f64>> {
+        let bfuncs = bloch_states(nx, ny, kx, ky);
+        let sites = rhombus_sites(nx, ny);
+        ops::ring(&sites, &bfuncs)
+    }
+
+    /// Exchange interaction with a general 3x3 exchange matrix J^ab for each
+    /// bond direction as given by `directed_interacting_sites`
+    pub fn h_ss_tensor(nx: Dim, ny: Dim, kx: K, ky: K, l: I,
+                       jmats: &[[[f64; 3]; 3]; 3])
+                       -> CsrMatrix<CComplex<f64>> {
+        let bfuncs = bloch_states(nx, ny, kx, ky);
+        let sites = directed_interacting_sites(nx, ny, l);
+        ops::ss_tensor(&sites, jmats, &bfuncs)
+    }
+
+    /// Dzyaloshinskii-Moriya interaction with one D vector per bond direction
+    /// as given by `directed_interacting_sites`
+    pub fn h_ss_dm(nx: Dim, ny: Dim, kx: K, ky: K, l: I, dvecs: &[[f64; 3]; 3])
+                   -> CsrMatrix<CComplex<f64>> {
+        let bfuncs = bloch_states(nx, ny, kx, ky);
+        let sites = directed_interacting_sites(nx, ny, l);
+        ops::ss_dm(&sites, dvecs, &bfuncs)
+    }
+
+    pub fn ss_z(nx: Dim, ny: Dim, kx: K, ky: K, l: I) -> CsrMatrix<CComplex<f64>> {
         let bfuncs = bloch_states(nx, ny, kx, ky);
         let sites = all_sites(nx, ny, l);
         ops::ss_z(&sites, &bfuncs)
     }
 
     pub fn ss_xy(nx: Dim, ny: Dim, kx: K, ky: K, l: I)
-                 -> CoordMatrix<CComplex<f64>> {
+                 -> CsrMatrix<CComplex<f64>> {
         let bfuncs = bloch_states(nx, ny, kx, ky);
         let sites = all_sites(nx, ny, l);
         ops::ss_xy(&sites, &bfuncs)
     }
 
+    pub fn h_ss_z_real(nx: Dim, ny: Dim, kx: K, ky: K, l: I) -> CsrMatrix<f64> {
+        let bfuncs = real_bloch_states(nx, ny, kx, ky);
+        let sites = interacting_sites(nx, ny, l);
+        ops::ss_z(&sites, &bfuncs)
+    }
+
+    pub fn h_ss_xy_real(nx: Dim, ny: Dim, kx: K, ky: K, l: I) -> CsrMatrix<f64> {
+        let bfuncs = real_bloch_states(nx, ny, kx, ky);
+        let sites = interacting_sites(nx, ny, l);
+        ops::ss_xy(&sites, &bfuncs)
+    }
+
+    pub fn h_ring_real(nx: Dim, ny: Dim, kx: K, ky: K) -> CsrMatrix<f64> {
+        let bfuncs = real_bloch_states(nx, ny, kx, ky);
+        let sites = rhombus_sites(nx, ny);
+        ops::ring(&sites, &bfuncs)
+    }
+
+    pub fn ss_z_real(nx: Dim, ny: Dim, kx: K, ky: K, l: I) -> CsrMatrix<f64> {
+        let bfuncs = real_bloch_states(nx, ny, kx, ky);
+        let sites = all_sites(nx, ny, l);
+        ops::ss_z(&sites, &bfuncs)
+    }
+
+    pub fn ss_xy_real(nx: Dim, ny: Dim, kx: K, ky: K, l: I) -> CsrMatrix<f64> {
+        let bfuncs = real_bloch_states(nx, ny, kx, ky);
+        let sites = all_sites(nx, ny, l);
+        ops::ss_xy(&sites, &bfuncs)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn chi_chi(nx: Dim, ny: Dim, kx: K, ky: K, dx: I, dy: I, o1: Orientation,
+                   o2: Orientation)
+                   -> CsrMatrix<CComplex<f64>> {
+        let bfuncs = bloch_states(nx, ny, kx, ky);
+        let pairs = triangle_pairs(nx, ny, dx, dy, o1, o2);
+        ops::chi_chi(&pairs, &bfuncs)
+    }
+
+    pub fn chi_order(nx: Dim, ny: Dim, kx: K, ky: K, staggered: bool)
+                     -> CsrMatrix<CComplex<f64>> {
+        let bfuncs = bloch_states(nx, ny, kx, ky);
+        let triangles = oriented_triangles(nx, ny);
+        ops::chi_order(&triangles, staggered, &bfuncs)
+    }
+
     #[cfg(test)]
     mod tests {
         use super::*;
+        use fnv::FnvHashMap;
+        use num_complex::Complex;
 
         #[test]
         fn bloch_states_test() {
@@ -129,6 +185,148 @@ pub mod k {
             let ky = K(3);
             let bfuncs = bloch_states(nx, ny, kx, ky);
             assert_eq!(bfuncs.nonzero, 4080);
+
+            // a single column is left alone by translations along x. Of the
+            // orbits of a ring of 4, three have 4 states, one 2 and two 1
+            let dims = (0..4).map(|ky| bloch_states(Dim(1), Dim(4), K(0), K(ky)))
+                             .map(|bfuncs| bfuncs.nonzero)
+                             .collect::<Vec<_>>();
+            assert_eq!(dims, vec![6, 3, 4, 3]);
+        }
+
+        #[test]
+        fn real_bloch_states_test() {
+            // the real and complex paths agree wherever all phases are ±1
+            let nx = Dim(4);
+            let ny = Dim(4);
+            let (kx, ky) = (K(0), K(2));
+            let cmat = h_ss_xy(nx, ny, kx, ky, I(1));
+            let rmat = h_ss_xy_real(nx, ny, kx, ky, I(1));
+            let celements = cmat.entries()
+                                .into_iter()
+                                .map(|(i, j, c)| ((i, j), (c.re, c.im)))
+                                .collect::<FnvHashMap<_, _>>();
+            let relements = rmat.entries()
+                                .into_iter()
+                                .map(|(i, j, &r)| ((i, j), r))
+                                .collect::<FnvHashMap<_, _>>();
+            assert_eq!(celements.len(), relements.len());
+            for (key, &(re, im)) in celements.iter() {
+                assert!(im.abs() < 1e-10);
+                assert!((re - relements.get(key).unwrap()).abs() < 1e-10);
+            }
+        }
+
+        #[test]
+        fn thread_count_test() {
+            // the output must not depend on how rows are spread across threads.
+            // The thread count is global, so it is put back for the other tests
+            let before = num_threads();
+            let build = |nthreads| {
+                set_num_threads(nthreads);
+                let mat = h_ss_xy(Dim(4), Dim(4), K(1), K(2), I(1));
+                mat.entries()
+                   .into_iter()
+                   .map(|(i, j, c)| (i, j, c.re, c.im))
+                   .collect::<Vec<_>>()
+            };
+            let serial = build(1);
+            let parallel = build(3);
+            set_num_threads(before);
+            assert_eq!(serial, parallel);
+        }
+
+        #[test]
+        fn chi_chi_trace_test() {
+            // χ^2 on a single triangle has eigenvalues 0 (S = 3/2) and 3/16
+            // (both S = 1/2 doublets), hence a trace of 3/4 over its 8 states
+            let nx = Dim(3);
+            let ny = Dim(3);
+            let mut trace = 0.;
+            for kx in 0..3 {
+                for ky in 0..3 {
+                    let mat = chi_chi(nx, ny, K(kx), K(ky), I(0), I(0),
+                                      Orientation::Up, Orientation::Up);
+                    for (i, j, d) in mat.entries() {
+                        if i == j {
+                            trace += d.re;
+                        }
+                    }
+                }
+            }
+            assert!((trace - 9. * 0.75 * 64.).abs() < 1e-8);
+        }
+
+        #[test]
+        fn h_ring_trace_test() {
+            // only the fully polarized configurations on a rhombus are left
+            // invariant by P_ijkl, hence a trace of 2^(N - 2) per rhombus
+            let nx = Dim(3);
+            let ny = Dim(3);
+            let mut trace = 0.;
+            for kx in 0..3 {
+                for ky in 0..3 {
+                    let mat = h_ring(nx, ny, K(kx), K(ky));
+                    for (i, j, d) in mat.entries() {
+                        if i == j {
+                            trace += d.re;
+                        }
+                    }
+                }
+            }
+            assert!((trace - 27. * 128.).abs() < 1e-8);
+        }
+
+        #[test]
+        fn h_ss_tensor_test() {
+            // γ S^+_i S^+_j + γ* S^-_i S^-_j written as an exchange matrix
+            // reproduces h_ss_ppmm
+            let nx = Dim(3);
+            let ny = Dim(4);
+            let mut jmats = [[[0.; 3]; 3]; 3];
+            for (d, &ang) in [0., 2. * PI / 3., -2. * PI / 3.].iter().enumerate() {
+                let (s, c) = ang.sin_cos();
+                jmats[d] = [[2. * c, -2. * s, 0.], [-2. * s, -2. * c, 0.], [0.; 3]];
+            }
+            let to_map = |mat: CsrMatrix<CComplex<f64>>| {
+                mat.entries()
+                   .into_iter()
+                   .map(|(i, j, d)| ((i, j), Complex::new(d.re, d.im)))
+                   .collect::<FnvHashMap<(u32, u32), Complex<f64>>>()
+            };
+            let tensor = to_map(h_ss_tensor(nx, ny, K(1), K(2), I(1), &jmats));
+            let ppmm = to_map(h_ss_ppmm(nx, ny, K(1), K(2), I(1)));
+            for (key, &val) in ppmm.iter() {
+                let other = *tensor.get(key).unwrap_or(&Complex::new(0., 0.));
+                assert!((val - other).norm() < 1e-10);
+            }
+            for (key, &val) in tensor.iter() {
+                let other = *ppmm.get(key).unwrap_or(&Complex::new(0., 0.));
+                assert!((val - other).norm() < 1e-10);
+            }
+        }
+
+        #[test]
+        fn h_ss_dm_trace_test() {
+            // all cross terms between bonds are traceless, so Tr(H^2) is
+            // 2^(N - 3) Σ_ij |D_ij|^2
+            let nx = Dim(3);
+            let ny = Dim(3);
+            let dvecs = [[0.3, -0.2, 1.], [0., 0.7, 0.], [-0.5, 0., 0.1]];
+            let mut trace = 0.;
+            for kx in 0..3 {
+                for ky in 0..3 {
+                    let mat = h_ss_dm(nx, ny, K(kx), K(ky), I(1), &dvecs);
+                    let (data, _, _) = mat.as_slices();
+                    trace += data.iter()
+                                 .map(|d| d.re * d.re + d.im * d.im)
+                                 .sum::<f64>();
+                }
+            }
+            let dsq = dvecs.iter()
+                           .map(|d| d.iter().map(|x| x * x).sum::<f64>())
+                           .sum::<f64>();
+            assert!((trace - 9. * dsq * 64.).abs() < 1e-8);
         }
     }
 }
@@ -136,6 +334,415 @@ pub mod k {
 /// This module contains functions that work under the assumption that lattice
 /// momentum and total Sz are conserved.
 pub mod ks {
+    use rayon::prelude::*;
+
+    use blochfunc::{BlochFunc, BlochFuncSet};
+    use common::*;
+    use ops;
+
+    /// Checks the lattice, momentum and number of spins pointing up every
+    /// function in this module takes
+    pub fn check(nx: Dim, ny: Dim, kx: K, ky: K, nup: u32)
+                 -> Result<(), InputError> {
+        check_lattice(nx, ny)?;
+        check_momentum(nx, ny, kx, ky)?;
+        check_nup(nx, ny, nup)
+    }
+
+    pub fn bloch_states(nx: Dim, ny: Dim, kx: K, ky: K, nup: u32) -> BlochFuncSet {
+        build_bloch_states(nx, ny, kx, ky, nup)
+    }
+
+    /// Bloch functions with real coefficients. Only valid for momenta where
+    /// `is_real_momentum` holds.
+    fn real_bloch_states(nx: Dim, ny: Dim, kx: K, ky: K, nup: u32)
+                         -> BlochFuncSet<f64> {
+        assert!(is_real_momentum(nx, ny, kx, ky));
+        build_bloch_states(nx, ny, kx, ky, nup)
+    }
+
+    fn build_bloch_states<P: Phase>(nx: Dim, ny: Dim, kx: K, ky: K, nup: u32)
+                                    -> BlochFuncSet<P> {
+        let n = nx * ny;
+        let sz_basis_states = sz_basis(n, nup);
+        let trans = Translations::new(nx, ny);
+        // translations preserve the total Sz so every orbit lies entirely within
+        // sz_basis_states
+        let bfuncs = with_thread_pool(|| {
+            sz_basis_states.par_iter()
+                           .filter(|&&dec| is_orbit_lead(dec, &trans))
+                           .filter_map(|&dec| bloch_func(dec, &trans, kx, ky))
+                           .collect::<Vec<BlochFunc<P>>>()
+        });
+
+        let mut table = BlochFuncSet::create(nx, ny, bfuncs);
+        table.sort();
+        table
+    }
+
+    pub fn h_ss_z(nx: Dim, ny: Dim, kx: K, ky: K, nup: u32, l: I)
+                  -> CsrMatrix<CComplex<f64>> {
+        let bfuncs = bloch_states(nx, ny, kx, ky, nup);
+        let sites = interacting_sites(nx, ny, l);
+        ops::ss_z(&sites, &bfuncs)
+    }
+
+    pub fn h_ss_xy(nx: Dim, ny: Dim, kx: K, ky: K, nup: u32, l: I)
+                   -> CsrMatrix<CComplex<f64>> {
+        let bfuncs = bloch_states(nx, ny, kx, ky, nup);
+        let sites = interacting_sites(nx, ny, l);
+        ops::ss_xy(&sites, &bfuncs)
+    }
+
+    pub fn h_sss_chi(nx: Dim, ny: Dim, kx: K, ky: K, nup: u32)
+                     -> CsrMatrix<CComplex<f64>> {
+        let bfuncs = bloch_states(nx, ny, kx, ky, nup);
+        let sites = triangular_vert_sites(nx, ny);
+        ops::sss_chi(&sites, &bfuncs)
+    }
+
+    pub fn h_ring(nx: Dim, ny: Dim, kx: K, ky: K, nup: u32)
+                  -> CsrMatrix<CComplex<f64>> {
+        let bfuncs = bloch_states(nx, ny, kx, ky, nup);
+        let sites = rhombus_sites(nx, ny);
+        ops::ring(&sites, &bfuncs)
+    }
+
+    /// Dzyaloshinskii-Moriya interaction with an out-of-plane D_z for each
+    /// bond direction as given by `directed_interacting_sites`. In-plane
+    /// components of D do not conserve total Sz and are only available in `k`
+    pub fn h_ss_dm_z(nx: Dim, ny: Dim, kx: K, ky: K, nup: u32, l: I, dz: &[f64; 3])
+                     -> CsrMatrix<CComplex<f64>> {
+        let bfuncs = bloch_states(nx, ny, kx, ky, nup);
+        let sites = directed_interacting_sites(nx, ny, l);
+        let dvecs = [[0., 0., dz[0]], [0., 0., dz[1]], [0., 0., dz[2]]];
+        ops::ss_dm(&sites, &dvecs, &bfuncs)
+    }
+
+    pub fn ss_z(nx: Dim, ny: Dim, kx: K, ky: K, nup: u32, l: I)
+                -> CsrMatrix<CComplex<f64>> {
+        let bfuncs = bloch_states(nx, ny, kx, ky, nup);
+        let sites = all_sites(nx, ny, l);
+        ops::ss_z(&sites, &bfuncs)
+    }
+
+    pub fn ss_xy(nx: Dim, ny: Dim, kx: K, ky: K, nup: u32, l: I)
+                 -> CsrMatrix<CComplex<f64>> {
+        let bfuncs = bloch_states(nx, ny, kx, ky, nup);
+        let sites = all_sites(nx, ny, l);
+        ops::ss_xy(&sites, &bfuncs)
+    }
+
+    pub fn h_ss_z_real(nx: Dim, ny: Dim, kx: K, ky: K, nup: u32, l: I)
+                       -> CsrMatrix<f64> {
+        let bfuncs = real_bloch_states(nx, ny, kx, ky, nup);
+        let sites = interacting_sites(nx, ny, l);
+        ops::ss_z(&sites, &bfuncs)
+    }
+
+    pub fn h_ss_xy_real(nx: Dim, ny: Dim, kx: K, ky: K, nup: u32, l: I)
+                        -> CsrMatrix<f64> {
+        let bfuncs = real_bloch_states(nx, ny, kx, ky, nup);
+        let sites = interacting_sites(nx, ny, l);
+        ops::ss_xy(&sites, &bfuncs)
+    }
+
+    pub fn h_ring_real(nx: Dim, ny: Dim, kx: K, ky: K, nup: u32)
+                       -> CsrMatrix<f64> {
+        let bfuncs = real_bloch_states(nx, ny, kx, ky, nup);
+        let sites = rhombus_sites(nx, ny);
+        ops::ring(&sites, &bfuncs)
+    }
+
+    pub fn ss_z_real(nx: Dim, ny: Dim, kx: K, ky: K, nup: u32, l: I)
+                     -> CsrMatrix<f64> {
+        let bfuncs = real_bloch_states(nx, ny, kx, ky, nup);
+        let sites = all_sites(nx, ny, l);
+        ops::ss_z(&sites, &bfuncs)
+    }
+
+    pub fn ss_xy_real(nx: Dim, ny: Dim, kx: K, ky: K, nup: u32, l: I)
+                      -> CsrMatrix<f64> {
+        let bfuncs = real_bloch_states(nx, ny, kx, ky, nup);
+        let sites = all_sites(nx, ny, l);
+        ops::ss_xy(&sites, &bfuncs)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn chi_chi(nx: Dim, ny: Dim, kx: K, ky: K, nup: u32, dx: I, dy: I,
+                   o1: Orientation, o2: Orientation)
+                   -> CsrMatrix<CComplex<f64>> {
+        let bfuncs = bloch_states(nx, ny, kx, ky, nup);
+        let pairs = triangle_pairs(nx, ny, dx, dy, o1, o2);
+        ops::chi_chi(&pairs, &bfuncs)
+    }
+
+    pub fn chi_order(nx: Dim, ny: Dim, kx: K, ky: K, nup: u32, staggered: bool)
+                     -> CsrMatrix<CComplex<f64>> {
+        let bfuncs = bloch_states(nx, ny, kx, ky, nup);
+        let triangles = oriented_triangles(nx, ny);
+        ops::chi_order(&triangles, staggered, &bfuncs)
+    }
+}
+
+/// This module contains functions that work under the assumption that lattice
+/// momentum and the parity of the number of spins pointing up are conserved.
+/// That is the case for S^+S^+ + S^-S^- terms, which change total Sz by 2,
+/// as long as no term changes it by 1.
+pub mod kp {
+    use rayon::prelude::*;
+
+    use blochfunc::{BlochFunc, BlochFuncSet};
+    use common::*;
+    use ops;
+
+    /// Checks the lattice, momentum and parity every function in this module
+    /// takes. The parity is that of nup, 0 for even and 1 for odd
+    pub fn check(nx: Dim, ny: Dim, kx: K, ky: K, parity: u32)
+                 -> Result<(), InputError> {
+        check_full_lattice(nx, ny)?;
+        check_momentum(nx, ny, kx, ky)?;
+        check_parity(parity)
+    }
+
+    pub fn bloch_states(nx: Dim, ny: Dim, kx: K, ky: K, parity: u32)
+                        -> BlochFuncSet {
+        let n = nx * ny;
+        let nstates = 2_usize.pow(n.raw_int());
+        let trans = Translations::new(nx, ny);
+        // translations preserve the number of spins pointing up, so every orbit
+        // lies entirely within one parity
+        let bfuncs = with_thread_pool(|| {
+            (0..nstates).into_par_iter()
+                        .filter(|&dec| dec.count_ones() % 2 == parity)
+                        .map(|dec| BinaryBasis(dec as u64))
+                        .filter(|&dec| is_orbit_lead(dec, &trans))
+                        .filter_map(|dec| bloch_func(dec, &trans, kx, ky))
+                        .collect::<Vec<BlochFunc>>()
+        });
+
+        let mut table = BlochFuncSet::create(nx, ny, bfuncs);
+        table.sort();
+        table
+    }
+
+    pub fn h_ss_z(nx: Dim, ny: Dim, kx: K, ky: K, parity: u32, l: I)
+                  -> CsrMatrix<CComplex<f64>> {
+        let bfuncs = bloch_states(nx, ny, kx, ky, parity);
+        let sites = interacting_sites(nx, ny, l);
+        ops::ss_z(&sites, &bfuncs)
+    }
+
+    pub fn h_ss_xy(nx: Dim, ny: Dim, kx: K, ky: K, parity: u32, l: I)
+                   -> CsrMatrix<CComplex<f64>> {
+        let bfuncs = bloch_states(nx, ny, kx, ky, parity);
+        let sites = interacting_sites(nx, ny, l);
+        ops::ss_xy(&sites, &bfuncs)
+    }
+
+    pub fn h_ss_ppmm(nx: Dim, ny: Dim, kx: K, ky: K, parity: u32, l: I)
+                     -> CsrMatrix<CComplex<f64>> {
+        let bfuncs = bloch_states(nx, ny, kx, ky, parity);
+        let sites = interacting_sites(nx, ny, l);
+        ops::ss_ppmm(&sites, &bfuncs)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use consv::{k, testing::*};
+
+        #[test]
+        fn parity_sectors_test() {
+            // the two parities together make up the momentum sector
+            let (nx, ny, l) = (Dim(4), Dim(2), I(1));
+            for &(kx, ky) in [(K(0), K(0)), (K(1), K(1))].iter() {
+                let mut union = Vec::new();
+                for parity in 0..2 {
+                    union.extend(eigenvalues(vec![
+                        (1., h_ss_xy(nx, ny, kx, ky, parity, l)),
+                        (0.7, h_ss_z(nx, ny, kx, ky, parity, l)),
+                        (0.4, h_ss_ppmm(nx, ny, kx, ky, parity, l)),
+                    ]));
+                }
+                let eigs = eigenvalues(vec![(1., k::h_ss_xy(nx, ny, kx, ky, l)),
+                                            (0.7, k::h_ss_z(nx, ny, kx, ky, l)),
+                                            (0.4, k::h_ss_ppmm(nx, ny, kx, ky, l))]);
+                assert_same_spectrum(union, eigs);
+            }
+        }
+    }
+}
+
+/// This module contains functions that work under the assumption that lattice
+/// momentum is conserved under a subgroup of the lattice translations, e.g. the
+/// translations of the √3×√3 superlattice. The subgroup is given by its
+/// generators t1 and t2 in units of lattice spacings.
+pub mod kr {
+    use fnv::FnvHashMap;
+    use num_complex::Complex;
+
+    use blochfunc::{BlochFunc, BlochFuncSet};
+    use common::*;
+    use ops;
+
+    /// Checks that there is one field for each sublattice of the translations
+    /// generated by t1 and t2
+    pub fn check_fields(nx: Dim, ny: Dim, t1: (I, I), t2: (I, I), nfields: usize)
+                        -> Result<(), InputError> {
+        // the translations act freely so every sublattice has as many sites as
+        // there are translations
+        let group = translation_subgroup(nx, ny, t1, t2);
+        let expected = (nx * ny).raw_int() as usize / group.len();
+        if nfields != expected {
+            return Err(InputError::FieldCount { expected, found: nfields });
+        }
+        Ok(())
+    }
+
+    fn bloch_states(nx: Dim, ny: Dim, t1: (I, I), t2: (I, I), kx: K, ky: K)
+                    -> BlochFuncSet {
+        let n = nx * ny;
+        let group = translation_subgroup(nx, ny, t1, t2);
+        let trans = Translations::new(nx, ny);
+        let mut sieve = vec![true; 2_usize.pow(n.raw_int())];
+        let mut bfuncs: Vec<BlochFunc> = Vec::new();
+        let phase = |x: u32, y: u32| {
+            let r = 1.;
+            let j = (ny.raw_int() - y) % ny.raw_int();
+            let ang1 = 2. * PI * (x * kx.raw_int()) as f64 / nx.raw_int() as f64;
+            let ang2 = 2. * PI * (j * ky.raw_int()) as f64 / ny.raw_int() as f64;
+            Complex::from_polar(&r, &(ang1 + ang2))
+        };
+
+        for dec in 0..2_usize.pow(n.raw_int()) {
+            if sieve[dec] {
+                // only the translations in the subgroup are used to build up
+                // the Bloch functions. Otherwise identical to `k::bloch_states`
+                let mut decs: FnvHashMap<BinaryBasis, Complex<f64>> =
+                    FnvHashMap::default();
+                let lead = BinaryBasis(dec as u64);
+                for &(x, y) in group.iter() {
+                    let new_dec = trans.translate(lead, x, y);
+                    sieve[new_dec.raw_int() as usize] = false;
+                    let new_p = match decs.get(&new_dec) {
+                        Some(&p) => p + phase(x, y),
+                        None => phase(x, y)
+                    };
+                    decs.insert(new_dec, new_p);
+                }
+
+                let norm = decs.values()
+                               .map(|&x| x.norm_sqr())
+                               .sum::<f64>()
+                               .sqrt();
+
+                if norm > 1e-8 {
+                    bfuncs.push(BlochFunc { lead, decs, norm });
+                }
+            }
+        }
+
+        let mut table = BlochFuncSet::create(nx, ny, bfuncs);
+        table.sort();
+        table
+    }
+
+    pub fn h_ss_z(nx: Dim, ny: Dim, t1: (I, I), t2: (I, I), kx: K, ky: K, l: I)
+                  -> CsrMatrix<CComplex<f64>> {
+        let bfuncs = bloch_states(nx, ny, t1, t2, kx, ky);
+        let sites = interacting_sites(nx, ny, l);
+        ops::ss_z(&sites, &bfuncs)
+    }
+
+    pub fn h_ss_xy(nx: Dim, ny: Dim, t1: (I, I), t2: (I, I), kx: K, ky: K, l: I)
+                   -> CsrMatrix<CComplex<f64>> {
+        let bfuncs = bloch_states(nx, ny, t1, t2, kx, ky);
+        let sites = interacting_sites(nx, ny, l);
+        ops::ss_xy(&sites, &bfuncs)
+    }
+
+    pub fn h_ss_ppmm(nx: Dim, ny: Dim, t1: (I, I), t2: (I, I), kx: K, ky: K, l: I)
+                     -> CsrMatrix<CComplex<f64>> {
+        let bfuncs = bloch_states(nx, ny, t1, t2, kx, ky);
+        let sites = interacting_sites(nx, ny, l);
+        ops::ss_ppmm(&sites, &bfuncs)
+    }
+
+    pub fn h_ss_pmz(nx: Dim, ny: Dim, t1: (I, I), t2: (I, I), kx: K, ky: K, l: I)
+                    -> CsrMatrix<CComplex<f64>> {
+        let bfuncs = bloch_states(nx, ny, t1, t2, kx, ky);
+        let sites = interacting_sites(nx, ny, l);
+        ops::ss_pmz(&sites, &bfuncs)
+    }
+
+    pub fn h_sss_chi(nx: Dim, ny: Dim, t1: (I, I), t2: (I, I), kx: K, ky: K)
+                     -> CsrMatrix<CComplex<f64>> {
+        let bfuncs = bloch_states(nx, ny, t1, t2, kx, ky);
+        let sites = triangular_vert_sites(nx, ny);
+        ops::sss_chi(&sites, &bfuncs)
+    }
+
+    /// Longitudinal field with one value of h per sublattice. The sublattices
+    /// are labeled as in `common::sublattices`
+    pub fn h_s_z(nx: Dim, ny: Dim, t1: (I, I), t2: (I, I), kx: K, ky: K,
+                 fields: &[f64])
+                 -> CsrMatrix<CComplex<f64>> {
+        let bfuncs = bloch_states(nx, ny, t1, t2, kx, ky);
+        let group = translation_subgroup(nx, ny, t1, t2);
+        let labels = sublattices(nx, ny, &group);
+        ops::s_z(&sublattice_fields(&labels, fields), &bfuncs)
+    }
+
+    /// Transverse field with one (hx, hy) per sublattice, e.g. a pinning
+    /// field for 120° order. The sublattices are labeled as in
+    /// `common::sublattices`
+    pub fn h_s_xy(nx: Dim, ny: Dim, t1: (I, I), t2: (I, I), kx: K, ky: K,
+                  fields: &[(f64, f64)])
+                  -> CsrMatrix<CComplex<f64>> {
+        let bfuncs = bloch_states(nx, ny, t1, t2, kx, ky);
+        let group = translation_subgroup(nx, ny, t1, t2);
+        let labels = sublattices(nx, ny, &group);
+        ops::s_xy(&sublattice_fields(&labels, fields), &bfuncs)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn bloch_states_test() {
+            // the full translation group reproduces k::bloch_states
+            let nx = Dim(4);
+            let ny = Dim(4);
+            let t1 = (I(1), I(0));
+            let t2 = (I(0), I(1));
+            let bfuncs = bloch_states(nx, ny, t1, t2, K(1), K(3));
+            assert_eq!(bfuncs.nonzero, 4080);
+        }
+
+        #[test]
+        fn sqrt3_bloch_states_test() {
+            // every momentum of the √3×√3 translations together span the
+            // entire Hilbert space
+            let nx = Dim(3);
+            let ny = Dim(3);
+            let t1 = (I(1), I(1));
+            let t2 = (I(-1), I(2));
+            let group = translation_subgroup(nx, ny, t1, t2);
+            let dims = reduced_momenta(nx, ny, &group)
+                .into_iter()
+                .map(|(kx, ky)| bloch_states(nx, ny, t1, t2, kx, ky).nonzero)
+                .sum::<u32>();
+            assert_eq!(dims, 512);
+        }
+    }
+}
+
+/// This module contains functions that work under the assumption that total Sz
+/// and lattice momentum under a subgroup of the lattice translations are
+/// conserved. See `kr` for how the subgroup is specified.
+pub mod ksr {
     use fnv::FnvHashMap;
     use num_complex::Complex;
 
@@ -143,66 +750,50 @@ pub mod ks {
     use common::*;
     use ops;
 
-    fn bloch_states<'a>(nx: Dim, ny: Dim, kx: K, ky: K, nup: u32) -> BlochFuncSet {
+    fn bloch_states(nx: Dim, ny: Dim, t1: (I, I), t2: (I, I), kx: K, ky: K,
+                    nup: u32)
+                    -> BlochFuncSet {
         let n = nx * ny;
+        let group = translation_subgroup(nx, ny, t1, t2);
+        let trans = Translations::new(nx, ny);
 
         let sz_basis_states = sz_basis(n, nup);
         let mut szdec_to_ind: FnvHashMap<BinaryBasis, usize> = FnvHashMap::default();
-        let mut ind_to_szdec: FnvHashMap<usize, BinaryBasis> = FnvHashMap::default();
         for (i, &bs) in sz_basis_states.iter().enumerate() {
-            ind_to_szdec.insert(i, bs);
             szdec_to_ind.insert(bs, i);
         }
 
         let mut sieve = vec![true; sz_basis_states.len()];
         let mut bfuncs: Vec<BlochFunc> = Vec::new();
-        let phase = |i, j| {
+        let phase = |x: u32, y: u32| {
             let r = 1.;
-            let ang1 = 2. * PI * (i * kx.raw_int()) as f64 / nx.raw_int() as f64;
+            let j = (ny.raw_int() - y) % ny.raw_int();
+            let ang1 = 2. * PI * (x * kx.raw_int()) as f64 / nx.raw_int() as f64;
             let ang2 = 2. * PI * (j * ky.raw_int()) as f64 / ny.raw_int() as f64;
             Complex::from_polar(&r, &(ang1 + ang2))
         };
 
-        for ind in 0..sieve.len() {
+        for (ind, &lead) in sz_basis_states.iter().enumerate() {
             if sieve[ind] {
-                // if the corresponding entry of dec in "sieve" is not false,
-                // we find all translations of dec and put them in a BlochFunc
-                // then mark all corresponding entries in "sieve" as false.
-
-                // "decs" is a hashtable that holds vectors whose entries
-                // correspond to Bloch function constituent configurations which
-                // are mapped to single decimals that represent the leading states.
                 let mut decs: FnvHashMap<BinaryBasis, Complex<f64>> =
                     FnvHashMap::default();
-                // "new_dec" represents the configuration we are currently iterating
-                // over.
-                let dec = *ind_to_szdec.get(&ind).unwrap();
-                let mut new_dec = dec;
-                let mut new_ind = ind;
-                for j in 0..ny.raw_int() {
-                    for i in 0..nx.raw_int() {
-                        sieve[new_ind as usize] = false;
-                        let new_p = match decs.get(&new_dec) {
-                            Some(&p) => p + phase(i, j),
-                            None => phase(i, j)
-                        };
-                        decs.insert(new_dec, new_p);
-                        new_dec = translate_x(new_dec, nx, ny);
-                        new_ind = *szdec_to_ind.get(&new_dec).unwrap() as usize;
-                    }
-                    new_dec = translate_y(new_dec, nx, ny);
+                for &(x, y) in group.iter() {
+                    let new_dec = trans.translate(lead, x, y);
+                    sieve[*szdec_to_ind.get(&new_dec).unwrap()] = false;
+                    let new_p = match decs.get(&new_dec) {
+                        Some(&p) => p + phase(x, y),
+                        None => phase(x, y)
+                    };
+                    decs.insert(new_dec, new_p);
                 }
 
-                let lead = dec;
                 let norm = decs.values()
-                               .into_iter()
                                .map(|&x| x.norm_sqr())
                                .sum::<f64>()
                                .sqrt();
 
                 if norm > 1e-8 {
-                    let mut bfunc = BlochFunc { lead, decs, norm };
-                    bfuncs.push(bfunc);
+                    bfuncs.push(BlochFunc { lead, decs, norm });
                 }
             }
         }
@@ -212,38 +803,567 @@ pub mod ks {
         table
     }
 
-    pub fn h_ss_z(nx: Dim, ny: Dim, kx: K, ky: K, nup: u32, l: I)
-                  -> CoordMatrix<CComplex<f64>> {
-        let bfuncs = bloch_states(nx, ny, kx, ky, nup);
+    #[allow(clippy::too_many_arguments)]
+    pub fn h_ss_z(nx: Dim, ny: Dim, t1: (I, I), t2: (I, I), kx: K, ky: K, nup: u32,
+                  l: I)
+                  -> CsrMatrix<CComplex<f64>> {
+        let bfuncs = bloch_states(nx, ny, t1, t2, kx, ky, nup);
         let sites = interacting_sites(nx, ny, l);
         ops::ss_z(&sites, &bfuncs)
     }
 
-    pub fn h_ss_xy(nx: Dim, ny: Dim, kx: K, ky: K, nup: u32, l: I)
-                   -> CoordMatrix<CComplex<f64>> {
-        let bfuncs = bloch_states(nx, ny, kx, ky, nup);
+    #[allow(clippy::too_many_arguments)]
+    pub fn h_ss_xy(nx: Dim, ny: Dim, t1: (I, I), t2: (I, I), kx: K, ky: K, nup: u32,
+                   l: I)
+                   -> CsrMatrix<CComplex<f64>> {
+        let bfuncs = bloch_states(nx, ny, t1, t2, kx, ky, nup);
         let sites = interacting_sites(nx, ny, l);
         ops::ss_xy(&sites, &bfuncs)
     }
 
-    pub fn h_sss_chi(nx: Dim, ny: Dim, kx: K, ky: K, nup: u32)
-                     -> CoordMatrix<CComplex<f64>> {
-        let bfuncs = bloch_states(nx, ny, kx, ky, nup);
+    pub fn h_sss_chi(nx: Dim, ny: Dim, t1: (I, I), t2: (I, I), kx: K, ky: K,
+                     nup: u32)
+                     -> CsrMatrix<CComplex<f64>> {
+        let bfuncs = bloch_states(nx, ny, t1, t2, kx, ky, nup);
         let sites = triangular_vert_sites(nx, ny);
         ops::sss_chi(&sites, &bfuncs)
     }
 
-    pub fn ss_z(nx: Dim, ny: Dim, kx: K, ky: K, nup: u32, l: I)
-                -> CoordMatrix<CComplex<f64>> {
-        let bfuncs = bloch_states(nx, ny, kx, ky, nup);
-        let sites = all_sites(nx, ny, l);
-        ops::ss_z(&sites, &bfuncs)
+    /// Longitudinal field with one value of h per sublattice. The sublattices
+    /// are labeled as in `common::sublattices`
+    #[allow(clippy::too_many_arguments)]
+    pub fn h_s_z(nx: Dim, ny: Dim, t1: (I, I), t2: (I, I), kx: K, ky: K, nup: u32,
+                 fields: &[f64])
+                 -> CsrMatrix<CComplex<f64>> {
+        let bfuncs = bloch_states(nx, ny, t1, t2, kx, ky, nup);
+        let group = translation_subgroup(nx, ny, t1, t2);
+        let labels = sublattices(nx, ny, &group);
+        ops::s_z(&sublattice_fields(&labels, fields), &bfuncs)
     }
+}
 
-    pub fn ss_xy(nx: Dim, ny: Dim, kx: K, ky: K, nup: u32, l: I)
-                 -> CoordMatrix<CComplex<f64>> {
-        let bfuncs = bloch_states(nx, ny, kx, ky, nup);
-        let sites = all_sites(nx, ny, l);
-        ops::ss_xy(&sites, &bfuncs)
+/// This module builds operators in the plain basis of all 2^N configurations,
+/// where the configuration dec is basis state number dec. Nothing is
+/// symmetrized, which makes it slow and only fit for small clusters, but also a
+/// simple reference to check the other modules against.
+pub mod none {
+    use fnv::FnvHashMap;
+    use num_complex::Complex;
+    use rayon::prelude::*;
+
+    use common::*;
+    use ops::{self, OpString, SpinOp};
+
+    /// Checks the lattice, which may have at most 31 sites for the basis to be
+    /// indexed by a `CsrMatrix`
+    pub fn check(nx: Dim, ny: Dim) -> Result<(), InputError> {
+        check_full_lattice(nx, ny)
+    }
+
+    pub fn dim(nx: Dim, ny: Dim) -> u32 { 1 << (nx * ny).raw_int() }
+
+    /// The matrix with <j|A|i> = Σ c over the (j, c) that f gives for i
+    fn build<F>(nx: Dim, ny: Dim, f: F) -> CsrMatrix<CComplex<f64>>
+        where F: Fn(BinaryBasis) -> Vec<(BinaryBasis, Complex<f64>)> + Sync
+    {
+        let dim = dim(nx, ny);
+        let columns = with_thread_pool(|| {
+            (0..dim).into_par_iter()
+                    .map(|i| {
+                        let mut column = FnvHashMap::default();
+                        for (j, c) in f(BinaryBasis(i as u64)) {
+                            *column.entry(j.raw_int() as u32)
+                                   .or_insert(Complex::new(0., 0.)) += c;
+                        }
+                        column.into_iter().collect::<Vec<_>>()
+                    })
+                    .collect::<Vec<_>>()
+        });
+        let mut data = Vec::new();
+        let mut rows = Vec::new();
+        let mut cols = Vec::new();
+        for (i, column) in columns.into_iter().enumerate() {
+            for (j, c) in column.into_iter() {
+                rows.push(j);
+                cols.push(i as u32);
+                data.push(CComplex::from_phase(c));
+            }
+        }
+        CsrMatrix::from_triplets(data, rows, cols, dim, dim)
+    }
+
+    fn op_strings(nx: Dim, ny: Dim, terms: &[OpString])
+                  -> CsrMatrix<CComplex<f64>> {
+        build(nx, ny, |dec| terms.iter().filter_map(|t| t.act_on(dec)).collect())
+    }
+
+    /// The strings of c S^a_i S^b_j on every bond of range l
+    fn bond_strings(nx: Dim, ny: Dim, l: I, c: Complex<f64>, a: SpinOp, b: SpinOp)
+                    -> Vec<OpString> {
+        let (site1, site2) = interacting_sites(nx, ny, l);
+        site1.into_iter()
+             .zip(site2)
+             .map(|(s1, s2)| OpString::new(c, vec![(s1, a), (s2, b)]))
+             .collect()
+    }
+
+    pub fn h_ss_z(nx: Dim, ny: Dim, l: I) -> CsrMatrix<CComplex<f64>> {
+        let one = Complex::new(1., 0.);
+        op_strings(nx, ny, &bond_strings(nx, ny, l, one, SpinOp::Z, SpinOp::Z))
+    }
+
+    /// Σ (S^x_i S^x_j + S^y_i S^y_j) = 1/2 Σ (S^+_i S^-_j + S^-_i S^+_j)
+    pub fn h_ss_xy(nx: Dim, ny: Dim, l: I) -> CsrMatrix<CComplex<f64>> {
+        let half = Complex::new(0.5, 0.);
+        let (p, m) = (SpinOp::Plus, SpinOp::Minus);
+        let mut terms = bond_strings(nx, ny, l, half, p, m);
+        terms.extend(bond_strings(nx, ny, l, half, m, p));
+        op_strings(nx, ny, &terms)
+    }
+
+    /// Σ (γ_ij S^+_i S^+_j + γ_ij* S^-_i S^-_j)
+    pub fn h_ss_ppmm(nx: Dim, ny: Dim, l: I) -> CsrMatrix<CComplex<f64>> {
+        let (site1, site2) = interacting_sites(nx, ny, l);
+        let (p, m) = (SpinOp::Plus, SpinOp::Minus);
+        let mut terms = Vec::with_capacity(2 * site1.len());
+        for (&s1, &s2) in site1.iter().zip(site2.iter()) {
+            let g = gamma(nx, ny, s1, s2);
+            terms.push(OpString::new(g, vec![(s1, p), (s2, p)]));
+            terms.push(OpString::new(g.conj(), vec![(s1, m), (s2, m)]));
+        }
+        op_strings(nx, ny, &terms)
+    }
+
+    /// i Σ S^z_i (γ_ij* S^-_j - γ_ij S^+_j) with both orientations of every
+    /// bond
+    pub fn h_ss_pmz(nx: Dim, ny: Dim, l: I) -> CsrMatrix<CComplex<f64>> {
+        let (site1, site2) = interacting_sites(nx, ny, l);
+        let (p, m, z) = (SpinOp::Plus, SpinOp::Minus, SpinOp::Z);
+        let i = Complex::new(0., 1.);
+        let mut terms = Vec::with_capacity(4 * site1.len());
+        for (&s1, &s2) in site1.iter().zip(site2.iter()) {
+            for &(a, b) in [(s1, s2), (s2, s1)].iter() {
+                let g = gamma(nx, ny, a, b);
+                terms.push(OpString::new(i * g.conj(), vec![(a, z), (b, m)]));
+                terms.push(OpString::new(-i * g, vec![(a, z), (b, p)]));
+            }
+        }
+        op_strings(nx, ny, &terms)
+    }
+
+    pub fn h_sss_chi(nx: Dim, ny: Dim) -> CsrMatrix<CComplex<f64>> {
+        let (site1, site2, site3) = triangular_vert_sites(nx, ny);
+        let mut terms = Vec::with_capacity(6 * site1.len());
+        for ((&s1, &s2), &s3) in site1.iter().zip(site2.iter()).zip(site3.iter()) {
+            terms.extend(ops::chirality_strings(s1, s2, s3));
+        }
+        op_strings(nx, ny, &terms)
+    }
+
+    pub fn h_ring(nx: Dim, ny: Dim) -> CsrMatrix<CComplex<f64>> {
+        let (site1, site2, site3, site4) = rhombus_sites(nx, ny);
+        let one = Complex::new(1., 0.);
+        build(nx, ny, |dec| {
+            let mut out = Vec::with_capacity(2 * site1.len());
+            for i in 0..site1.len() {
+                let (s1, s2, s3, s4) = (site1[i], site2[i], site3[i], site4[i]);
+                out.push((ops::ring_permute(dec, &[s1, s2, s3, s4]), one));
+                out.push((ops::ring_permute(dec, &[s1, s4, s3, s2]), one));
+            }
+            out
+        })
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn h_ss_xy_test() {
+            let nx = Dim(3);
+            let ny = Dim(3);
+            let h = h_ss_xy(nx, ny, I(1));
+            assert_eq!(h.nrows, 512);
+            let (data, indices, indptr) = h.into_parts();
+            // a single up spin hops to each of its 6 neighbors
+            let row = 1;
+            let (start, end) = (indptr[row] as usize, indptr[row + 1] as usize);
+            assert_eq!(end - start, 6);
+            for e in start..end {
+                assert!(indices[e].count_ones() == 1);
+                assert_eq!(data[e], CComplex { re: 0.5, im: 0. });
+            }
+        }
+    }
+}
+
+/// This module contains functions that only assume total Sz to be conserved,
+/// for Hamiltonians that break lattice translations through random bonds,
+/// impurities, open boundaries or site-dependent fields. The basis holds every
+/// configuration with nup spins pointing up in increasing order, so a
+/// configuration is located by its rank rather than looked up in a hash table.
+///
+/// Besides the translationally invariant operators of `ks`, the operators come
+/// in variants that take their bonds, triangles or fields one by one, each with
+/// its own coupling. Sites are then given by their index on the lattice.
+pub mod s {
+    use fnv::FnvHashMap;
+    use num_complex::Complex;
+    use rayon::prelude::*;
+
+    use common::*;
+    use ops::{self, OpString, SpinOp};
+
+    type Bond = (BinaryBasis, BinaryBasis, f64);
+    type Triangle = (BinaryBasis, BinaryBasis, BinaryBasis, f64);
+
+    /// Checks the lattice and number of spins pointing up every function in
+    /// this module takes
+    pub fn check(nx: Dim, ny: Dim, nup: u32) -> Result<(), InputError> {
+        check_lattice(nx, ny)?;
+        check_nup(nx, ny, nup)
+    }
+
+    /// Checks that the sites the bonds or triangles passed to this module
+    /// refer to lie on the lattice
+    pub fn check_sites(nx: Dim, ny: Dim, sites: &[u32]) -> Result<(), InputError> {
+        ::common::check_sites(nx, ny, sites.iter().cloned())
+    }
+
+    /// Checks that there is one field for every site
+    pub fn check_fields(nx: Dim, ny: Dim, nfields: usize) -> Result<(), InputError> {
+        let expected = (nx * ny).raw_int() as usize;
+        if nfields != expected {
+            return Err(InputError::FieldCount { expected, found: nfields });
+        }
+        Ok(())
+    }
+
+    /// The configurations with a fixed number of spins pointing up
+    pub struct SzBasis {
+        /// The configurations in increasing order
+        pub states: Vec<BinaryBasis>,
+        // binom[m][r] is m choose r for r up to nup
+        binom:      Vec<Vec<u64>>
+    }
+
+    impl SzBasis {
+        pub fn new(nx: Dim, ny: Dim, nup: u32) -> SzBasis {
+            let n = (nx * ny).raw_int() as usize;
+            let mut states = sz_basis(nx * ny, nup);
+            states.sort_by_key(|dec| dec.raw_int());
+            let mut binom = vec![vec![0_u64; nup as usize + 1]; n + 1];
+            binom[0][0] = 1;
+            for m in 1..n + 1 {
+                binom[m][0] = 1;
+                for r in 1..nup as usize + 1 {
+                    binom[m][r] = binom[m - 1][r - 1] + binom[m - 1][r];
+                }
+            }
+            SzBasis { states, binom }
+        }
+
+        pub fn dim(&self) -> u32 { self.states.len() as u32 }
+
+        /// The index of dec in `states`. Ordering the configurations by value
+        /// orders the sets of up spins colexicographically, and the rank of a
+        /// set in that order is Σ_i C(p_i, i) over the positions p_1 < p_2 <
+        /// ... of its up spins. dec must have the right number of up spins.
+        pub fn rank(&self, dec: BinaryBasis) -> u32 {
+            let mut d = dec.raw_int();
+            let mut rank = 0;
+            let mut i = 1;
+            while d != 0 {
+                rank += self.binom[d.trailing_zeros() as usize][i];
+                d &= d - 1;
+                i += 1;
+            }
+            rank as u32
+        }
+    }
+
+    /// The matrix with <j|A|i> = Σ c over the (j, c) that f gives for state i
+    fn build<F>(basis: &SzBasis, f: F) -> CsrMatrix<CComplex<f64>>
+        where F: Fn(BinaryBasis) -> Vec<(BinaryBasis, Complex<f64>)> + Sync
+    {
+        let columns = with_thread_pool(|| {
+            basis.states
+                 .par_iter()
+                 .map(|&dec| {
+                     let mut column = FnvHashMap::default();
+                     for (new_dec, c) in f(dec) {
+                         *column.entry(basis.rank(new_dec))
+                                .or_insert(Complex::new(0., 0.)) += c;
+                     }
+                     column.into_iter().collect::<Vec<_>>()
+                 })
+                 .collect::<Vec<_>>()
+        });
+        let mut data = Vec::new();
+        let mut rows = Vec::new();
+        let mut cols = Vec::new();
+        for (i, column) in columns.into_iter().enumerate() {
+            for (j, c) in column.into_iter() {
+                rows.push(j);
+                cols.push(i as u32);
+                data.push(CComplex::from_phase(c));
+            }
+        }
+        CsrMatrix::from_triplets(data, rows, cols, basis.dim(), basis.dim())
+    }
+
+    fn op_strings(nx: Dim, ny: Dim, nup: u32, terms: &[OpString])
+                  -> CsrMatrix<CComplex<f64>> {
+        let basis = SzBasis::new(nx, ny, nup);
+        build(&basis, |dec| terms.iter().filter_map(|t| t.act_on(dec)).collect())
+    }
+
+    fn site(s: u32) -> BinaryBasis { POW2[s as usize] }
+
+    /// The bonds between the pairs of sites, all with coupling 1
+    fn uniform_bonds(sites: (Vec<BinaryBasis>, Vec<BinaryBasis>)) -> Vec<Bond> {
+        let (site1, site2) = sites;
+        site1.into_iter().zip(site2).map(|(s1, s2)| (s1, s2, 1.)).collect()
+    }
+
+    fn explicit_bonds(bonds: &[(u32, u32, f64)]) -> Vec<Bond> {
+        bonds.iter().map(|&(s1, s2, j)| (site(s1), site(s2), j)).collect()
+    }
+
+    fn bond_strings<F>(bonds: &[Bond], f: F) -> Vec<OpString>
+        where F: Fn(BinaryBasis, BinaryBasis, f64) -> Vec<OpString>
+    {
+        bonds.iter().flat_map(|&(s1, s2, j)| f(s1, s2, j)).collect()
+    }
+
+    fn dm_z_strings(si: BinaryBasis, sj: BinaryBasis, dz: f64) -> Vec<OpString> {
+        ops::dm_strings(si, sj, &[0., 0., dz])
+    }
+
+    fn triangle_strings(triangles: &[Triangle]) -> Vec<OpString> {
+        let mut terms = Vec::with_capacity(6 * triangles.len());
+        for &(s1, s2, s3, chi) in triangles.iter() {
+            for mut t in ops::chirality_strings(s1, s2, s3).into_iter() {
+                t.coeff *= chi;
+                terms.push(t);
+            }
+        }
+        terms
+    }
+
+    pub fn h_ss_z(nx: Dim, ny: Dim, nup: u32, l: I) -> CsrMatrix<CComplex<f64>> {
+        let bonds = uniform_bonds(interacting_sites(nx, ny, l));
+        op_strings(nx, ny, nup, &bond_strings(&bonds, ops::ss_z_strings))
+    }
+
+    pub fn h_ss_xy(nx: Dim, ny: Dim, nup: u32, l: I) -> CsrMatrix<CComplex<f64>> {
+        let bonds = uniform_bonds(interacting_sites(nx, ny, l));
+        op_strings(nx, ny, nup, &bond_strings(&bonds, ops::ss_xy_strings))
+    }
+
+    pub fn h_sss_chi(nx: Dim, ny: Dim, nup: u32) -> CsrMatrix<CComplex<f64>> {
+        let (site1, site2, site3) = triangular_vert_sites(nx, ny);
+        let triangles = site1.into_iter()
+                             .zip(site2)
+                             .zip(site3)
+                             .map(|((s1, s2), s3)| (s1, s2, s3, 1.))
+                             .collect::<Vec<_>>();
+        op_strings(nx, ny, nup, &triangle_strings(&triangles))
+    }
+
+    pub fn h_ring(nx: Dim, ny: Dim, nup: u32) -> CsrMatrix<CComplex<f64>> {
+        let (site1, site2, site3, site4) = rhombus_sites(nx, ny);
+        let basis = SzBasis::new(nx, ny, nup);
+        let one = Complex::new(1., 0.);
+        build(&basis, |dec| {
+            let mut out = Vec::with_capacity(2 * site1.len());
+            for i in 0..site1.len() {
+                let (s1, s2, s3, s4) = (site1[i], site2[i], site3[i], site4[i]);
+                out.push((ops::ring_permute(dec, &[s1, s2, s3, s4]), one));
+                out.push((ops::ring_permute(dec, &[s1, s4, s3, s2]), one));
+            }
+            out
+        })
+    }
+
+    /// Dzyaloshinskii-Moriya interaction with an out-of-plane D_z for each
+    /// bond direction as given by `directed_interacting_sites`
+    pub fn h_ss_dm_z(nx: Dim, ny: Dim, nup: u32, l: I, dz: &[f64; 3])
+                     -> CsrMatrix<CComplex<f64>> {
+        let (site1, site2, dirs) = directed_interacting_sites(nx, ny, l);
+        let bonds = site1.into_iter()
+                         .zip(site2)
+                         .zip(dirs)
+                         .map(|((s1, s2), d)| (s1, s2, dz[d]))
+                         .collect::<Vec<_>>();
+        op_strings(nx, ny, nup, &bond_strings(&bonds, dm_z_strings))
+    }
+
+    pub fn ss_z(nx: Dim, ny: Dim, nup: u32, l: I) -> CsrMatrix<CComplex<f64>> {
+        let bonds = uniform_bonds(all_sites(nx, ny, l));
+        op_strings(nx, ny, nup, &bond_strings(&bonds, ops::ss_z_strings))
+    }
+
+    pub fn ss_xy(nx: Dim, ny: Dim, nup: u32, l: I) -> CsrMatrix<CComplex<f64>> {
+        let bonds = uniform_bonds(all_sites(nx, ny, l));
+        op_strings(nx, ny, nup, &bond_strings(&bonds, ops::ss_xy_strings))
+    }
+
+    /// See `ops::chi_chi`
+    pub fn chi_chi(nx: Dim, ny: Dim, nup: u32, dx: I, dy: I, o1: Orientation,
+                   o2: Orientation)
+                   -> CsrMatrix<CComplex<f64>> {
+        let (tri1, tri2) = triangle_pairs(nx, ny, dx, dy, o1, o2);
+        let mut terms = Vec::new();
+        for (&(s1, s2, s3), &(s4, s5, s6)) in tri1.iter().zip(tri2.iter()) {
+            let chi2 = ops::chirality_strings(s4, s5, s6);
+            for a in ops::chirality_strings(s1, s2, s3).iter() {
+                terms.extend(chi2.iter().map(|b| a.product(b)));
+            }
+        }
+        op_strings(nx, ny, nup, &terms)
+    }
+
+    /// See `ops::chi_order`
+    pub fn chi_order(nx: Dim, ny: Dim, nup: u32, staggered: bool)
+                     -> CsrMatrix<CComplex<f64>> {
+        let (up, down) = oriented_triangles(nx, ny);
+        let sign = if staggered { -1. } else { 1. };
+        let mut triangles = Vec::with_capacity(up.len() + down.len());
+        triangles.extend(up.into_iter().map(|(s1, s2, s3)| (s1, s2, s3, 1.)));
+        triangles.extend(down.into_iter().map(|(s1, s2, s3)| (s1, s2, s3, sign)));
+        op_strings(nx, ny, nup, &triangle_strings(&triangles))
+    }
+
+    /// Σ J_ij S^z_i S^z_j over the bonds (i, j, J_ij)
+    pub fn bonds_ss_z(nx: Dim, ny: Dim, nup: u32, bonds: &[(u32, u32, f64)])
+                      -> CsrMatrix<CComplex<f64>> {
+        let bonds = explicit_bonds(bonds);
+        op_strings(nx, ny, nup, &bond_strings(&bonds, ops::ss_z_strings))
+    }
+
+    /// Σ J_ij (S^x_i S^x_j + S^y_i S^y_j) over the bonds (i, j, J_ij)
+    pub fn bonds_ss_xy(nx: Dim, ny: Dim, nup: u32, bonds: &[(u32, u32, f64)])
+                       -> CsrMatrix<CComplex<f64>> {
+        let bonds = explicit_bonds(bonds);
+        op_strings(nx, ny, nup, &bond_strings(&bonds, ops::ss_xy_strings))
+    }
+
+    /// Σ D^z_ij (S_i × S_j)^z over the bonds (i, j, D^z_ij)
+    pub fn bonds_ss_dm_z(nx: Dim, ny: Dim, nup: u32, bonds: &[(u32, u32, f64)])
+                         -> CsrMatrix<CComplex<f64>> {
+        let bonds = explicit_bonds(bonds);
+        op_strings(nx, ny, nup, &bond_strings(&bonds, dm_z_strings))
+    }
+
+    /// Σ χ_ijk S_i · (S_j × S_k) over the triangles (i, j, k, χ_ijk)
+    pub fn triangles_sss_chi(nx: Dim, ny: Dim, nup: u32,
+                             triangles: &[(u32, u32, u32, f64)])
+                             -> CsrMatrix<CComplex<f64>> {
+        let triangles = triangles.iter()
+                                 .map(|&(s1, s2, s3, chi)| {
+                                     (site(s1), site(s2), site(s3), chi)
+                                 })
+                                 .collect::<Vec<_>>();
+        op_strings(nx, ny, nup, &triangle_strings(&triangles))
+    }
+
+    /// Σ_r h_r S^z_r with one field h_r for every site r
+    pub fn h_s_z(nx: Dim, ny: Dim, nup: u32, fields: &[f64])
+                 -> CsrMatrix<CComplex<f64>> {
+        let terms = fields.iter()
+                          .enumerate()
+                          .map(|(r, &h)| {
+                              OpString::new(Complex::new(h, 0.),
+                                            vec![(site(r as u32), SpinOp::Z)])
+                          })
+                          .collect::<Vec<_>>();
+        op_strings(nx, ny, nup, &terms)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use consv::{ks, testing::*};
+
+        #[test]
+        fn rank_test() {
+            for nup in 0..7 {
+                let basis = SzBasis::new(Dim(3), Dim(2), nup);
+                assert_eq!(basis.dim() as u64, choose(Dim(6), nup));
+                for (i, &dec) in basis.states.iter().enumerate() {
+                    assert_eq!(basis.rank(dec), i as u32);
+                }
+            }
+        }
+
+        #[test]
+        fn open_chain_test() {
+            // the Heisenberg chain of 4 sites with open ends has the ground
+            // state energy -(3 + 2√3) / 4 at total Sz = 0
+            let (nx, ny) = (Dim(4), Dim(1));
+            let bonds = [(0, 1, 1.), (1, 2, 1.), (2, 3, 1.)];
+            let eigs = eigenvalues(vec![(1., bonds_ss_xy(nx, ny, 2, &bonds)),
+                                        (1., bonds_ss_z(nx, ny, 2, &bonds))]);
+            assert_eq!(eigs.len(), 6);
+            assert!((eigs[0] + (3. + 2. * 3_f64.sqrt()) / 4.).abs() < 1e-12);
+
+            // a uniform field only shifts every level by h Sz
+            let eigs = eigenvalues(vec![(1., h_s_z(nx, ny, 3, &[0.5; 4]))]);
+            assert!(eigs.iter().all(|e| (e - 0.5).abs() < 1e-12));
+        }
+
+        #[test]
+        fn momentum_sectors_test() {
+            // with translationally invariant couplings the spectrum is made up
+            // of those of the momentum sectors
+            let (nx, ny, nup) = (Dim(3), Dim(3), 4);
+            let (l1, l2, dz) = (I(1), I(2), [0.3, -0.1, 0.2]);
+            let eigs = eigenvalues(vec![(1., h_ss_xy(nx, ny, nup, l1)),
+                                        (0.7, h_ss_z(nx, ny, nup, l1)),
+                                        (0.2, h_ss_xy(nx, ny, nup, l2)),
+                                        (0.3, h_sss_chi(nx, ny, nup)),
+                                        (0.1, h_ring(nx, ny, nup)),
+                                        (1., h_ss_dm_z(nx, ny, nup, l1, &dz))]);
+            let mut union = Vec::new();
+            for kx in 0..3 {
+                for ky in 0..3 {
+                    let (kx, ky) = (K(kx), K(ky));
+                    union.extend(eigenvalues(vec![
+                        (1., ks::h_ss_xy(nx, ny, kx, ky, nup, l1)),
+                        (0.7, ks::h_ss_z(nx, ny, kx, ky, nup, l1)),
+                        (0.2, ks::h_ss_xy(nx, ny, kx, ky, nup, l2)),
+                        (0.3, ks::h_sss_chi(nx, ny, kx, ky, nup)),
+                        (0.1, ks::h_ring(nx, ny, kx, ky, nup)),
+                        (1., ks::h_ss_dm_z(nx, ny, kx, ky, nup, l1, &dz)),
+                    ]));
+                }
+            }
+            assert_same_spectrum(union, eigs);
+        }
+    }
+}
+
+/// Helpers shared by the tests of the modules above
+#[cfg(test)]
+mod testing {
+    use common::*;
+    use model::Hamiltonian;
+
+    /// All eigenvalues of Σ J A over the pairs (J, A), by dense
+    /// diagonalization
+    pub fn eigenvalues(terms: Vec<(f64, CsrMatrix<CComplex<f64>>)>) -> Vec<f64> {
+        Hamiltonian::from_terms(terms).eigenvalues()
+    }
+
+    /// Asserts that the spectra of the sectors, gathered in union, together
+    /// make up the spectrum eigs of the larger space
+    pub fn assert_same_spectrum(mut union: Vec<f64>, mut eigs: Vec<f64>) {
+        union.sort_by(|x, y| x.partial_cmp(y).unwrap());
+        eigs.sort_by(|x, y| x.partial_cmp(y).unwrap());
+        assert_eq!(union.len(), eigs.len());
+        for (x, y) in union.iter().zip(eigs.iter()) {
+            assert!((x - y).abs() < 1e-8, "{} != {}", x, y);
+        }
     }
 }
@@ -0,0 +1,369 @@
+// the exports in lib.rs share one safety contract stated there, but the
+// helpers here each document their own
+#![warn(clippy::missing_safety_doc)]
+
+/// Error reporting for the functions exported over the FFI. Every function that
+/// could fail writes its result through an out pointer and returns a `Status`.
+/// When the status is anything but `Ok` a description of what went wrong could
+/// be retrieved with `last_error_message` from the same thread.
+///
+/// Matrices cross the boundary in two steps. A builder hands out a `CsrHandle`
+/// whose arrays are owned by Rust, the caller asks for its `CsrDims`, allocates
+/// arrays of its own and has them filled by `fill`. The matrix is then given
+/// back to be freed with the layout it was allocated with.
+use libc::size_t;
+use std::{
+    any::Any,
+    cell::RefCell,
+    ffi::CString,
+    io,
+    os::raw::c_char,
+    panic::{self, AssertUnwindSafe},
+    ptr, slice
+};
+
+use common::{CsrMatrix, InputError};
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Status {
+    Ok = 0,
+    /// The parameters were out of range
+    InvalidInput = 1,
+    /// A file could not be read or written
+    Io = 2,
+    /// A null pointer was passed where a valid one is required
+    NullPointer = 3,
+    /// Rust panicked. This is a bug or a violated precondition that is not
+    /// checked up front
+    Panic = 4
+}
+
+pub enum Error {
+    Input(InputError),
+    Io(io::Error),
+    /// The named pointer argument was null
+    Null(&'static str)
+}
+
+impl From<InputError> for Error {
+    fn from(e: InputError) -> Self { Error::Input(e) }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self { Error::Io(e) }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(msg: String) {
+    // a message with an interior nul would be cut short on the C side anyway
+    let msg = CString::new(msg.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(msg));
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match payload.downcast_ref::<&str>() {
+        Some(s) => s.to_string(),
+        None => match payload.downcast_ref::<String>() {
+            Some(s) => s.clone(),
+            None => "Rust panicked".to_string()
+        }
+    }
+}
+
+/// Runs f and writes its result to out. Errors are turned into a status code
+/// and so are panics, which would otherwise abort the calling process.
+///
+/// # Safety
+///
+/// out is either null or valid for a write of a `T`. Whatever it held before
+/// is overwritten without being dropped.
+pub unsafe fn run<T, F>(out: *mut T, f: F) -> Status
+    where F: FnOnce() -> Result<T, Error>
+{
+    if out.is_null() {
+        set_last_error("the output pointer is null".to_string());
+        return Status::NullPointer;
+    }
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(x)) => {
+            ptr::write(out, x);
+            Status::Ok
+        }
+        Ok(Err(Error::Input(e))) => {
+            set_last_error(e.to_string());
+            Status::InvalidInput
+        }
+        Ok(Err(Error::Io(e))) => {
+            set_last_error(e.to_string());
+            Status::Io
+        }
+        Ok(Err(Error::Null(name))) => {
+            set_last_error(format!("the {} pointer is null", name));
+            Status::NullPointer
+        }
+        Err(payload) => {
+            set_last_error(panic_message(&*payload));
+            Status::Panic
+        }
+    }
+}
+
+/// As `run` for functions that build a matrix, which is handed to the caller
+/// as a `CsrHandle`
+///
+/// # Safety
+///
+/// As for `run`.
+pub unsafe fn run_matrix<T, F>(out: *mut CsrHandle<T>, f: F) -> Status
+    where F: FnOnce() -> Result<CsrMatrix<T>, Error>
+{
+    run(out, || f().map(CsrHandle::new))
+}
+
+/// Borrows the object behind a pointer passed in by the caller
+///
+/// # Safety
+///
+/// ptr is either null or points to a `T` that stays alive and unchanged for
+/// 'a.
+pub unsafe fn object<'a, T>(name: &'static str, ptr: *const T)
+                            -> Result<&'a T, Error> {
+    ptr.as_ref().ok_or(Error::Null(name))
+}
+
+/// Borrows the array of length len at ptr. Like `copy_into` it accepts a null
+/// pointer for an empty array
+///
+/// # Safety
+///
+/// Unless len is 0, ptr is either null or points to len `T`s that stay alive
+/// and unchanged for 'a.
+pub unsafe fn array<'a, T>(name: &'static str, ptr: *const T, len: usize)
+                           -> Result<&'a [T], Error> {
+    if len == 0 {
+        Ok(&[])
+    } else if ptr.is_null() {
+        Err(Error::Null(name))
+    } else {
+        Ok(slice::from_raw_parts(ptr, len))
+    }
+}
+
+/// An array owned by Rust while the caller holds on to it
+#[repr(C)]
+pub struct Vector<T> {
+    ptr: *mut T,
+    len: size_t
+}
+
+impl<T> Vector<T> {
+    fn from_vec(v: Vec<T>) -> Vector<T> {
+        // a boxed slice has no spare capacity, so the pointer and the length
+        // are all it takes to rebuild it
+        let len = v.len() as size_t;
+        let ptr = Box::into_raw(v.into_boxed_slice()) as *mut T;
+        Vector { ptr, len }
+    }
+
+    /// Takes back the buffer. It must have come from `from_vec`, unchanged
+    unsafe fn into_vec(self) -> Vec<T> {
+        Box::from_raw(ptr::slice_from_raw_parts_mut(self.ptr, self.len)).into_vec()
+    }
+
+    /// The buffer, which must have come from `from_vec` and not been taken
+    /// back yet
+    unsafe fn as_slice(&self) -> &[T] { slice::from_raw_parts(self.ptr, self.len) }
+}
+
+/// A `CsrMatrix` handed over to the caller, who holds it until it is passed to
+/// one of the `request_free` functions
+#[repr(C)]
+pub struct CsrHandle<T> {
+    data:    Vector<T>,
+    indices: Vector<u32>,
+    indptr:  Vector<u64>,
+    ncols:   u32,
+    nrows:   u32
+}
+
+impl<T> CsrHandle<T> {
+    pub fn new(mat: CsrMatrix<T>) -> CsrHandle<T> {
+        let (nrows, ncols) = (mat.nrows, mat.ncols);
+        let (data, indices, indptr) = mat.into_parts();
+        CsrHandle { data: Vector::from_vec(data),
+                    indices: Vector::from_vec(indices),
+                    indptr: Vector::from_vec(indptr),
+                    ncols,
+                    nrows }
+    }
+
+    /// Takes the matrix back from the caller
+    ///
+    /// # Safety
+    ///
+    /// The handle came from `new`, unchanged, and has not been taken back
+    /// before.
+    pub unsafe fn into_matrix(self) -> CsrMatrix<T> {
+        CsrMatrix::from_parts(self.data.into_vec(),
+                              self.indices.into_vec(),
+                              self.indptr.into_vec(),
+                              self.nrows,
+                              self.ncols)
+    }
+}
+
+/// What a caller needs to know to allocate the arrays of a CSR matrix
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CsrDims {
+    pub nrows: u32,
+    pub ncols: u32,
+    /// The number of stored entries, which is the length of data and indices.
+    /// indptr has nrows + 1 entries
+    pub nnz:   u64
+}
+
+pub fn dims<T>(mat: &CsrHandle<T>) -> CsrDims {
+    CsrDims { nrows: mat.nrows,
+              ncols: mat.ncols,
+              nnz:   mat.data.len as u64 }
+}
+
+/// Copies src into the buffer of length len at dst
+///
+/// # Safety
+///
+/// dst is either null or valid for writes of len `T`s.
+pub unsafe fn copy_into<T: Copy>(array: &'static str, src: &[T], dst: *mut T,
+                                 len: size_t)
+                                 -> Result<(), Error> {
+    if len < src.len() {
+        return Err(InputError::BufferSize { array,
+                                            needed: src.len(),
+                                            found: len }.into());
+    }
+    // an empty array may come with a dangling or null pointer
+    if !src.is_empty() {
+        if dst.is_null() {
+            return Err(Error::Null(array));
+        }
+        slice::from_raw_parts_mut(dst, src.len()).copy_from_slice(src);
+    }
+    Ok(())
+}
+
+/// Copies the arrays of mat into buffers allocated by the caller. Each buffer
+/// comes with its length, which must be at least that given by `dims`.
+///
+/// # Safety
+///
+/// mat came from `CsrHandle::new` and has not been taken back, and each buffer
+/// is as for `copy_into`.
+pub unsafe fn fill<T: Copy>(mat: &CsrHandle<T>, data: *mut T, data_len: size_t,
+                            indices: *mut u32, indices_len: size_t,
+                            indptr: *mut u64, indptr_len: size_t)
+                            -> Result<(), Error> {
+    copy_into("data", mat.data.as_slice(), data, data_len)?;
+    copy_into("indices", mat.indices.as_slice(), indices, indices_len)?;
+    copy_into("indptr", mat.indptr.as_slice(), indptr, indptr_len)
+}
+
+/// The message describing the last error on this thread, or null if nothing
+/// has failed yet. The string is owned by Rust and stays valid until the next
+/// failing call on the same thread.
+pub fn last_error_message() -> *const c_char {
+    LAST_ERROR.with(|e| match *e.borrow() {
+        Some(ref msg) => msg.as_ptr(),
+        None => ptr::null()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    #[test]
+    fn run_test() {
+        let mut out = 0;
+        let status = unsafe { run(&mut out, || Ok(3)) };
+        assert_eq!((status, out), (Status::Ok, 3));
+
+        let status = unsafe {
+            run(&mut out, || Err(InputError::BondRange(4).into()))
+        };
+        assert_eq!((status, out), (Status::InvalidInput, 3));
+        let msg = unsafe { CStr::from_ptr(last_error_message()) };
+        assert_eq!(msg.to_str().unwrap(), InputError::BondRange(4).to_string());
+
+        let status = unsafe { run::<i32, _>(&mut out, || panic!("oops")) };
+        assert_eq!(status, Status::Panic);
+        let msg = unsafe { CStr::from_ptr(last_error_message()) };
+        assert_eq!(msg.to_str().unwrap(), "oops");
+
+        let status = unsafe { run(ptr::null_mut(), || Ok(3)) };
+        assert_eq!(status, Status::NullPointer);
+    }
+
+    #[test]
+    fn null_input_test() {
+        let mut out = 0;
+        let status = unsafe {
+            run(&mut out, || Ok(array("fields", ptr::null::<f64>(), 3)?.len()))
+        };
+        assert_eq!(status, Status::NullPointer);
+        let msg = unsafe { CStr::from_ptr(last_error_message()) };
+        assert_eq!(msg.to_str().unwrap(), "the fields pointer is null");
+
+        // empty arrays need no storage
+        let status = unsafe {
+            run(&mut out, || Ok(array("fields", ptr::null::<f64>(), 0)?.len()))
+        };
+        assert_eq!((status, out), (Status::Ok, 0));
+        assert!(unsafe { object("sector", ptr::null::<u32>()) }.is_err());
+        assert!(unsafe { copy_into("kx", &[1_u32], ptr::null_mut(), 1) }.is_err());
+    }
+
+    #[test]
+    fn fill_test() {
+        let mat = CsrMatrix::from_triplets(vec![1., 2., 3.],
+                                           vec![0, 2, 2],
+                                           vec![1, 0, 1],
+                                           3,
+                                           2);
+        let mat = CsrHandle::new(mat);
+        let d = dims(&mat);
+        assert_eq!(d, CsrDims { nrows: 3, ncols: 2, nnz: 3 });
+
+        let mut data = vec![0.; d.nnz as usize];
+        let mut indices = vec![0; d.nnz as usize];
+        let mut indptr = vec![0; d.nrows as usize + 1];
+        let mut fill_with = |indptr_len| unsafe {
+            fill(&mat,
+                 data.as_mut_ptr(),
+                 data.len(),
+                 indices.as_mut_ptr(),
+                 indices.len(),
+                 indptr.as_mut_ptr(),
+                 indptr_len)
+        };
+        match fill_with(2) {
+            Err(Error::Input(e)) => {
+                let expected =
+                    InputError::BufferSize { array: "indptr", needed: 4, found: 2 };
+                assert_eq!(e, expected);
+            }
+            _ => panic!("a short buffer was filled")
+        }
+        assert!(fill_with(4).is_ok());
+        assert_eq!(data, vec![1., 2., 3.]);
+        assert_eq!(indices, vec![1, 0, 1]);
+        assert_eq!(indptr, vec![0, 1, 1, 3]);
+        let mat = unsafe { mat.into_matrix() };
+        assert_eq!(mat.as_slices(), (&data[..], &indices[..], &indptr[..]));
+    }
+}
@@ -0,0 +1,453 @@
+/// Lowest eigenvalues and eigenvectors of Hermitian operators by the Lanczos
+/// method. Every Lanczos vector is kept and fully reorthogonalized against,
+/// which costs memory but keeps ghost eigenvalues out of the spectrum. When the
+/// Krylov space closes before the requested eigenvalues converge, the iteration
+/// continues from a fresh vector orthogonal to it, so degenerate eigenvalues are
+/// found with their multiplicity.
+use num_complex::Complex;
+
+type C = Complex<f64>;
+
+/// A deterministic xorshift generator. Runs should be reproducible and the
+/// starting vector need not be random in any stronger sense.
+struct Xorshift(u64);
+
+impl Xorshift {
+    fn next(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1_u64 << 53) as f64 - 0.5
+    }
+
+    fn vector(&mut self, dim: usize) -> Vec<C> {
+        (0..dim).map(|_| Complex::new(self.next(), self.next())).collect()
+    }
+}
+
+fn dot(x: &[C], y: &[C]) -> C {
+    x.iter()
+     .zip(y.iter())
+     .fold(Complex::new(0., 0.), |acc, (a, b)| acc + a.conj() * b)
+}
+
+fn norm(x: &[C]) -> f64 { x.iter().map(|a| a.norm_sqr()).sum::<f64>().sqrt() }
+
+/// Removes the components of w along each of the orthonormal vectors in basis.
+/// Done twice, which is enough to bring w to orthogonality up to rounding
+fn orthogonalize(w: &mut [C], basis: &[Vec<C>]) {
+    for _ in 0..2 {
+        for v in basis.iter() {
+            let c = dot(v, w);
+            for (a, b) in w.iter_mut().zip(v.iter()) {
+                *a -= c * b;
+            }
+        }
+    }
+}
+
+/// The number of eigenvalues of the symmetric tridiagonal matrix with diagonal
+/// a and off-diagonal b that are smaller than x, by Sylvester's law of inertia
+fn sturm_count(a: &[f64], b: &[f64], x: f64) -> usize {
+    let mut count = 0;
+    let mut q = 1.;
+    for i in 0..a.len() {
+        let off = if i == 0 { 0. } else { b[i - 1] * b[i - 1] / q };
+        q = a[i] - x - off;
+        if q == 0. {
+            q = -1e-300;
+        }
+        if q < 0. {
+            count += 1;
+        }
+    }
+    count
+}
+
+/// The lowest n eigenvalues of a symmetric tridiagonal matrix, by bisection
+pub fn tridiagonal_eigenvalues(a: &[f64], b: &[f64], n: usize) -> Vec<f64> {
+    // Gershgorin discs bound the spectrum
+    let radius = |i: usize| {
+        let left = if i == 0 { 0. } else { b[i - 1].abs() };
+        let right = if i + 1 < a.len() { b[i].abs() } else { 0. };
+        left + right
+    };
+    let lower = (0..a.len()).map(|i| a[i] - radius(i))
+                            .fold(f64::INFINITY, f64::min);
+    let upper = (0..a.len()).map(|i| a[i] + radius(i))
+                            .fold(f64::NEG_INFINITY, f64::max);
+    (0..n.min(a.len())).map(|k| {
+                           // the smallest x with more than k eigenvalues below
+                           let (mut lo, mut hi) = (lower, upper);
+                           for _ in 0..200 {
+                               let mid = 0.5 * (lo + hi);
+                               if mid <= lo || mid >= hi {
+                                   break;
+                               }
+                               if sturm_count(a, b, mid) > k {
+                                   hi = mid;
+                               } else {
+                                   lo = mid;
+                               }
+                           }
+                           0.5 * (lo + hi)
+                       })
+                       .collect()
+}
+
+/// Solves (T - e) x = y in place for the symmetric tridiagonal matrix T with
+/// diagonal a and off-diagonal b, by Gaussian elimination with partial
+/// pivoting as in LAPACK's dgttrf. Pivots that vanish are replaced by a tiny
+/// number, as T - e is singular when e is one of its eigenvalues.
+fn tridiagonal_solve(a: &[f64], b: &[f64], e: f64, x: &mut [f64]) {
+    let n = a.len();
+    let scale = a.iter().chain(b.iter()).fold(1_f64, |m, x| m.max(x.abs()));
+    let tiny = 1e-14 * scale;
+    let mut d = a.iter().map(|a| a - e).collect::<Vec<_>>();
+    let (mut dl, mut du) = (b.to_vec(), b.to_vec());
+    let mut du2 = vec![0.; n.saturating_sub(2)];
+    let mut swapped = vec![false; n.saturating_sub(1)];
+    for i in 0..n.saturating_sub(1) {
+        if d[i].abs() >= dl[i].abs() {
+            if d[i] == 0. {
+                d[i] = tiny;
+            }
+            let fact = dl[i] / d[i];
+            dl[i] = fact;
+            d[i + 1] -= fact * du[i];
+        } else {
+            let fact = d[i] / dl[i];
+            d[i] = dl[i];
+            dl[i] = fact;
+            let temp = du[i];
+            du[i] = d[i + 1];
+            d[i + 1] = temp - fact * d[i + 1];
+            if i + 2 < n {
+                du2[i] = du[i + 1];
+                du[i + 1] *= -fact;
+            }
+            swapped[i] = true;
+        }
+    }
+    if d[n - 1] == 0. {
+        d[n - 1] = tiny;
+    }
+
+    for i in 0..n.saturating_sub(1) {
+        if swapped[i] {
+            x.swap(i, i + 1);
+        }
+        x[i + 1] -= dl[i] * x[i];
+    }
+    for i in (0..n).rev() {
+        let mut r = x[i];
+        if i + 1 < n {
+            r -= du[i] * x[i + 1];
+        }
+        if i + 2 < n {
+            r -= du2[i] * x[i + 2];
+        }
+        x[i] = r / d[i];
+    }
+}
+
+/// Normalized eigenvectors of the symmetric tridiagonal matrix with diagonal a
+/// and off-diagonal b for its eigenvalues eigs, by inverse iteration.
+/// Eigenvectors of (nearly) degenerate eigenvalues are kept orthogonal to one
+/// another.
+pub fn tridiagonal_eigenvectors(a: &[f64], b: &[f64], eigs: &[f64])
+                                -> Vec<Vec<f64>> {
+    let n = a.len();
+    let scale = a.iter().chain(b.iter()).fold(1_f64, |m, x| m.max(x.abs()));
+    let mut rng = Xorshift(0x9e37_79b9_7f4a_7c15);
+    let mut vectors: Vec<Vec<f64>> = Vec::with_capacity(eigs.len());
+    for &e in eigs.iter() {
+        let mut x = (0..n).map(|_| rng.next()).collect::<Vec<_>>();
+        for _ in 0..3 {
+            tridiagonal_solve(a, b, e, &mut x);
+            for (j, v) in vectors.iter().enumerate() {
+                if (eigs[j] - e).abs() <= 1e-8 * scale {
+                    let c = v.iter().zip(x.iter()).map(|(v, x)| v * x).sum::<f64>();
+                    x.iter_mut().zip(v.iter()).for_each(|(x, v)| *x -= c * v);
+                }
+            }
+            let norm = x.iter().map(|x| x * x).sum::<f64>().sqrt();
+            x.iter_mut().for_each(|x| *x /= norm);
+        }
+        vectors.push(x);
+    }
+    vectors
+}
+
+/// All eigenvalues in ascending order of the dense Hermitian n × n matrix a,
+/// stored row by row. Householder reflections bring it to tridiagonal form,
+/// whose off-diagonal could then be made real by rescaling the basis vectors
+/// by phases. Only fit for small matrices, e.g. to check Lanczos against.
+pub fn hermitian_eigenvalues(mut a: Vec<C>, n: usize) -> Vec<f64> {
+    assert_eq!(a.len(), n * n);
+    let mut b = vec![0.; n.saturating_sub(1)];
+    for k in 0..n.saturating_sub(1) {
+        // the reflection I - 2vv* sends column k below the diagonal to alpha e1
+        let mut v = (k + 1..n).map(|i| a[i * n + k]).collect::<Vec<_>>();
+        let xnorm = norm(&v);
+        b[k] = xnorm;
+        if v.len() == 1 || xnorm == 0. {
+            continue;
+        }
+        let phase = if v[0].norm() == 0. { Complex::new(1., 0.) }
+                    else { v[0] / v[0].norm() };
+        v[0] += phase * xnorm;
+        let vnorm = norm(&v);
+        v.iter_mut().for_each(|x| *x /= vnorm);
+
+        // B -> (I - 2vv*) B (I - 2vv*) = B - 2vw* - 2wv* on the trailing block,
+        // with p = Bv and w = p - (v*p) v
+        let m = n - k - 1;
+        let block = |i: usize, j: usize| (k + 1 + i) * n + k + 1 + j;
+        let p = (0..m).map(|i| {
+                          (0..m).fold(Complex::new(0., 0.),
+                                      |acc, j| acc + a[block(i, j)] * v[j])
+                      })
+                      .collect::<Vec<_>>();
+        let vp = dot(&v, &p);
+        let w = p.iter().zip(v.iter()).map(|(p, v)| p - vp * v).collect::<Vec<_>>();
+        for i in 0..m {
+            for j in 0..m {
+                a[block(i, j)] -= (v[i] * w[j].conj() + w[i] * v[j].conj()) * 2.;
+            }
+        }
+    }
+    let diag = (0..n).map(|i| a[i * n + i].re).collect::<Vec<_>>();
+    tridiagonal_eigenvalues(&diag, &b, n)
+}
+
+/// The Lanczos iteration behind `lowest_eigenvalues`, starting from the given
+/// vector or from a pseudorandom one. Returns the Ritz values along with the
+/// Lanczos vectors and the tridiagonal matrix they span.
+fn lanczos<F>(op: F, dim: usize, start: Option<&[C]>, nev: usize, tol: f64,
+              max_iter: usize)
+              -> (Vec<f64>, Vec<Vec<C>>, Vec<f64>, Vec<f64>)
+    where F: Fn(&[C], &mut [C])
+{
+    let mut rng = Xorshift(0x2545_f491_4f6c_dd1d);
+    let mut basis: Vec<Vec<C>> = Vec::new();
+    let (mut a, mut b) = (Vec::new(), Vec::new());
+    let mut v = rng.vector(dim);
+    // a zero start, or one with infinite or NaN entries, could not be
+    // normalized and is ignored in favour of the pseudorandom vector
+    let start = start.filter(|s| norm(s).is_finite() && norm(s) > 0.);
+    if let Some(start) = start {
+        // a start with a definite symmetry would keep the iteration in its
+        // symmetry sector, so a little of the pseudorandom vector stays in
+        let scale = 0.1 * norm(start) / norm(&v);
+        for (v, s) in v.iter_mut().zip(start.iter()) {
+            *v = *v * scale + s;
+        }
+    }
+    let n = norm(&v);
+    v.iter_mut().for_each(|x| *x /= n);
+
+    let mut w = vec![Complex::new(0., 0.); dim];
+    let mut previous: Vec<f64> = Vec::new();
+    loop {
+        op(&v, &mut w);
+        a.push(dot(&v, &w).re);
+        basis.push(v);
+        orthogonalize(&mut w, &basis);
+
+        let ritz = tridiagonal_eigenvalues(&a, &b, nev);
+        let converged = ritz.len() == nev
+                        && previous.len() == nev
+                        && ritz.iter()
+                               .zip(previous.iter())
+                               .all(|(x, y)| (x - y).abs() <= tol * x.abs().max(1.));
+        if converged || basis.len() == dim || basis.len() >= max_iter {
+            return (ritz, basis, a, b);
+        }
+        previous = ritz;
+
+        let mut beta = norm(&w);
+        let scale = a.iter().fold(1_f64, |m, x| m.max(x.abs()));
+        if beta <= 1e-12 * scale {
+            // the Krylov space is invariant. Carry on in its complement, where
+            // the Ritz values start over
+            beta = 0.;
+            previous.clear();
+            w = rng.vector(dim);
+            orthogonalize(&mut w, &basis);
+            let n = norm(&w);
+            w.iter_mut().for_each(|x| *x /= n);
+        } else {
+            w.iter_mut().for_each(|x| *x /= beta);
+        }
+        b.push(beta);
+        v = w;
+        w = vec![Complex::new(0., 0.); dim];
+    }
+}
+
+/// The lowest nev eigenvalues, in ascending order, of the Hermitian operator of
+/// dimension dim applied to a vector by op, which writes the product into its
+/// second argument. Iteration stops when none of the nev eigenvalues moves by
+/// more than tol (relative to its magnitude, or absolute below 1) from one step
+/// to the next, or after max_iter steps.
+///
+/// Every Lanczos vector is kept until the iteration ends, so memory grows as
+/// O(steps · dim): up to 16 · max_iter · dim bytes. max_iter is what bounds it
+/// on large sectors.
+pub fn lowest_eigenvalues<F>(op: F, dim: usize, nev: usize, tol: f64,
+                             max_iter: usize)
+                             -> Vec<f64>
+    where F: Fn(&[C], &mut [C])
+{
+    let nev = nev.min(dim);
+    if nev == 0 {
+        return Vec::new();
+    }
+    lanczos(op, dim, None, nev, tol, max_iter).0
+}
+
+/// As `lowest_eigenvalues` but with the normalized eigenvectors as well
+pub fn lowest_eigenpairs<F>(op: F, dim: usize, nev: usize, tol: f64,
+                            max_iter: usize)
+                            -> (Vec<f64>, Vec<Vec<C>>)
+    where F: Fn(&[C], &mut [C])
+{
+    eigenpairs(op, dim, None, nev, tol, max_iter)
+}
+
+/// As `lowest_eigenpairs` but starting from the given vector, whose length is
+/// the dimension. A good guess for the ground state, such as the ground state
+/// of a nearby Hamiltonian, saves many steps. A small pseudorandom part is
+/// mixed in, so that eigenvectors orthogonal to the guess by symmetry are still
+/// found. A start that is zero or not finite is ignored.
+pub fn lowest_eigenpairs_from<F>(op: F, start: &[C], nev: usize, tol: f64,
+                                 max_iter: usize)
+                                 -> (Vec<f64>, Vec<Vec<C>>)
+    where F: Fn(&[C], &mut [C])
+{
+    eigenpairs(op, start.len(), Some(start), nev, tol, max_iter)
+}
+
+fn eigenpairs<F>(op: F, dim: usize, start: Option<&[C]>, nev: usize, tol: f64,
+                 max_iter: usize)
+                 -> (Vec<f64>, Vec<Vec<C>>)
+    where F: Fn(&[C], &mut [C])
+{
+    let nev = nev.min(dim);
+    if nev == 0 {
+        return (Vec::new(), Vec::new());
+    }
+    let (eigs, basis, a, b) = lanczos(op, dim, start, nev, tol, max_iter);
+    let vectors = tridiagonal_eigenvectors(&a, &b, &eigs)
+        .iter()
+        .map(|x| {
+            let mut y = vec![Complex::new(0., 0.); dim];
+            for (&c, v) in x.iter().zip(basis.iter()) {
+                for (y, v) in y.iter_mut().zip(v.iter()) {
+                    *y += v * c;
+                }
+            }
+            y
+        })
+        .collect();
+    (eigs, vectors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tridiagonal_test() {
+        // the path graph on 4 vertices has eigenvalues 2cos(kπ/5)
+        let eigs = tridiagonal_eigenvalues(&[0.; 4], &[1.; 3], 4);
+        for (k, e) in eigs.iter().enumerate() {
+            let expected = 2. * ((4 - k) as f64 * ::std::f64::consts::PI / 5.).cos();
+            assert!((e - expected).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn hermitian_test() {
+        // the hopping ring of eigenpairs_test with a flux through it, which
+        // shifts the momenta and so the eigenvalues to -2cos((2πk + φ)/6)
+        let (n, flux) = (6, 0.9);
+        let hop = Complex::from_polar(&1., &(flux / 6.));
+        let mut a = vec![Complex::new(0., 0.); n * n];
+        for i in 0..n {
+            a[i * n + (i + 1) % n] = -hop;
+            a[((i + 1) % n) * n + i] = -hop.conj();
+        }
+        let mut expected = (0..n).map(|k| {
+                                     let q = 2. * ::std::f64::consts::PI * k as f64;
+                                     -2. * ((q + flux) / 6.).cos()
+                                 })
+                                 .collect::<Vec<_>>();
+        expected.sort_by(|x, y| x.partial_cmp(y).unwrap());
+        let eigs = hermitian_eigenvalues(a, n);
+        for (e, x) in eigs.iter().zip(expected.iter()) {
+            assert!((e - x).abs() < 1e-10);
+        }
+    }
+
+    #[test]
+    fn degenerate_test() {
+        // a diagonal operator with a threefold degenerate lowest eigenvalue
+        let diag = [3., -1., 2., -1., 0.5, -1., 7.];
+        let op = |x: &[C], y: &mut [C]| {
+            for ((y, x), d) in y.iter_mut().zip(x.iter()).zip(diag.iter()) {
+                *y = x * d;
+            }
+        };
+        let eigs = lowest_eigenvalues(op, diag.len(), 5, 1e-12, 100);
+        let expected = [-1., -1., -1., 0.5, 2.];
+        assert_eq!(eigs.len(), 5);
+        for (e, x) in eigs.iter().zip(expected.iter()) {
+            assert!((e - x).abs() < 1e-10);
+        }
+    }
+
+    #[test]
+    fn eigenpairs_test() {
+        // a ring of 6 sites with hopping has eigenvalues -2cos(2πk/6), with
+        // the lowest excited level doubly degenerate
+        let op = |x: &[C], y: &mut [C]| {
+            for i in 0..6 {
+                y[i] = -(x[(i + 1) % 6] + x[(i + 5) % 6]);
+            }
+        };
+        let (eigs, vectors) = lowest_eigenpairs(op, 6, 3, 1e-12, 100);
+        let expected = [-2., -1., -1.];
+        for (e, x) in eigs.iter().zip(expected.iter()) {
+            assert!((e - x).abs() < 1e-10);
+        }
+        for (i, (e, v)) in eigs.iter().zip(vectors.iter()).enumerate() {
+            let mut w = vec![Complex::new(0., 0.); 6];
+            op(v, &mut w);
+            let residual = w.iter()
+                            .zip(v.iter())
+                            .map(|(w, v)| (w - v * e).norm_sqr())
+                            .sum::<f64>();
+            assert!(residual.sqrt() < 1e-8);
+            for (j, u) in vectors.iter().enumerate() {
+                let expected = if i == j { 1. } else { 0. };
+                assert!((dot(u, v) - expected).norm() < 1e-8);
+            }
+        }
+
+        // starting from an eigenvector closes the Krylov space at once
+        let (eigs, _) = lowest_eigenpairs_from(op, &vectors[0], 1, 1e-12, 100);
+        assert!((eigs[0] + 2.).abs() < 1e-10);
+
+        // starts that cannot be normalized fall back to the pseudorandom one
+        let zero = vec![Complex::new(0., 0.); 6];
+        let nan = vec![Complex::new(f64::NAN, 0.); 6];
+        for start in [zero, nan].iter() {
+            let (eigs, _) = lowest_eigenpairs_from(op, start, 3, 1e-12, 100);
+            for (e, x) in eigs.iter().zip(expected.iter()) {
+                assert!((e - x).abs() < 1e-10);
+            }
+        }
+    }
+}
@@ -1,100 +1,1412 @@
+// the unsafe functions exported below share the safety contract stated above
+// them rather than each repeating it
+#![allow(clippy::missing_safety_doc)]
+
 extern crate fnv;
 extern crate libc;
 extern crate num_bigint;
 extern crate num_complex;
 extern crate num_traits;
+extern crate rayon;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate toml;
 
 #[macro_use]
 mod buildtype;
 
 mod blochfunc;
+pub mod checkpoint;
 pub mod common;
 pub mod consv;
+mod ffi;
+pub mod lanczos;
+pub mod mmio;
+pub mod model;
+pub mod npy;
 mod ops;
+pub mod sector;
 mod sitevector;
+pub mod spec;
 
-use common::{CComplex, CoordMatrix, Dim, I, K};
+use common::{
+    check_bond, check_full_lattice, check_lattice, check_momentum, check_nup,
+    check_real_momentum, CComplex, Dim, InputError, Orientation, I, K
+};
+use ffi::{run, run_matrix, CsrDims, CsrHandle, Status};
+use libc::size_t;
+use num_complex::Complex;
+use sector::{Orbits, Sector};
+use std::{ffi::CStr, io, os::raw::c_char};
 
 // The following functions wrap functions in child modules so they could be
 // exported via the FFI without namespace collisions (the FFI follows C
 // convention so namespace doesn't exist.)
+//
+// Functions that could fail on bad input write their result to "out" and
+// return a status code. On anything but Status::Ok "out" is left untouched and
+// last_error_message tells what went wrong.
+//
+// Safety: every exported function relies on the caller for what it cannot
+// check. A pointer argument is either null, which is reported as
+// Status::NullPointer, or points to a live value of its type. An array pointer
+// holds at least as many entries as the length passed along with it, or the
+// length its comment gives. Sectors, orbits and matrices were handed out by
+// this library, are passed back unchanged and are freed exactly once, after
+// which they are not used again.
+#[no_mangle]
+pub unsafe extern "C" fn k_h_ss_z(nx: u32, ny: u32, kx: u32, ky: u32, l: u32,
+                                  out: *mut CsrHandle<CComplex<f64>>)
+                                  -> Status {
+    run_matrix(out, || {
+        consv::k::check(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        check_bond(I(l as i32))?;
+        Ok(consv::k::h_ss_z(Dim(nx), Dim(ny), K(kx), K(ky), I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn k_h_ss_xy(nx: u32, ny: u32, kx: u32, ky: u32, l: u32,
+                                   out: *mut CsrHandle<CComplex<f64>>)
+                                   -> Status {
+    run_matrix(out, || {
+        consv::k::check(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        check_bond(I(l as i32))?;
+        Ok(consv::k::h_ss_xy(Dim(nx), Dim(ny), K(kx), K(ky), I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn k_h_ss_ppmm(nx: u32, ny: u32, kx: u32, ky: u32, l: u32,
+                                     out: *mut CsrHandle<CComplex<f64>>)
+                                     -> Status {
+    run_matrix(out, || {
+        consv::k::check(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        check_bond(I(l as i32))?;
+        Ok(consv::k::h_ss_ppmm(Dim(nx), Dim(ny), K(kx), K(ky), I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn k_h_ss_pmz(nx: u32, ny: u32, kx: u32, ky: u32, l: u32,
+                                    out: *mut CsrHandle<CComplex<f64>>)
+                                    -> Status {
+    run_matrix(out, || {
+        consv::k::check(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        check_bond(I(l as i32))?;
+        Ok(consv::k::h_ss_pmz(Dim(nx), Dim(ny), K(kx), K(ky), I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn k_h_sss_chi(nx: u32, ny: u32, kx: u32, ky: u32,
+                                     out: *mut CsrHandle<CComplex<f64>>)
+                                     -> Status {
+    run_matrix(out, || {
+        consv::k::check(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        Ok(consv::k::h_sss_chi(Dim(nx), Dim(ny), K(kx), K(ky)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn k_h_ring(nx: u32, ny: u32, kx: u32, ky: u32,
+                                  out: *mut CsrHandle<CComplex<f64>>)
+                                  -> Status {
+    run_matrix(out, || {
+        consv::k::check(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        Ok(consv::k::h_ring(Dim(nx), Dim(ny), K(kx), K(ky)))
+    })
+}
+
+// "jmats" holds the 3x3 exchange matrices J^ab of the three bond directions
+// flattened in row-major order into a single array of length 27
+#[no_mangle]
+pub unsafe extern "C" fn k_h_ss_tensor(nx: u32, ny: u32, kx: u32, ky: u32, l: u32,
+                                       jmats: *const f64,
+                                       out: *mut CsrHandle<CComplex<f64>>)
+                                       -> Status {
+    run_matrix(out, || {
+        consv::k::check(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        check_bond(I(l as i32))?;
+        let j = ffi::array("jmats", jmats, 27)?;
+        let mut jmats = [[[0.; 3]; 3]; 3];
+        for (i, &x) in j.iter().enumerate() {
+            jmats[i / 9][(i / 3) % 3][i % 3] = x;
+        }
+        Ok(consv::k::h_ss_tensor(Dim(nx),
+                                 Dim(ny),
+                                 K(kx),
+                                 K(ky),
+                                 I(l as i32),
+                                 &jmats))
+    })
+}
+
+// "dvecs" holds the D vectors (D_x, D_y, D_z) of the three bond directions
+// flattened into a single array of length 9
+#[no_mangle]
+pub unsafe extern "C" fn k_h_ss_dm(nx: u32, ny: u32, kx: u32, ky: u32, l: u32,
+                                   dvecs: *const f64,
+                                   out: *mut CsrHandle<CComplex<f64>>)
+                                   -> Status {
+    run_matrix(out, || {
+        consv::k::check(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        check_bond(I(l as i32))?;
+        let d = ffi::array("dvecs", dvecs, 9)?;
+        let dvecs = [[d[0], d[1], d[2]], [d[3], d[4], d[5]], [d[6], d[7], d[8]]];
+        Ok(consv::k::h_ss_dm(Dim(nx), Dim(ny), K(kx), K(ky), I(l as i32), &dvecs))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn k_ss_z(nx: u32, ny: u32, kx: u32, ky: u32, l: u32,
+                                out: *mut CsrHandle<CComplex<f64>>)
+                                -> Status {
+    run_matrix(out, || {
+        consv::k::check(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        check_bond(I(l as i32))?;
+        Ok(consv::k::ss_z(Dim(nx), Dim(ny), K(kx), K(ky), I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn k_ss_xy(nx: u32, ny: u32, kx: u32, ky: u32, l: u32,
+                                 out: *mut CsrHandle<CComplex<f64>>)
+                                 -> Status {
+    run_matrix(out, || {
+        consv::k::check(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        check_bond(I(l as i32))?;
+        Ok(consv::k::ss_xy(Dim(nx), Dim(ny), K(kx), K(ky), I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn k_chi_chi(nx: u32, ny: u32, kx: u32, ky: u32, dx: u32,
+                                   dy: u32, o1: u32, o2: u32,
+                                   out: *mut CsrHandle<CComplex<f64>>)
+                                   -> Status {
+    run_matrix(out, || {
+        consv::k::check(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        Ok(consv::k::chi_chi(Dim(nx),
+                             Dim(ny),
+                             K(kx),
+                             K(ky),
+                             I(dx as i32),
+                             I(dy as i32),
+                             Orientation::from_raw(o1)?,
+                             Orientation::from_raw(o2)?))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn k_chi_order(nx: u32, ny: u32, kx: u32, ky: u32,
+                                     staggered: bool,
+                                     out: *mut CsrHandle<CComplex<f64>>)
+                                     -> Status {
+    run_matrix(out, || {
+        consv::k::check(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        Ok(consv::k::chi_order(Dim(nx), Dim(ny), K(kx), K(ky), staggered))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ks_h_ss_z(nx: u32, ny: u32, kx: u32, ky: u32, nup: u32,
+                                   l: u32, out: *mut CsrHandle<CComplex<f64>>)
+                                   -> Status {
+    run_matrix(out, || {
+        consv::ks::check(Dim(nx), Dim(ny), K(kx), K(ky), nup)?;
+        check_bond(I(l as i32))?;
+        Ok(consv::ks::h_ss_z(Dim(nx), Dim(ny), K(kx), K(ky), nup, I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ks_h_ss_xy(nx: u32, ny: u32, kx: u32, ky: u32, nup: u32,
+                                    l: u32, out: *mut CsrHandle<CComplex<f64>>)
+                                    -> Status {
+    run_matrix(out, || {
+        consv::ks::check(Dim(nx), Dim(ny), K(kx), K(ky), nup)?;
+        check_bond(I(l as i32))?;
+        Ok(consv::ks::h_ss_xy(Dim(nx), Dim(ny), K(kx), K(ky), nup, I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ks_h_sss_chi(nx: u32, ny: u32, kx: u32, ky: u32, nup: u32,
+                                      out: *mut CsrHandle<CComplex<f64>>)
+                                      -> Status {
+    run_matrix(out, || {
+        consv::ks::check(Dim(nx), Dim(ny), K(kx), K(ky), nup)?;
+        Ok(consv::ks::h_sss_chi(Dim(nx), Dim(ny), K(kx), K(ky), nup))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ks_h_ring(nx: u32, ny: u32, kx: u32, ky: u32, nup: u32,
+                                   out: *mut CsrHandle<CComplex<f64>>)
+                                   -> Status {
+    run_matrix(out, || {
+        consv::ks::check(Dim(nx), Dim(ny), K(kx), K(ky), nup)?;
+        Ok(consv::ks::h_ring(Dim(nx), Dim(ny), K(kx), K(ky), nup))
+    })
+}
+
+// "dz" holds D_z for each of the three bond directions
+#[no_mangle]
+pub unsafe extern "C" fn ks_h_ss_dm_z(nx: u32, ny: u32, kx: u32, ky: u32, nup: u32,
+                                      l: u32, dz: *const f64,
+                                      out: *mut CsrHandle<CComplex<f64>>)
+                                      -> Status {
+    run_matrix(out, || {
+        consv::ks::check(Dim(nx), Dim(ny), K(kx), K(ky), nup)?;
+        check_bond(I(l as i32))?;
+        let d = ffi::array("dz", dz, 3)?;
+        let dz = [d[0], d[1], d[2]];
+        Ok(consv::ks::h_ss_dm_z(Dim(nx),
+                                Dim(ny),
+                                K(kx),
+                                K(ky),
+                                nup,
+                                I(l as i32),
+                                &dz))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ks_ss_z(nx: u32, ny: u32, kx: u32, ky: u32, nup: u32,
+                                 l: u32, out: *mut CsrHandle<CComplex<f64>>)
+                                 -> Status {
+    run_matrix(out, || {
+        consv::ks::check(Dim(nx), Dim(ny), K(kx), K(ky), nup)?;
+        check_bond(I(l as i32))?;
+        Ok(consv::ks::ss_z(Dim(nx), Dim(ny), K(kx), K(ky), nup, I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ks_ss_xy(nx: u32, ny: u32, kx: u32, ky: u32, nup: u32,
+                                  l: u32, out: *mut CsrHandle<CComplex<f64>>)
+                                  -> Status {
+    run_matrix(out, || {
+        consv::ks::check(Dim(nx), Dim(ny), K(kx), K(ky), nup)?;
+        check_bond(I(l as i32))?;
+        Ok(consv::ks::ss_xy(Dim(nx), Dim(ny), K(kx), K(ky), nup, I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ks_chi_chi(nx: u32, ny: u32, kx: u32, ky: u32, nup: u32,
+                                    dx: u32, dy: u32, o1: u32, o2: u32,
+                                    out: *mut CsrHandle<CComplex<f64>>)
+                                    -> Status {
+    run_matrix(out, || {
+        consv::ks::check(Dim(nx), Dim(ny), K(kx), K(ky), nup)?;
+        Ok(consv::ks::chi_chi(Dim(nx),
+                              Dim(ny),
+                              K(kx),
+                              K(ky),
+                              nup,
+                              I(dx as i32),
+                              I(dy as i32),
+                              Orientation::from_raw(o1)?,
+                              Orientation::from_raw(o2)?))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ks_chi_order(nx: u32, ny: u32, kx: u32, ky: u32, nup: u32,
+                                      staggered: bool,
+                                      out: *mut CsrHandle<CComplex<f64>>)
+                                      -> Status {
+    run_matrix(out, || {
+        consv::ks::check(Dim(nx), Dim(ny), K(kx), K(ky), nup)?;
+        Ok(consv::ks::chi_order(Dim(nx), Dim(ny), K(kx), K(ky), nup, staggered))
+    })
+}
+
+// "parity" is that of the number of spins pointing up, 0 for even and 1 for
+// odd
+#[no_mangle]
+pub unsafe extern "C" fn kp_h_ss_z(nx: u32, ny: u32, kx: u32, ky: u32, parity: u32,
+                                   l: u32, out: *mut CsrHandle<CComplex<f64>>)
+                                   -> Status {
+    run_matrix(out, || {
+        consv::kp::check(Dim(nx), Dim(ny), K(kx), K(ky), parity)?;
+        check_bond(I(l as i32))?;
+        Ok(consv::kp::h_ss_z(Dim(nx), Dim(ny), K(kx), K(ky), parity, I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn kp_h_ss_xy(nx: u32, ny: u32, kx: u32, ky: u32, parity: u32,
+                                    l: u32, out: *mut CsrHandle<CComplex<f64>>)
+                                    -> Status {
+    run_matrix(out, || {
+        consv::kp::check(Dim(nx), Dim(ny), K(kx), K(ky), parity)?;
+        check_bond(I(l as i32))?;
+        Ok(consv::kp::h_ss_xy(Dim(nx), Dim(ny), K(kx), K(ky), parity, I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn kp_h_ss_ppmm(nx: u32, ny: u32, kx: u32, ky: u32,
+                                      parity: u32, l: u32,
+                                      out: *mut CsrHandle<CComplex<f64>>)
+                                      -> Status {
+    run_matrix(out, || {
+        consv::kp::check(Dim(nx), Dim(ny), K(kx), K(ky), parity)?;
+        check_bond(I(l as i32))?;
+        Ok(consv::kp::h_ss_ppmm(Dim(nx),
+                                Dim(ny),
+                                K(kx),
+                                K(ky),
+                                parity,
+                                I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn kr_h_ss_z(nx: u32, ny: u32, t1x: i32, t1y: i32, t2x: i32,
+                                   t2y: i32, kx: u32, ky: u32, l: u32,
+                                   out: *mut CsrHandle<CComplex<f64>>)
+                                   -> Status {
+    let (t1, t2) = ((I(t1x), I(t1y)), (I(t2x), I(t2y)));
+    run_matrix(out, || {
+        consv::k::check(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        check_bond(I(l as i32))?;
+        Ok(consv::kr::h_ss_z(Dim(nx), Dim(ny), t1, t2, K(kx), K(ky), I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn kr_h_ss_xy(nx: u32, ny: u32, t1x: i32, t1y: i32, t2x: i32,
+                                    t2y: i32, kx: u32, ky: u32, l: u32,
+                                    out: *mut CsrHandle<CComplex<f64>>)
+                                    -> Status {
+    let (t1, t2) = ((I(t1x), I(t1y)), (I(t2x), I(t2y)));
+    run_matrix(out, || {
+        consv::k::check(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        check_bond(I(l as i32))?;
+        Ok(consv::kr::h_ss_xy(Dim(nx), Dim(ny), t1, t2, K(kx), K(ky), I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn kr_h_ss_ppmm(nx: u32, ny: u32, t1x: i32, t1y: i32, t2x: i32,
+                                      t2y: i32, kx: u32, ky: u32, l: u32,
+                                      out: *mut CsrHandle<CComplex<f64>>)
+                                      -> Status {
+    let (t1, t2) = ((I(t1x), I(t1y)), (I(t2x), I(t2y)));
+    run_matrix(out, || {
+        consv::k::check(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        check_bond(I(l as i32))?;
+        Ok(consv::kr::h_ss_ppmm(Dim(nx), Dim(ny), t1, t2, K(kx), K(ky), I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn kr_h_ss_pmz(nx: u32, ny: u32, t1x: i32, t1y: i32, t2x: i32,
+                                     t2y: i32, kx: u32, ky: u32, l: u32,
+                                     out: *mut CsrHandle<CComplex<f64>>)
+                                     -> Status {
+    let (t1, t2) = ((I(t1x), I(t1y)), (I(t2x), I(t2y)));
+    run_matrix(out, || {
+        consv::k::check(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        check_bond(I(l as i32))?;
+        Ok(consv::kr::h_ss_pmz(Dim(nx), Dim(ny), t1, t2, K(kx), K(ky), I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn kr_h_sss_chi(nx: u32, ny: u32, t1x: i32, t1y: i32, t2x: i32,
+                                      t2y: i32, kx: u32, ky: u32,
+                                      out: *mut CsrHandle<CComplex<f64>>)
+                                      -> Status {
+    let (t1, t2) = ((I(t1x), I(t1y)), (I(t2x), I(t2y)));
+    run_matrix(out, || {
+        consv::k::check(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        Ok(consv::kr::h_sss_chi(Dim(nx), Dim(ny), t1, t2, K(kx), K(ky)))
+    })
+}
+
+// "fields" holds one value of h per sublattice
+#[no_mangle]
+pub unsafe extern "C" fn kr_h_s_z(nx: u32, ny: u32, t1x: i32, t1y: i32, t2x: i32,
+                                  t2y: i32, kx: u32, ky: u32, fields: *const f64,
+                                  nfields: size_t,
+                                  out: *mut CsrHandle<CComplex<f64>>)
+                                  -> Status {
+    let (t1, t2) = ((I(t1x), I(t1y)), (I(t2x), I(t2y)));
+    run_matrix(out, || {
+        consv::k::check(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        consv::kr::check_fields(Dim(nx), Dim(ny), t1, t2, nfields)?;
+        let fields = ffi::array("fields", fields, nfields)?;
+        Ok(consv::kr::h_s_z(Dim(nx), Dim(ny), t1, t2, K(kx), K(ky), fields))
+    })
+}
+
+// "fields" holds the pairs (hx, hy) for each sublattice flattened into a single
+// array of length 2 * nfields
+#[no_mangle]
+pub unsafe extern "C" fn kr_h_s_xy(nx: u32, ny: u32, t1x: i32, t1y: i32, t2x: i32,
+                                   t2y: i32, kx: u32, ky: u32, fields: *const f64,
+                                   nfields: size_t,
+                                   out: *mut CsrHandle<CComplex<f64>>)
+                                   -> Status {
+    let (t1, t2) = ((I(t1x), I(t1y)), (I(t2x), I(t2y)));
+    run_matrix(out, || {
+        consv::k::check(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        consv::kr::check_fields(Dim(nx), Dim(ny), t1, t2, nfields)?;
+        let fields = ffi::array("fields", fields, 2 * nfields)?;
+        let fields = fields.chunks(2).map(|h| (h[0], h[1])).collect::<Vec<_>>();
+        Ok(consv::kr::h_s_xy(Dim(nx), Dim(ny), t1, t2, K(kx), K(ky), &fields))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ksr_h_ss_z(nx: u32, ny: u32, t1x: i32, t1y: i32, t2x: i32,
+                                    t2y: i32, kx: u32, ky: u32, nup: u32, l: u32,
+                                    out: *mut CsrHandle<CComplex<f64>>)
+                                    -> Status {
+    let (t1, t2) = ((I(t1x), I(t1y)), (I(t2x), I(t2y)));
+    run_matrix(out, || {
+        consv::ks::check(Dim(nx), Dim(ny), K(kx), K(ky), nup)?;
+        check_bond(I(l as i32))?;
+        Ok(consv::ksr::h_ss_z(Dim(nx),
+                              Dim(ny),
+                              t1,
+                              t2,
+                              K(kx),
+                              K(ky),
+                              nup,
+                              I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ksr_h_ss_xy(nx: u32, ny: u32, t1x: i32, t1y: i32, t2x: i32,
+                                     t2y: i32, kx: u32, ky: u32, nup: u32, l: u32,
+                                     out: *mut CsrHandle<CComplex<f64>>)
+                                     -> Status {
+    let (t1, t2) = ((I(t1x), I(t1y)), (I(t2x), I(t2y)));
+    run_matrix(out, || {
+        consv::ks::check(Dim(nx), Dim(ny), K(kx), K(ky), nup)?;
+        check_bond(I(l as i32))?;
+        Ok(consv::ksr::h_ss_xy(Dim(nx),
+                               Dim(ny),
+                               t1,
+                               t2,
+                               K(kx),
+                               K(ky),
+                               nup,
+                               I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ksr_h_sss_chi(nx: u32, ny: u32, t1x: i32, t1y: i32,
+                                       t2x: i32, t2y: i32, kx: u32, ky: u32,
+                                       nup: u32, out: *mut CsrHandle<CComplex<f64>>)
+                                       -> Status {
+    let (t1, t2) = ((I(t1x), I(t1y)), (I(t2x), I(t2y)));
+    run_matrix(out, || {
+        consv::ks::check(Dim(nx), Dim(ny), K(kx), K(ky), nup)?;
+        Ok(consv::ksr::h_sss_chi(Dim(nx), Dim(ny), t1, t2, K(kx), K(ky), nup))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ksr_h_s_z(nx: u32, ny: u32, t1x: i32, t1y: i32, t2x: i32,
+                                   t2y: i32, kx: u32, ky: u32, nup: u32,
+                                   fields: *const f64, nfields: size_t,
+                                   out: *mut CsrHandle<CComplex<f64>>)
+                                   -> Status {
+    let (t1, t2) = ((I(t1x), I(t1y)), (I(t2x), I(t2y)));
+    run_matrix(out, || {
+        consv::ks::check(Dim(nx), Dim(ny), K(kx), K(ky), nup)?;
+        consv::kr::check_fields(Dim(nx), Dim(ny), t1, t2, nfields)?;
+        let fields = ffi::array("fields", fields, nfields)?;
+        Ok(consv::ksr::h_s_z(Dim(nx), Dim(ny), t1, t2, K(kx), K(ky), nup, fields))
+    })
+}
+
+/// Sets the number of threads used to build bases and matrices. 0 restores the
+/// default of one thread per logical core.
+#[no_mangle]
+pub extern "C" fn set_num_threads(n: u32) { common::set_num_threads(n as usize) }
+
+/// The message describing the last failed call on the calling thread, or null
+/// if none has failed. The string is owned by Rust and stays valid until the
+/// next failed call on the same thread.
+#[no_mangle]
+pub extern "C" fn last_error_message() -> *const c_char { ffi::last_error_message() }
+
+#[no_mangle]
+pub unsafe extern "C" fn s_h_ss_z(nx: u32, ny: u32, nup: u32, l: u32,
+                                  out: *mut CsrHandle<CComplex<f64>>)
+                                  -> Status {
+    run_matrix(out, || {
+        consv::s::check(Dim(nx), Dim(ny), nup)?;
+        check_bond(I(l as i32))?;
+        Ok(consv::s::h_ss_z(Dim(nx), Dim(ny), nup, I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn s_h_ss_xy(nx: u32, ny: u32, nup: u32, l: u32,
+                                   out: *mut CsrHandle<CComplex<f64>>)
+                                   -> Status {
+    run_matrix(out, || {
+        consv::s::check(Dim(nx), Dim(ny), nup)?;
+        check_bond(I(l as i32))?;
+        Ok(consv::s::h_ss_xy(Dim(nx), Dim(ny), nup, I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn s_h_sss_chi(nx: u32, ny: u32, nup: u32,
+                                     out: *mut CsrHandle<CComplex<f64>>)
+                                     -> Status {
+    run_matrix(out, || {
+        consv::s::check(Dim(nx), Dim(ny), nup)?;
+        Ok(consv::s::h_sss_chi(Dim(nx), Dim(ny), nup))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn s_h_ring(nx: u32, ny: u32, nup: u32,
+                                  out: *mut CsrHandle<CComplex<f64>>)
+                                  -> Status {
+    run_matrix(out, || {
+        consv::s::check(Dim(nx), Dim(ny), nup)?;
+        Ok(consv::s::h_ring(Dim(nx), Dim(ny), nup))
+    })
+}
+
+// "dz" holds D_z for each of the three bond directions
+#[no_mangle]
+pub unsafe extern "C" fn s_h_ss_dm_z(nx: u32, ny: u32, nup: u32, l: u32,
+                                     dz: *const f64,
+                                     out: *mut CsrHandle<CComplex<f64>>)
+                                     -> Status {
+    run_matrix(out, || {
+        consv::s::check(Dim(nx), Dim(ny), nup)?;
+        check_bond(I(l as i32))?;
+        let d = ffi::array("dz", dz, 3)?;
+        let dz = [d[0], d[1], d[2]];
+        Ok(consv::s::h_ss_dm_z(Dim(nx), Dim(ny), nup, I(l as i32), &dz))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn s_ss_z(nx: u32, ny: u32, nup: u32, l: u32,
+                                out: *mut CsrHandle<CComplex<f64>>)
+                                -> Status {
+    run_matrix(out, || {
+        consv::s::check(Dim(nx), Dim(ny), nup)?;
+        check_bond(I(l as i32))?;
+        Ok(consv::s::ss_z(Dim(nx), Dim(ny), nup, I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn s_ss_xy(nx: u32, ny: u32, nup: u32, l: u32,
+                                 out: *mut CsrHandle<CComplex<f64>>)
+                                 -> Status {
+    run_matrix(out, || {
+        consv::s::check(Dim(nx), Dim(ny), nup)?;
+        check_bond(I(l as i32))?;
+        Ok(consv::s::ss_xy(Dim(nx), Dim(ny), nup, I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn s_chi_chi(nx: u32, ny: u32, nup: u32, dx: u32, dy: u32,
+                                   o1: u32, o2: u32,
+                                   out: *mut CsrHandle<CComplex<f64>>)
+                                   -> Status {
+    run_matrix(out, || {
+        consv::s::check(Dim(nx), Dim(ny), nup)?;
+        Ok(consv::s::chi_chi(Dim(nx),
+                             Dim(ny),
+                             nup,
+                             I(dx as i32),
+                             I(dy as i32),
+                             Orientation::from_raw(o1)?,
+                             Orientation::from_raw(o2)?))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn s_chi_order(nx: u32, ny: u32, nup: u32, staggered: bool,
+                                     out: *mut CsrHandle<CComplex<f64>>)
+                                     -> Status {
+    run_matrix(out, || {
+        consv::s::check(Dim(nx), Dim(ny), nup)?;
+        Ok(consv::s::chi_order(Dim(nx), Dim(ny), nup, staggered))
+    })
+}
+
+// The bonds (i, j) with a coupling each, where "sites" holds the site indices
+// flattened into a single array of length 2 * nbonds and "couplings" holds the
+// nbonds couplings
+unsafe fn s_bonds(nx: u32, ny: u32, sites: *const u32, couplings: *const f64,
+                  nbonds: size_t)
+                  -> Result<Vec<(u32, u32, f64)>, ffi::Error> {
+    let sites = ffi::array("sites", sites, 2 * nbonds)?;
+    consv::s::check_sites(Dim(nx), Dim(ny), sites)?;
+    let couplings = ffi::array("couplings", couplings, nbonds)?;
+    Ok(sites.chunks(2)
+            .zip(couplings.iter())
+            .map(|(s, &j)| (s[0], s[1], j))
+            .collect())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn s_bonds_ss_z(nx: u32, ny: u32, nup: u32, sites: *const u32,
+                                      couplings: *const f64, nbonds: size_t,
+                                      out: *mut CsrHandle<CComplex<f64>>)
+                                      -> Status {
+    run_matrix(out, || {
+        consv::s::check(Dim(nx), Dim(ny), nup)?;
+        let bonds = s_bonds(nx, ny, sites, couplings, nbonds)?;
+        Ok(consv::s::bonds_ss_z(Dim(nx), Dim(ny), nup, &bonds))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn s_bonds_ss_xy(nx: u32, ny: u32, nup: u32, sites: *const u32,
+                                       couplings: *const f64, nbonds: size_t,
+                                       out: *mut CsrHandle<CComplex<f64>>)
+                                       -> Status {
+    run_matrix(out, || {
+        consv::s::check(Dim(nx), Dim(ny), nup)?;
+        let bonds = s_bonds(nx, ny, sites, couplings, nbonds)?;
+        Ok(consv::s::bonds_ss_xy(Dim(nx), Dim(ny), nup, &bonds))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn s_bonds_ss_dm_z(nx: u32, ny: u32, nup: u32,
+                                         sites: *const u32, couplings: *const f64,
+                                         nbonds: size_t,
+                                         out: *mut CsrHandle<CComplex<f64>>)
+                                         -> Status {
+    run_matrix(out, || {
+        consv::s::check(Dim(nx), Dim(ny), nup)?;
+        let bonds = s_bonds(nx, ny, sites, couplings, nbonds)?;
+        Ok(consv::s::bonds_ss_dm_z(Dim(nx), Dim(ny), nup, &bonds))
+    })
+}
+
+// "sites" holds the triangles (i, j, k) flattened into a single array of length
+// 3 * ntriangles and "couplings" the chirality coupling of each
+#[no_mangle]
+pub unsafe extern "C" fn s_triangles_sss_chi(nx: u32, ny: u32, nup: u32,
+                                             sites: *const u32,
+                                             couplings: *const f64,
+                                             ntriangles: size_t,
+                                             out: *mut CsrHandle<CComplex<f64>>)
+                                             -> Status {
+    run_matrix(out, || {
+        consv::s::check(Dim(nx), Dim(ny), nup)?;
+        let sites = ffi::array("sites", sites, 3 * ntriangles)?;
+        consv::s::check_sites(Dim(nx), Dim(ny), sites)?;
+        let couplings = ffi::array("couplings", couplings, ntriangles)?;
+        let triangles = sites.chunks(3)
+                             .zip(couplings.iter())
+                             .map(|(s, &chi)| (s[0], s[1], s[2], chi))
+                             .collect::<Vec<_>>();
+        Ok(consv::s::triangles_sss_chi(Dim(nx), Dim(ny), nup, &triangles))
+    })
+}
+
+// "fields" holds one value of h per site
+#[no_mangle]
+pub unsafe extern "C" fn s_h_s_z(nx: u32, ny: u32, nup: u32, fields: *const f64,
+                                 nfields: size_t,
+                                 out: *mut CsrHandle<CComplex<f64>>)
+                                 -> Status {
+    run_matrix(out, || {
+        consv::s::check(Dim(nx), Dim(ny), nup)?;
+        consv::s::check_fields(Dim(nx), Dim(ny), nfields)?;
+        let fields = ffi::array("fields", fields, nfields)?;
+        Ok(consv::s::h_s_z(Dim(nx), Dim(ny), nup, fields))
+    })
+}
+
+// Real counterparts of the functions above for momenta where all Bloch phases
+// are ±1, i.e. 2 * kx % nx == 0 and 2 * ky % ny == 0
+#[no_mangle]
+pub unsafe extern "C" fn k_h_ss_z_real(nx: u32, ny: u32, kx: u32, ky: u32, l: u32,
+                                       out: *mut CsrHandle<f64>)
+                                       -> Status {
+    run_matrix(out, || {
+        consv::k::check(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        check_real_momentum(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        check_bond(I(l as i32))?;
+        Ok(consv::k::h_ss_z_real(Dim(nx), Dim(ny), K(kx), K(ky), I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn k_h_ss_xy_real(nx: u32, ny: u32, kx: u32, ky: u32, l: u32,
+                                        out: *mut CsrHandle<f64>)
+                                        -> Status {
+    run_matrix(out, || {
+        consv::k::check(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        check_real_momentum(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        check_bond(I(l as i32))?;
+        Ok(consv::k::h_ss_xy_real(Dim(nx), Dim(ny), K(kx), K(ky), I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn k_h_ring_real(nx: u32, ny: u32, kx: u32, ky: u32,
+                                       out: *mut CsrHandle<f64>)
+                                       -> Status {
+    run_matrix(out, || {
+        consv::k::check(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        check_real_momentum(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        Ok(consv::k::h_ring_real(Dim(nx), Dim(ny), K(kx), K(ky)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn k_ss_z_real(nx: u32, ny: u32, kx: u32, ky: u32, l: u32,
+                                     out: *mut CsrHandle<f64>)
+                                     -> Status {
+    run_matrix(out, || {
+        consv::k::check(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        check_real_momentum(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        check_bond(I(l as i32))?;
+        Ok(consv::k::ss_z_real(Dim(nx), Dim(ny), K(kx), K(ky), I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn k_ss_xy_real(nx: u32, ny: u32, kx: u32, ky: u32, l: u32,
+                                      out: *mut CsrHandle<f64>)
+                                      -> Status {
+    run_matrix(out, || {
+        consv::k::check(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        check_real_momentum(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        check_bond(I(l as i32))?;
+        Ok(consv::k::ss_xy_real(Dim(nx), Dim(ny), K(kx), K(ky), I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ks_h_ss_z_real(nx: u32, ny: u32, kx: u32, ky: u32, nup: u32,
+                                        l: u32, out: *mut CsrHandle<f64>)
+                                        -> Status {
+    run_matrix(out, || {
+        consv::ks::check(Dim(nx), Dim(ny), K(kx), K(ky), nup)?;
+        check_real_momentum(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        check_bond(I(l as i32))?;
+        Ok(consv::ks::h_ss_z_real(Dim(nx), Dim(ny), K(kx), K(ky), nup, I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ks_h_ss_xy_real(nx: u32, ny: u32, kx: u32, ky: u32,
+                                         nup: u32, l: u32, out: *mut CsrHandle<f64>)
+                                         -> Status {
+    run_matrix(out, || {
+        consv::ks::check(Dim(nx), Dim(ny), K(kx), K(ky), nup)?;
+        check_real_momentum(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        check_bond(I(l as i32))?;
+        Ok(consv::ks::h_ss_xy_real(Dim(nx), Dim(ny), K(kx), K(ky), nup, I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ks_h_ring_real(nx: u32, ny: u32, kx: u32, ky: u32, nup: u32,
+                                        out: *mut CsrHandle<f64>)
+                                        -> Status {
+    run_matrix(out, || {
+        consv::ks::check(Dim(nx), Dim(ny), K(kx), K(ky), nup)?;
+        check_real_momentum(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        Ok(consv::ks::h_ring_real(Dim(nx), Dim(ny), K(kx), K(ky), nup))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ks_ss_z_real(nx: u32, ny: u32, kx: u32, ky: u32, nup: u32,
+                                      l: u32, out: *mut CsrHandle<f64>)
+                                      -> Status {
+    run_matrix(out, || {
+        consv::ks::check(Dim(nx), Dim(ny), K(kx), K(ky), nup)?;
+        check_real_momentum(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        check_bond(I(l as i32))?;
+        Ok(consv::ks::ss_z_real(Dim(nx), Dim(ny), K(kx), K(ky), nup, I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ks_ss_xy_real(nx: u32, ny: u32, kx: u32, ky: u32, nup: u32,
+                                       l: u32, out: *mut CsrHandle<f64>)
+                                       -> Status {
+    run_matrix(out, || {
+        consv::ks::check(Dim(nx), Dim(ny), K(kx), K(ky), nup)?;
+        check_real_momentum(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        check_bond(I(l as i32))?;
+        Ok(consv::ks::ss_xy_real(Dim(nx), Dim(ny), K(kx), K(ky), nup, I(l as i32)))
+    })
+}
+
+// Sectors are handed to the caller as opaque pointers. Each one must be
+// returned to sector_free exactly once
+#[no_mangle]
+pub unsafe extern "C" fn sector_k(nx: u32, ny: u32, kx: u32, ky: u32,
+                                  out: *mut *mut Sector)
+                                  -> Status {
+    run(out, || {
+        consv::k::check(Dim(nx), Dim(ny), K(kx), K(ky))?;
+        let sector = Sector::k(Dim(nx), Dim(ny), K(kx), K(ky));
+        Ok(Box::into_raw(Box::new(sector)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sector_ks(nx: u32, ny: u32, kx: u32, ky: u32, nup: u32,
+                                   out: *mut *mut Sector)
+                                   -> Status {
+    run(out, || {
+        consv::ks::check(Dim(nx), Dim(ny), K(kx), K(ky), nup)?;
+        let sector = Sector::ks(Dim(nx), Dim(ny), K(kx), K(ky), nup);
+        Ok(Box::into_raw(Box::new(sector)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sector_free(sector: *mut Sector) {
+    // like free, a null pointer is ignored
+    if !sector.is_null() {
+        drop(Box::from_raw(sector));
+    }
+}
+
+// the dimension of the sector, or 0 for a null pointer
+#[no_mangle]
+pub unsafe extern "C" fn sector_dim(sector: *const Sector) -> u32 {
+    sector.as_ref().map_or(0, |s| s.dim())
+}
+
+unsafe fn path_str<'a>(path: *const c_char) -> Result<&'a str, ffi::Error> {
+    if path.is_null() {
+        return Err(ffi::Error::Null("path"));
+    }
+    Ok(CStr::from_ptr(path).to_str().map_err(|_| {
+        io::Error::new(io::ErrorKind::InvalidInput, "path is not valid UTF-8")
+    })?)
+}
+
+// saves the basis to path in .npz format
+#[no_mangle]
+pub unsafe extern "C" fn sector_save(sector: *const Sector, path: *const c_char)
+                                     -> Status {
+    // nothing is written out but run still wants somewhere to put ()
+    run(&mut (), || {
+        let sector = ffi::object("sector", sector)?;
+        Ok(sector.save(path_str(path)?)?)
+    })
+}
+
+// loads a basis saved by sector_save. The result is freed with sector_free like
+// any other sector
+#[no_mangle]
+pub unsafe extern "C" fn sector_load(path: *const c_char, out: *mut *mut Sector)
+                                     -> Status {
+    run(out, || {
+        let sector = Sector::load(path_str(path)?)?;
+        Ok(Box::into_raw(Box::new(sector)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sector_h_ss_z(sector: *const Sector, l: u32,
+                                       out: *mut CsrHandle<CComplex<f64>>)
+                                       -> Status {
+    run_matrix(out, || {
+        let sector = ffi::object("sector", sector)?;
+        check_bond(I(l as i32))?;
+        Ok(sector.h_ss_z(I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sector_h_ss_xy(sector: *const Sector, l: u32,
+                                        out: *mut CsrHandle<CComplex<f64>>)
+                                        -> Status {
+    run_matrix(out, || {
+        let sector = ffi::object("sector", sector)?;
+        check_bond(I(l as i32))?;
+        Ok(sector.h_ss_xy(I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sector_h_ss_ppmm(sector: *const Sector, l: u32,
+                                          out: *mut CsrHandle<CComplex<f64>>)
+                                          -> Status {
+    run_matrix(out, || {
+        let sector = ffi::object("sector", sector)?;
+        check_bond(I(l as i32))?;
+        Ok(sector.h_ss_ppmm(I(l as i32))?)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sector_h_ss_pmz(sector: *const Sector, l: u32,
+                                         out: *mut CsrHandle<CComplex<f64>>)
+                                         -> Status {
+    run_matrix(out, || {
+        let sector = ffi::object("sector", sector)?;
+        check_bond(I(l as i32))?;
+        Ok(sector.h_ss_pmz(I(l as i32))?)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sector_h_sss_chi(sector: *const Sector,
+                                          out: *mut CsrHandle<CComplex<f64>>)
+                                          -> Status {
+    run_matrix(out, || {
+        let sector = ffi::object("sector", sector)?;
+        Ok(sector.h_sss_chi())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sector_h_ring(sector: *const Sector,
+                                       out: *mut CsrHandle<CComplex<f64>>)
+                                       -> Status {
+    run_matrix(out, || {
+        let sector = ffi::object("sector", sector)?;
+        Ok(sector.h_ring())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sector_h_ss_tensor(sector: *const Sector, l: u32,
+                                            jmats: *const f64,
+                                            out: *mut CsrHandle<CComplex<f64>>)
+                                            -> Status {
+    run_matrix(out, || {
+        let sector = ffi::object("sector", sector)?;
+        check_bond(I(l as i32))?;
+        let j = ffi::array("jmats", jmats, 27)?;
+        let mut jmats = [[[0.; 3]; 3]; 3];
+        for (i, &x) in j.iter().enumerate() {
+            jmats[i / 9][(i / 3) % 3][i % 3] = x;
+        }
+        Ok(sector.h_ss_tensor(I(l as i32), &jmats)?)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sector_h_ss_dm(sector: *const Sector, l: u32,
+                                        dvecs: *const f64,
+                                        out: *mut CsrHandle<CComplex<f64>>)
+                                        -> Status {
+    run_matrix(out, || {
+        let sector = ffi::object("sector", sector)?;
+        check_bond(I(l as i32))?;
+        let d = ffi::array("dvecs", dvecs, 9)?;
+        let dvecs = [[d[0], d[1], d[2]], [d[3], d[4], d[5]], [d[6], d[7], d[8]]];
+        Ok(sector.h_ss_dm(I(l as i32), &dvecs)?)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sector_ss_z(sector: *const Sector, l: u32,
+                                     out: *mut CsrHandle<CComplex<f64>>)
+                                     -> Status {
+    run_matrix(out, || {
+        let sector = ffi::object("sector", sector)?;
+        check_bond(I(l as i32))?;
+        Ok(sector.ss_z(I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sector_ss_xy(sector: *const Sector, l: u32,
+                                      out: *mut CsrHandle<CComplex<f64>>)
+                                      -> Status {
+    run_matrix(out, || {
+        let sector = ffi::object("sector", sector)?;
+        check_bond(I(l as i32))?;
+        Ok(sector.ss_xy(I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sector_chi_chi(sector: *const Sector, dx: u32, dy: u32,
+                                        o1: u32, o2: u32,
+                                        out: *mut CsrHandle<CComplex<f64>>)
+                                        -> Status {
+    run_matrix(out, || {
+        let sector = ffi::object("sector", sector)?;
+        Ok(sector.chi_chi(I(dx as i32),
+                          I(dy as i32),
+                          Orientation::from_raw(o1)?,
+                          Orientation::from_raw(o2)?))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sector_chi_order(sector: *const Sector, staggered: bool,
+                                          out: *mut CsrHandle<CComplex<f64>>)
+                                          -> Status {
+    run_matrix(out, || {
+        let sector = ffi::object("sector", sector)?;
+        Ok(sector.chi_order(staggered))
+    })
+}
+
+// Single precision variants of the above for eigensolvers that are limited by
+// memory bandwidth. Elements are computed in double precision and rounded
+// once, and the matrices share the basis and the sparsity pattern of their
+// double precision counterparts, so vectors found with them are good starting
+// points for refining in double precision. They exist for sectors only:
+// the one-off k_, ks_ and real builders above rebuild the basis on every call
+// and have no single precision variants
+#[no_mangle]
+pub unsafe extern "C" fn sector_h_ss_z_f32(sector: *const Sector, l: u32,
+                                           out: *mut CsrHandle<CComplex<f32>>)
+                                           -> Status {
+    run_matrix(out, || {
+        let sector = ffi::object("sector", sector)?;
+        check_bond(I(l as i32))?;
+        Ok(sector.h_ss_z(I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sector_h_ss_xy_f32(sector: *const Sector, l: u32,
+                                            out: *mut CsrHandle<CComplex<f32>>)
+                                            -> Status {
+    run_matrix(out, || {
+        let sector = ffi::object("sector", sector)?;
+        check_bond(I(l as i32))?;
+        Ok(sector.h_ss_xy(I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sector_h_ss_ppmm_f32(sector: *const Sector, l: u32,
+                                              out: *mut CsrHandle<CComplex<f32>>)
+                                              -> Status {
+    run_matrix(out, || {
+        let sector = ffi::object("sector", sector)?;
+        check_bond(I(l as i32))?;
+        Ok(sector.h_ss_ppmm(I(l as i32))?)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sector_h_ss_pmz_f32(sector: *const Sector, l: u32,
+                                             out: *mut CsrHandle<CComplex<f32>>)
+                                             -> Status {
+    run_matrix(out, || {
+        let sector = ffi::object("sector", sector)?;
+        check_bond(I(l as i32))?;
+        Ok(sector.h_ss_pmz(I(l as i32))?)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sector_h_sss_chi_f32(sector: *const Sector,
+                                              out: *mut CsrHandle<CComplex<f32>>)
+                                              -> Status {
+    run_matrix(out, || {
+        let sector = ffi::object("sector", sector)?;
+        Ok(sector.h_sss_chi())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sector_h_ring_f32(sector: *const Sector,
+                                           out: *mut CsrHandle<CComplex<f32>>)
+                                           -> Status {
+    run_matrix(out, || {
+        let sector = ffi::object("sector", sector)?;
+        Ok(sector.h_ring())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sector_h_ss_tensor_f32(sector: *const Sector, l: u32,
+                                                jmats: *const f64,
+                                                out: *mut CsrHandle<CComplex<f32>>)
+                                                -> Status {
+    run_matrix(out, || {
+        let sector = ffi::object("sector", sector)?;
+        check_bond(I(l as i32))?;
+        let j = ffi::array("jmats", jmats, 27)?;
+        let mut jmats = [[[0.; 3]; 3]; 3];
+        for (i, &x) in j.iter().enumerate() {
+            jmats[i / 9][(i / 3) % 3][i % 3] = x;
+        }
+        Ok(sector.h_ss_tensor(I(l as i32), &jmats)?)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sector_h_ss_dm_f32(sector: *const Sector, l: u32,
+                                            dvecs: *const f64,
+                                            out: *mut CsrHandle<CComplex<f32>>)
+                                            -> Status {
+    run_matrix(out, || {
+        let sector = ffi::object("sector", sector)?;
+        check_bond(I(l as i32))?;
+        let d = ffi::array("dvecs", dvecs, 9)?;
+        let dvecs = [[d[0], d[1], d[2]], [d[3], d[4], d[5]], [d[6], d[7], d[8]]];
+        Ok(sector.h_ss_dm(I(l as i32), &dvecs)?)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sector_ss_z_f32(sector: *const Sector, l: u32,
+                                         out: *mut CsrHandle<CComplex<f32>>)
+                                         -> Status {
+    run_matrix(out, || {
+        let sector = ffi::object("sector", sector)?;
+        check_bond(I(l as i32))?;
+        Ok(sector.ss_z(I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sector_ss_xy_f32(sector: *const Sector, l: u32,
+                                          out: *mut CsrHandle<CComplex<f32>>)
+                                          -> Status {
+    run_matrix(out, || {
+        let sector = ffi::object("sector", sector)?;
+        check_bond(I(l as i32))?;
+        Ok(sector.ss_xy(I(l as i32)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sector_chi_chi_f32(sector: *const Sector, dx: u32, dy: u32,
+                                            o1: u32, o2: u32,
+                                            out: *mut CsrHandle<CComplex<f32>>)
+                                            -> Status {
+    run_matrix(out, || {
+        let sector = ffi::object("sector", sector)?;
+        Ok(sector.chi_chi(I(dx as i32),
+                          I(dy as i32),
+                          Orientation::from_raw(o1)?,
+                          Orientation::from_raw(o2)?))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sector_chi_order_f32(sector: *const Sector, staggered: bool,
+                                              out: *mut CsrHandle<CComplex<f32>>)
+                                              -> Status {
+    run_matrix(out, || {
+        let sector = ffi::object("sector", sector)?;
+        Ok(sector.chi_order(staggered))
+    })
+}
+
+// Orbits are handed out the same way as sectors and must be returned to
+// orbits_free. Sectors built from them are independent and may outlive them
+#[no_mangle]
+pub unsafe extern "C" fn orbits_k(nx: u32, ny: u32, out: *mut *mut Orbits)
+                                  -> Status {
+    run(out, || {
+        check_full_lattice(Dim(nx), Dim(ny))?;
+        Ok(Box::into_raw(Box::new(Orbits::new(Dim(nx), Dim(ny)))))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn orbits_ks(nx: u32, ny: u32, nup: u32, out: *mut *mut Orbits)
+                                   -> Status {
+    run(out, || {
+        check_lattice(Dim(nx), Dim(ny))?;
+        check_nup(Dim(nx), Dim(ny), nup)?;
+        Ok(Box::into_raw(Box::new(Orbits::with_nup(Dim(nx), Dim(ny), nup))))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn orbits_free(orbits: *mut Orbits) {
+    if !orbits.is_null() {
+        drop(Box::from_raw(orbits));
+    }
+}
+
+// the number of orbits, or 0 for a null pointer
+#[no_mangle]
+pub unsafe extern "C" fn orbits_len(orbits: *const Orbits) -> u64 {
+    orbits.as_ref().map_or(0, |o| o.norbits() as u64)
+}
+
 #[no_mangle]
-pub extern "C" fn k_h_ss_z(nx: u32, ny: u32, kx: u32, ky: u32, l: u32)
-                           -> CoordMatrix<CComplex<f64>> {
-    consv::k::h_ss_z(Dim(nx), Dim(ny), K(kx), K(ky), I(l as i32))
+pub unsafe extern "C" fn orbits_sector(orbits: *const Orbits, kx: u32, ky: u32,
+                                       out: *mut *mut Sector)
+                                       -> Status {
+    run(out, || {
+        let orbits = ffi::object("orbits", orbits)?;
+        let (nx, ny) = orbits.lattice();
+        check_momentum(nx, ny, K(kx), K(ky))?;
+        Ok(Box::into_raw(Box::new(orbits.sector(K(kx), K(ky)))))
+    })
 }
 
+// writes one momentum out of each pair k, -k to "kx" and "ky" along with the
+// number of momenta it stands for to "multiplicity". Each buffer holds "len"
+// entries, of which nx * ny always suffice, and the number of momenta written
+// goes to "out"
 #[no_mangle]
-pub extern "C" fn k_h_ss_xy(nx: u32, ny: u32, kx: u32, ky: u32, l: u32)
-                            -> CoordMatrix<CComplex<f64>> {
-    consv::k::h_ss_xy(Dim(nx), Dim(ny), K(kx), K(ky), I(l as i32))
+pub unsafe extern "C" fn irreducible_momenta(nx: u32, ny: u32, kx: *mut u32,
+                                             ky: *mut u32, multiplicity: *mut u32,
+                                             len: size_t, out: *mut size_t)
+                                             -> Status {
+    run(out, || {
+        check_lattice(Dim(nx), Dim(ny))?;
+        let momenta = common::irreducible_momenta(Dim(nx), Dim(ny));
+        let kxs = momenta.iter().map(|&(k, _, _)| k.raw_int()).collect::<Vec<_>>();
+        let kys = momenta.iter().map(|&(_, k, _)| k.raw_int()).collect::<Vec<_>>();
+        let ms = momenta.iter().map(|&(_, _, m)| m).collect::<Vec<_>>();
+        ffi::copy_into("kx", &kxs, kx, len)?;
+        ffi::copy_into("ky", &kys, ky, len)?;
+        ffi::copy_into("multiplicity", &ms, multiplicity, len)?;
+        Ok(momenta.len())
+    })
 }
 
+// the sector at -k, built from the one at k
 #[no_mangle]
-pub extern "C" fn k_h_ss_ppmm(nx: u32, ny: u32, kx: u32, ky: u32, l: u32)
-                              -> CoordMatrix<CComplex<f64>> {
-    consv::k::h_ss_ppmm(Dim(nx), Dim(ny), K(kx), K(ky), I(l as i32))
+pub unsafe extern "C" fn sector_inverse(sector: *const Sector, out: *mut *mut Sector)
+                                        -> Status {
+    run(out, || {
+        let sector = ffi::object("sector", sector)?;
+        Ok(Box::into_raw(Box::new(sector.inverse())))
+    })
 }
 
+// "vectors" holds nvecs vectors of the sector laid out one after another. Their
+// images under inversion, in the basis of sector_inverse, are written to
+// "inverted", a buffer of "inverted_len" entries
 #[no_mangle]
-pub extern "C" fn k_h_ss_pmz(nx: u32, ny: u32, kx: u32, ky: u32, l: u32)
-                             -> CoordMatrix<CComplex<f64>> {
-    consv::k::h_ss_pmz(Dim(nx), Dim(ny), K(kx), K(ky), I(l as i32))
+pub unsafe extern "C" fn sector_invert(sector: *const Sector,
+                                       vectors: *const CComplex<f64>,
+                                       nvecs: size_t,
+                                       inverted: *mut CComplex<f64>,
+                                       inverted_len: size_t)
+                                       -> Status {
+    run(&mut (), || {
+        let sector = ffi::object("sector", sector)?;
+        let dim = sector.dim() as usize;
+        // vectors of an empty sector have no entries to invert
+        if dim == 0 {
+            return Ok(());
+        }
+        let len = nvecs.checked_mul(dim)
+                       .ok_or(InputError::VectorCount { count: nvecs, dim })?;
+        let vectors = ffi::array("vectors", vectors, len)?
+                          .chunks(dim)
+                          .map(|v| {
+                                   v.iter()
+                                    .map(|c| Complex::new(c.re, c.im))
+                                    .collect()
+                               })
+                          .collect::<Vec<Vec<_>>>();
+        let images = sector.invert(&vectors)
+                           .into_iter()
+                           .flatten()
+                           .map(CComplex::from_num_complex)
+                           .collect::<Vec<_>>();
+        ffi::copy_into("inverted", &images, inverted, inverted_len)
+    })
 }
 
+// A matrix handed out by any of the functions above is copied into arrays
+// owned by the caller in two steps: csr_dims tells how long the arrays have to
+// be and csr_fill fills them. The matrix must then be returned to request_free
+// (request_free_real for real matrices and request_free_f32 for single
+// precision ones) exactly once
 #[no_mangle]
-pub extern "C" fn k_h_sss_chi(nx: u32, ny: u32, kx: u32, ky: u32)
-                              -> CoordMatrix<CComplex<f64>> {
-    consv::k::h_sss_chi(Dim(nx), Dim(ny), K(kx), K(ky))
+pub unsafe extern "C" fn csr_dims(mat: *const CsrHandle<CComplex<f64>>,
+                                  out: *mut CsrDims)
+                                  -> Status {
+    run(out, || Ok(ffi::dims(ffi::object("mat", mat)?)))
 }
 
 #[no_mangle]
-pub extern "C" fn k_ss_z(nx: u32, ny: u32, kx: u32, ky: u32, l: u32)
-                         -> CoordMatrix<CComplex<f64>> {
-    consv::k::ss_z(Dim(nx), Dim(ny), K(kx), K(ky), I(l as i32))
+pub unsafe extern "C" fn csr_dims_real(mat: *const CsrHandle<f64>,
+                                       out: *mut CsrDims)
+                                       -> Status {
+    run(out, || Ok(ffi::dims(ffi::object("mat", mat)?)))
 }
 
 #[no_mangle]
-pub extern "C" fn k_ss_xy(nx: u32, ny: u32, kx: u32, ky: u32, l: u32)
-                          -> CoordMatrix<CComplex<f64>> {
-    consv::k::ss_xy(Dim(nx), Dim(ny), K(kx), K(ky), I(l as i32))
+pub unsafe extern "C" fn csr_dims_f32(mat: *const CsrHandle<CComplex<f32>>,
+                                      out: *mut CsrDims)
+                                      -> Status {
+    run(out, || Ok(ffi::dims(ffi::object("mat", mat)?)))
 }
 
+// each buffer comes with its length, which must be no shorter than the array
+// copied into it
 #[no_mangle]
-pub extern "C" fn ks_h_ss_z(nx: u32, ny: u32, kx: u32, ky: u32, nup: u32, l: u32)
-                            -> CoordMatrix<CComplex<f64>> {
-    consv::ks::h_ss_z(Dim(nx), Dim(ny), K(kx), K(ky), nup, I(l as i32))
+pub unsafe extern "C" fn csr_fill(mat: *const CsrHandle<CComplex<f64>>,
+                                  data: *mut CComplex<f64>, data_len: size_t,
+                                  indices: *mut u32, indices_len: size_t,
+                                  indptr: *mut u64, indptr_len: size_t)
+                                  -> Status {
+    run(&mut (), || {
+        let mat = ffi::object("mat", mat)?;
+        ffi::fill(mat, data, data_len, indices, indices_len, indptr, indptr_len)
+    })
 }
 
 #[no_mangle]
-pub extern "C" fn ks_h_ss_xy(nx: u32, ny: u32, kx: u32, ky: u32, nup: u32, l: u32)
-                             -> CoordMatrix<CComplex<f64>> {
-    consv::ks::h_ss_xy(Dim(nx), Dim(ny), K(kx), K(ky), nup, I(l as i32))
+pub unsafe extern "C" fn csr_fill_real(mat: *const CsrHandle<f64>, data: *mut f64,
+                                       data_len: size_t, indices: *mut u32,
+                                       indices_len: size_t, indptr: *mut u64,
+                                       indptr_len: size_t)
+                                       -> Status {
+    run(&mut (), || {
+        let mat = ffi::object("mat", mat)?;
+        ffi::fill(mat, data, data_len, indices, indices_len, indptr, indptr_len)
+    })
 }
 
 #[no_mangle]
-pub extern "C" fn ks_h_sss_chi(nx: u32, ny: u32, kx: u32, ky: u32, nup: u32)
-                               -> CoordMatrix<CComplex<f64>> {
-    consv::ks::h_sss_chi(Dim(nx), Dim(ny), K(kx), K(ky), nup)
+pub unsafe extern "C" fn csr_fill_f32(mat: *const CsrHandle<CComplex<f32>>,
+                                      data: *mut CComplex<f32>, data_len: size_t,
+                                      indices: *mut u32, indices_len: size_t,
+                                      indptr: *mut u64, indptr_len: size_t)
+                                      -> Status {
+    run(&mut (), || {
+        let mat = ffi::object("mat", mat)?;
+        ffi::fill(mat, data, data_len, indices, indices_len, indptr, indptr_len)
+    })
 }
 
+// accepts a matrix from external callers so Rust can dispose of the arrays
+// passed to the caller. They are rebuilt with the length they were allocated
+// with, so the matrix must be passed back unchanged
 #[no_mangle]
-pub extern "C" fn ks_ss_z(nx: u32, ny: u32, kx: u32, ky: u32, nup: u32, l: u32)
-                          -> CoordMatrix<CComplex<f64>> {
-    consv::ks::ss_z(Dim(nx), Dim(ny), K(kx), K(ky), nup, I(l as i32))
+pub unsafe extern "C" fn request_free(mat: CsrHandle<CComplex<f64>>) {
+    drop(mat.into_matrix());
 }
 
 #[no_mangle]
-pub extern "C" fn ks_ss_xy(nx: u32, ny: u32, kx: u32, ky: u32, nup: u32, l: u32)
-                           -> CoordMatrix<CComplex<f64>> {
-    consv::ks::ss_xy(Dim(nx), Dim(ny), K(kx), K(ky), nup, I(l as i32))
+pub unsafe extern "C" fn request_free_real(mat: CsrHandle<f64>) {
+    drop(mat.into_matrix());
 }
 
-// accepts a pointer from external callers so Rust can dispose of the objects
-// passed to the caller
 #[no_mangle]
-pub unsafe extern "C" fn request_free(mat: CoordMatrix<CComplex<f64>>) {
-    Box::from_raw(mat.data.ptr);
-    Box::from_raw(mat.col.ptr);
-    Box::from_raw(mat.row.ptr);
+pub unsafe extern "C" fn request_free_f32(mat: CsrHandle<CComplex<f32>>) {
+    drop(mat.into_matrix());
 }
@@ -0,0 +1,339 @@
+/// Readers and writers for the Matrix Market exchange format, so that matrices
+/// built here could be compared against other exact diagonalization codes and
+/// fed to external eigensolvers, and matrices built elsewhere could be read back.
+///
+/// Matrices are written as coordinate files holding only the lower triangle,
+/// with the `hermitian` symmetry for complex matrices and `symmetric` for real
+/// ones. Any coordinate file with a `real`, `integer` or `complex` field could
+/// be read.
+use num_complex::Complex;
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path
+};
+
+use common::{CComplex, CsrMatrix};
+
+const HEADER: &str = "%%MatrixMarket";
+// relative tolerance for two entries to count as each other's conjugate
+const TOLERANCE: f64 = 1e-10;
+
+fn invalid_input(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Scalar types that could be stored in a Matrix Market file
+pub trait MmElement: Sized {
+    /// The field as spelled in the header
+    const FIELD: &'static str;
+    /// The symmetry a self-adjoint matrix of this type is written with
+    const SYMMETRY: &'static str;
+    fn to_complex(&self) -> Complex<f64>;
+    /// None if c could not be represented
+    fn from_complex(c: Complex<f64>) -> Option<Self>;
+    fn write<W: Write>(&self, w: &mut W) -> io::Result<()>;
+}
+
+impl MmElement for f64 {
+    const FIELD: &'static str = "real";
+    const SYMMETRY: &'static str = "symmetric";
+
+    fn to_complex(&self) -> Complex<f64> { Complex::new(*self, 0.) }
+
+    fn from_complex(c: Complex<f64>) -> Option<Self> {
+        if c.im == 0. {
+            Some(c.re)
+        } else {
+            None
+        }
+    }
+
+    // {:e} prints the shortest representation that reads back exactly
+    fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write!(w, "{:e}", self)
+    }
+}
+
+impl MmElement for CComplex<f64> {
+    const FIELD: &'static str = "complex";
+    const SYMMETRY: &'static str = "hermitian";
+
+    fn to_complex(&self) -> Complex<f64> { Complex::new(self.re, self.im) }
+
+    fn from_complex(c: Complex<f64>) -> Option<Self> {
+        Some(CComplex::from_num_complex(c))
+    }
+
+    fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write!(w, "{:e} {:e}", self.re, self.im)
+    }
+}
+
+/// Writes a self-adjoint matrix in Matrix Market coordinate format. Only the
+/// lower triangle is written, so the matrix is checked to be Hermitian
+/// (symmetric if real) beforehand.
+pub fn write_mm<W: Write, T: MmElement>(w: &mut W, mat: &CsrMatrix<T>)
+                                        -> io::Result<()> {
+    if mat.nrows != mat.ncols {
+        return Err(invalid_input("matrix is not square"));
+    }
+    let (data, indices, indptr) = mat.as_slices();
+    let row = |r: usize| indptr[r] as usize..indptr[r + 1] as usize;
+    let entry = |r: usize, c: usize| {
+        let range = row(r);
+        indices[range.clone()].binary_search(&(c as u32))
+                              .ok()
+                              .map(|e| data[range.start + e].to_complex())
+                              .unwrap_or_else(|| Complex::new(0., 0.))
+    };
+
+    // every entry, the diagonal included, has to be the conjugate of its
+    // mirror image. A missing mirror image counts as zero
+    let mut nnz = 0;
+    for r in 0..mat.nrows as usize {
+        for e in row(r) {
+            let c = indices[e] as usize;
+            let v = data[e].to_complex();
+            if (v - entry(c, r).conj()).norm() > TOLERANCE * v.norm().max(1.) {
+                return Err(invalid_input("matrix is not self-adjoint"));
+            }
+            if c <= r {
+                nnz += 1;
+            }
+        }
+    }
+
+    writeln!(w, "{} matrix coordinate {} {}", HEADER, T::FIELD, T::SYMMETRY)?;
+    writeln!(w, "{} {} {}", mat.nrows, mat.ncols, nnz)?;
+    for r in 0..mat.nrows as usize {
+        for e in row(r).filter(|&e| indices[e] as usize <= r) {
+            write!(w, "{} {} ", r + 1, indices[e] + 1)?;
+            data[e].write(w)?;
+            writeln!(w)?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads a matrix in Matrix Market coordinate format. Entries implied by the
+/// symmetry of the file are filled in. Reading a complex file as f64 fails
+/// unless all imaginary parts are zero.
+pub fn read_mm<R: BufRead, T: MmElement>(r: R) -> io::Result<CsrMatrix<T>> {
+    let mut lines = r.lines();
+    let header = lines.next()
+                      .unwrap_or_else(|| Err(invalid_data("empty file")))?
+                      .to_lowercase();
+    let header = header.split_whitespace().collect::<Vec<_>>();
+    if header.len() != 5
+       || header[0] != HEADER.to_lowercase()
+       || header[1] != "matrix"
+    {
+        return Err(invalid_data("not a Matrix Market file"));
+    }
+    if header[2] != "coordinate" {
+        return Err(invalid_data("only coordinate files are supported"));
+    }
+    let complex = match header[3] {
+        "real" | "integer" => false,
+        "complex" => true,
+        _ => return Err(invalid_data("unsupported field"))
+    };
+    let symmetry = header[4].to_string();
+    let symmetries = ["general", "symmetric", "skew-symmetric", "hermitian"];
+    if !symmetries.contains(&&*symmetry) {
+        return Err(invalid_data("unsupported symmetry"));
+    }
+
+    // whatever follows the header consists of comments, the size line and
+    // entries, one per line
+    let mut lines = lines.filter(|l| match *l {
+                                     Ok(ref l) => {
+                                         let l = l.trim_start();
+                                         !l.is_empty() && !l.starts_with('%')
+                                     }
+                                     Err(_) => true
+                                 });
+    let parse = |line: &str| -> io::Result<Vec<f64>> {
+        line.split_whitespace()
+            .map(|x| x.parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid_data("malformed line"))
+    };
+    let size = lines.next()
+                    .unwrap_or_else(|| Err(invalid_data("missing size line")))?;
+    let size = parse(&size)?;
+    if size.len() != 3 {
+        return Err(invalid_data("malformed size line"));
+    }
+    // a whole number no larger than max
+    let integer = |x: f64, max: f64| {
+        if x >= 0. && x <= max && x.fract() == 0. {
+            Ok(x)
+        } else {
+            Err(invalid_data("malformed size line"))
+        }
+    };
+    let nrows = integer(size[0], u32::MAX as f64)? as u32;
+    let ncols = integer(size[1], u32::MAX as f64)? as u32;
+    let nnz = integer(size[2], usize::MAX as f64)? as usize;
+    if symmetry != "general" && nrows != ncols {
+        return Err(invalid_data("symmetric matrix is not square"));
+    }
+
+    // the size line is not trusted to bound the allocation
+    let mut triplets = Vec::with_capacity(nnz.min(1 << 20));
+    for _ in 0..nnz {
+        let line = lines.next()
+                        .unwrap_or_else(|| Err(invalid_data("missing entries")))?;
+        let fields = parse(&line)?;
+        let v = match (complex, fields.len()) {
+            (false, 3) => Complex::new(fields[2], 0.),
+            (true, 4) => Complex::new(fields[2], fields[3]),
+            _ => return Err(invalid_data("malformed entry"))
+        };
+        // one based indices, which must be whole numbers within the matrix
+        let index = |x: f64, n: u32| {
+            if x >= 1. && x <= n as f64 && x.fract() == 0. {
+                Ok(x as u32 - 1)
+            } else {
+                Err(invalid_data("entry out of bounds"))
+            }
+        };
+        let (r, c) = (index(fields[0], nrows)?, index(fields[1], ncols)?);
+        let from_complex =
+            |v| T::from_complex(v).ok_or_else(|| invalid_data("entry is not real"));
+        triplets.push((r, c, from_complex(v)?));
+        if r != c {
+            let mirror = match &*symmetry {
+                "symmetric" => Some(v),
+                "skew-symmetric" => Some(-v),
+                "hermitian" => Some(v.conj()),
+                _ => None
+            };
+            if let Some(v) = mirror {
+                triplets.push((c, r, from_complex(v)?));
+            }
+        }
+    }
+
+    triplets.sort_unstable_by_key(|&(r, c, _)| (r, c));
+    if triplets.windows(2)
+               .any(|w| (w[0].0, w[0].1) == (w[1].0, w[1].1))
+    {
+        return Err(invalid_data("duplicate entries"));
+    }
+    let mut indptr = vec![0_u64; nrows as usize + 1];
+    let mut indices = Vec::with_capacity(triplets.len());
+    let mut data = Vec::with_capacity(triplets.len());
+    for (r, c, v) in triplets.into_iter() {
+        indptr[r as usize + 1] += 1;
+        indices.push(c);
+        data.push(v);
+    }
+    for r in 0..nrows as usize {
+        indptr[r + 1] += indptr[r];
+    }
+    Ok(CsrMatrix::from_parts(data, indices, indptr, nrows, ncols))
+}
+
+/// Writes a self-adjoint matrix to a Matrix Market file at path
+pub fn save_mm<P: AsRef<Path>, T: MmElement>(path: P, mat: &CsrMatrix<T>)
+                                             -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    write_mm(&mut w, mat)?;
+    w.flush()
+}
+
+/// Reads the Matrix Market file at path
+pub fn load_mm<P: AsRef<Path>, T: MmElement>(path: P) -> io::Result<CsrMatrix<T>> {
+    read_mm(BufReader::new(File::open(path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use consv;
+    use common::{Dim, I, K};
+
+    fn entries(mat: &CsrMatrix<CComplex<f64>>) -> Vec<(u32, u32, f64, f64)> {
+        mat.entries()
+           .into_iter()
+           .map(|(i, j, c)| (i, j, c.re, c.im))
+           .collect()
+    }
+
+    #[test]
+    fn round_trip_test() {
+        let mat = consv::k::h_ss_xy(Dim(4), Dim(3), K(1), K(2), I(1));
+        let mut buf = Vec::new();
+        write_mm(&mut buf, &mat).unwrap();
+        let read = read_mm::<_, CComplex<f64>>(&buf[..]).unwrap();
+        assert_eq!((read.nrows, read.ncols), (mat.nrows, mat.ncols));
+        // the upper triangle is recovered from the lower one, which agrees
+        // with it only up to rounding
+        let (read, mat) = (entries(&read), entries(&mat));
+        assert_eq!(read.len(), mat.len());
+        for (a, b) in read.iter().zip(mat.iter()) {
+            assert_eq!((a.0, a.1), (b.0, b.1));
+            assert!((a.2 - b.2).abs() < 1e-12 && (a.3 - b.3).abs() < 1e-12);
+        }
+
+        let mat = consv::k::h_ss_z_real(Dim(4), Dim(3), K(0), K(0), I(1));
+        let mut buf = Vec::new();
+        write_mm(&mut buf, &mat).unwrap();
+        let read = read_mm::<_, f64>(&buf[..]).unwrap();
+        assert_eq!(read.entries(), mat.entries());
+    }
+
+    #[test]
+    fn read_test() {
+        let file = "%%MatrixMarket matrix coordinate integer skew-symmetric\n\
+                    % a comment\n\
+                    3 3 2\n\
+                    2 1 4\n\
+                    3 2 -1\n";
+        let mat = read_mm::<_, f64>(file.as_bytes()).unwrap();
+        assert_eq!(mat.entries(),
+                   vec![(0, 1, &-4.), (1, 0, &4.), (1, 2, &1.), (2, 1, &-1.)]);
+
+        let file = "%%MatrixMarket matrix coordinate complex general\n\
+                    2 2 1\n\
+                    1 2 0 1\n";
+        assert!(read_mm::<_, f64>(file.as_bytes()).is_err());
+
+        let file = "%%MatrixMarket matrix coordinate real general\n\
+                    2 2 2\n\
+                    1 2 1\n\
+                    1 2 1\n";
+        assert!(read_mm::<_, f64>(file.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn malformed_read_test() {
+        let read = |body: &str| {
+            let file = format!("%%MatrixMarket matrix coordinate real general\n{}",
+                               body);
+            read_mm::<_, f64>(file.as_bytes()).err().map(|e| e.kind())
+        };
+        let invalid = Some(io::ErrorKind::InvalidData);
+        // indices that would wrap around as u32
+        assert_eq!(read("2 2 1\n4294967297 1 1\n"), invalid);
+        assert_eq!(read("2 2 1\n1 -1 1\n"), invalid);
+        assert_eq!(read("2 2 1\n1.5 1 1\n"), invalid);
+        assert_eq!(read("2 -2 1\n1 1 1\n"), invalid);
+        // a corrupt entry count is caught when the entries run out
+        assert_eq!(read("2 2 1e18\n1 1 1\n"), invalid);
+    }
+
+    #[test]
+    fn not_self_adjoint_test() {
+        let mat =
+            CsrMatrix::from_triplets(vec![1., 2.], vec![0, 1], vec![1, 0], 2, 2);
+        assert!(write_mm(&mut Vec::new(), &mat).is_err());
+    }
+}
@@ -0,0 +1,590 @@
+/// Hamiltonians written as sums of the operators a `Sector` could build, each
+/// with its own coupling, and the machinery to find their low-lying spectra
+/// sector by sector.
+use num_complex::Complex;
+use rayon::prelude::*;
+use std::{fmt, str::FromStr, sync::Arc};
+
+use common::*;
+use consv::none;
+use lanczos;
+use sector::Sector;
+
+/// The operators a Hamiltonian could be assembled from. Those taking a bond
+/// range sum over all first, second or third nearest neighbor bonds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Term {
+    /// Σ (S^x_i S^x_j + S^y_i S^y_j)
+    SsXy(I),
+    /// Σ S^z_i S^z_j
+    SsZ(I),
+    /// Σ (γ_ij S^+_i S^+_j + h.c.)
+    SsPpmm(I),
+    /// The J_{+-z} term of the anisotropic exchange model
+    SsPmz(I),
+    /// The scalar chirality summed over all triangles
+    SssChi,
+    /// Four-spin ring exchange on the elementary rhombi
+    Ring
+}
+
+impl Term {
+    /// The name and, for bond operators, the bond range
+    fn parts(&self) -> (&'static str, Option<I>) {
+        match *self {
+            Term::SsXy(l) => ("ss_xy", Some(l)),
+            Term::SsZ(l) => ("ss_z", Some(l)),
+            Term::SsPpmm(l) => ("ss_ppmm", Some(l)),
+            Term::SsPmz(l) => ("ss_pmz", Some(l)),
+            Term::SssChi => ("sss_chi", None),
+            Term::Ring => ("ring", None)
+        }
+    }
+
+    pub fn conserves_sz(&self) -> bool {
+        !matches!(*self, Term::SsPpmm(_) | Term::SsPmz(_))
+    }
+
+    /// Whether the operator is unchanged by the inversion r -> -r. Inversion
+    /// carries every upright triangle onto an inverted one traversed the other
+    /// way round, so the chirality flips sign while the rest of the terms are
+    /// left alone.
+    pub fn commutes_with_inversion(&self) -> bool { !matches!(*self, Term::SssChi) }
+
+    pub fn check(&self) -> Result<(), InputError> {
+        match self.parts().1 {
+            Some(l) => check_bond(l),
+            None => Ok(())
+        }
+    }
+
+    /// Fails if the term does not conserve total Sz and the sector fixes nup
+    pub fn build(&self, sector: &Sector)
+                 -> Result<CsrMatrix<CComplex<f64>>, InputError> {
+        match *self {
+            Term::SsXy(l) => Ok(sector.h_ss_xy(l)),
+            Term::SsZ(l) => Ok(sector.h_ss_z(l)),
+            Term::SsPpmm(l) => sector.h_ss_ppmm(l),
+            Term::SsPmz(l) => sector.h_ss_pmz(l),
+            Term::SssChi => Ok(sector.h_sss_chi()),
+            Term::Ring => Ok(sector.h_ring())
+        }
+    }
+
+    /// The operator in the basis of all 2^N configurations
+    pub fn build_full(&self, nx: Dim, ny: Dim) -> CsrMatrix<CComplex<f64>> {
+        match *self {
+            Term::SsXy(l) => none::h_ss_xy(nx, ny, l),
+            Term::SsZ(l) => none::h_ss_z(nx, ny, l),
+            Term::SsPpmm(l) => none::h_ss_ppmm(nx, ny, l),
+            Term::SsPmz(l) => none::h_ss_pmz(nx, ny, l),
+            Term::SssChi => none::h_sss_chi(nx, ny),
+            Term::Ring => none::h_ring(nx, ny)
+        }
+    }
+
+    /// ⟨v|H_t|v⟩ for each of the normalized vectors in the sector
+    pub fn expectation_values(&self, sector: &Sector, vectors: &[Vec<Complex<f64>>])
+                              -> Result<Vec<f64>, InputError> {
+        let parts = self.build(sector)?.into_parts();
+        Ok(vectors.iter().map(|v| expectation(&parts, v)).collect())
+    }
+}
+
+/// Terms are written as their name followed by the bond range for bond
+/// operators, as in "ss_xy:1" or "sss_chi"
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.parts() {
+            (name, Some(l)) => write!(f, "{}:{}", name, l.raw_int()),
+            (name, None) => write!(f, "{}", name)
+        }
+    }
+}
+
+impl FromStr for Term {
+    type Err = InputError;
+
+    fn from_str(s: &str) -> Result<Term, InputError> {
+        let unknown = || InputError::UnknownTerm(s.to_string());
+        let mut parts = s.splitn(2, ':');
+        let name = parts.next().unwrap_or("");
+        let l = match parts.next() {
+            Some(l) => Some(I(l.parse().map_err(|_| unknown())?)),
+            None => None
+        };
+        let term = match (name, l) {
+            ("ss_xy", Some(l)) => Term::SsXy(l),
+            ("ss_z", Some(l)) => Term::SsZ(l),
+            ("ss_ppmm", Some(l)) => Term::SsPpmm(l),
+            ("ss_pmz", Some(l)) => Term::SsPmz(l),
+            ("sss_chi", None) => Term::SssChi,
+            ("ring", None) => Term::Ring,
+            _ => return Err(unknown())
+        };
+        term.check()?;
+        Ok(term)
+    }
+}
+
+/// A Hamiltonian Σ J_t H_t on an nx × ny lattice
+#[derive(Clone, Debug, PartialEq)]
+pub struct Model {
+    pub nx:    Dim,
+    pub ny:    Dim,
+    pub terms: Vec<(Term, f64)>
+}
+
+impl Model {
+    pub fn check(&self) -> Result<(), InputError> {
+        check_lattice(self.nx, self.ny)?;
+        for &(term, _) in self.terms.iter() {
+            term.check()?;
+        }
+        Ok(())
+    }
+
+    /// Whether total Sz is a good quantum number
+    pub fn conserves_sz(&self) -> bool {
+        self.terms.iter().all(|&(t, j)| j == 0. || t.conserves_sz())
+    }
+
+    /// Checks that the sector exists and that nup is given if and only if
+    /// the model conserves total Sz
+    pub fn check_sector(&self, kx: K, ky: K, nup: Option<u32>)
+                        -> Result<(), InputError> {
+        check_momentum(self.nx, self.ny, kx, ky)?;
+        match nup {
+            Some(nup) if self.conserves_sz() => check_nup(self.nx, self.ny, nup),
+            None if !self.conserves_sz() => check_full_lattice(self.nx, self.ny),
+            _ => Err(InputError::SzConservation(self.conserves_sz()))
+        }
+    }
+
+    /// Checks that the observable can be measured in the sectors of the model.
+    /// Those of a model that conserves total Sz fix nup, so the observable has
+    /// to conserve it as well
+    pub fn check_observable(&self, term: Term) -> Result<(), InputError> {
+        term.check()?;
+        if self.conserves_sz() && !term.conserves_sz() {
+            return Err(InputError::SzConservation(true));
+        }
+        Ok(())
+    }
+
+    /// Whether the Hamiltonian commutes with the inversion r -> -r
+    pub fn is_inversion_symmetric(&self) -> bool {
+        self.terms.iter().all(|&(t, j)| j == 0. || t.commutes_with_inversion())
+    }
+
+    /// The momenta whose sectors need to be diagonalized along with the number
+    /// of momenta each stands for. When the Hamiltonian is inversion symmetric
+    /// k and -k share the same spectrum and only one of the two is listed (see
+    /// `irreducible_momenta`); otherwise every momentum is listed once.
+    pub fn momenta(&self) -> Vec<(K, K, u32)> {
+        if self.is_inversion_symmetric() {
+            irreducible_momenta(self.nx, self.ny)
+        } else {
+            let (nx, ny) = (self.nx.raw_int(), self.ny.raw_int());
+            (0..nx).flat_map(|kx| (0..ny).map(move |ky| (K(kx), K(ky), 1)))
+                   .collect()
+        }
+    }
+
+    /// One sector out of each set that is related by symmetry and so shares
+    /// the same spectrum. Momenta k and -k are related by inversion when the
+    /// Hamiltonian has it, and up spin counts nup and n - nup by a global spin
+    /// flip.
+    pub fn irreducible_sectors(&self) -> Vec<(K, K, Option<u32>)> {
+        let momenta = self.momenta();
+        let nups = if self.conserves_sz() {
+            (0..(self.nx * self.ny).raw_int() / 2 + 1).map(Some).collect()
+        } else {
+            vec![None]
+        };
+        let mut sectors = Vec::new();
+        for &nup in nups.iter() {
+            for &(kx, ky, _) in momenta.iter() {
+                sectors.push((kx, ky, nup));
+            }
+        }
+        sectors
+    }
+
+    pub fn sector(&self, kx: K, ky: K, nup: Option<u32>) -> Sector {
+        match nup {
+            Some(nup) => Sector::ks(self.nx, self.ny, kx, ky, nup),
+            None => Sector::k(self.nx, self.ny, kx, ky)
+        }
+    }
+
+    /// The terms with a nonzero coupling
+    pub fn nonzero_terms(&self) -> Vec<Term> {
+        self.terms.iter().filter(|&&(_, j)| j != 0.).map(|&(t, _)| t).collect()
+    }
+
+    /// The Hamiltonian restricted to the sector. Terms with a vanishing
+    /// coupling are left out. Fails if a term does not conserve total Sz and
+    /// the sector fixes nup.
+    pub fn hamiltonian(&self, sector: &Sector) -> Result<Hamiltonian, InputError> {
+        Ok(Components::new(&self.nonzero_terms(), sector)?.hamiltonian(&self.terms))
+    }
+
+    /// The Hamiltonian in the basis of all 2^N configurations, with no
+    /// symmetry used. Only fit for small clusters.
+    pub fn full_hamiltonian(&self) -> Result<Hamiltonian, InputError> {
+        none::check(self.nx, self.ny)?;
+        let terms = self.terms
+                        .iter()
+                        .filter(|&&(_, j)| j != 0.)
+                        .map(|&(t, j)| (j, Arc::new(t.build_full(self.nx, self.ny)
+                                                     .into_parts())))
+                        .collect();
+        Ok(Hamiltonian { dim: none::dim(self.nx, self.ny) as usize, terms })
+    }
+
+    /// Checks the symmetry sectors against the full Hilbert space: the
+    /// spectra of all momentum sectors, and of all nup when total Sz is
+    /// conserved, together have to make up the spectrum of the full
+    /// Hamiltonian. Every matrix is diagonalized densely, so this is only
+    /// fit for small clusters. Returns the largest deviation between
+    /// corresponding eigenvalues, or infinity when the dimensions do not even
+    /// add up.
+    pub fn check_sector_spectra(&self) -> Result<f64, InputError> {
+        self.check()?;
+        let full = self.full_hamiltonian()?.eigenvalues();
+        let (nx, ny) = (self.nx.raw_int(), self.ny.raw_int());
+        let nups = if self.conserves_sz() {
+            (0..nx * ny + 1).map(Some).collect()
+        } else {
+            vec![None]
+        };
+        let mut union = Vec::with_capacity(full.len());
+        for &nup in nups.iter() {
+            for kx in 0..nx {
+                for ky in 0..ny {
+                    let sector = self.sector(K(kx), K(ky), nup);
+                    union.extend(self.hamiltonian(&sector)?.eigenvalues());
+                }
+            }
+        }
+        if union.len() != full.len() {
+            return Ok(f64::INFINITY);
+        }
+        union.sort_by(|x, y| x.partial_cmp(y).unwrap());
+        Ok(union.iter()
+                .zip(full.iter())
+                .fold(0., |m, (x, y)| f64::max(m, (x - y).abs())))
+    }
+}
+
+/// The data, indices and indptr arrays of a CSR matrix
+type CsrParts = (Vec<CComplex<f64>>, Vec<u32>, Vec<u64>);
+
+/// Row r of the CSR matrix times x
+fn row_dot(parts: &CsrParts, r: usize, x: &[Complex<f64>]) -> Complex<f64> {
+    let (ref data, ref indices, ref indptr) = *parts;
+    let mut acc = Complex::new(0., 0.);
+    for e in indptr[r] as usize..indptr[r + 1] as usize {
+        let c = data[e];
+        acc += Complex::new(c.re, c.im) * x[indices[e] as usize];
+    }
+    acc
+}
+
+/// ⟨v|A|v⟩ for the Hermitian matrix A and the normalized vector v
+fn expectation(parts: &CsrParts, v: &[Complex<f64>]) -> f64 {
+    (0..v.len()).into_par_iter()
+                .map(|r| v[r].conj() * row_dot(parts, r, v))
+                .reduce(|| Complex::new(0., 0.), |a, b| a + b)
+                .re
+}
+
+/// The operators of a number of terms in one sector. Building them is what
+/// takes time, so a parameter sweep builds them once and only changes the
+/// couplings they are combined with.
+pub struct Components {
+    dim:   usize,
+    parts: Vec<(Term, Arc<CsrParts>)>
+}
+
+impl Components {
+    /// Fails if a term does not conserve total Sz and the sector fixes nup
+    pub fn new(terms: &[Term], sector: &Sector) -> Result<Components, InputError> {
+        let mut parts: Vec<(Term, Arc<CsrParts>)> = Vec::new();
+        for &t in terms.iter() {
+            if parts.iter().all(|&(u, _)| u != t) {
+                parts.push((t, Arc::new(t.build(sector)?.into_parts())));
+            }
+        }
+        Ok(Components { dim: sector.dim() as usize, parts })
+    }
+
+    pub fn dim(&self) -> usize { self.dim }
+
+    fn get(&self, term: Term) -> &Arc<CsrParts> {
+        match self.parts.iter().find(|&&(t, _)| t == term) {
+            Some((_, parts)) => parts,
+            None => panic!("{} is not among the components", term)
+        }
+    }
+
+    /// Σ J_t H_t over the terms. Panics if a term with a nonzero coupling is
+    /// not among the components.
+    pub fn hamiltonian(&self, terms: &[(Term, f64)]) -> Hamiltonian {
+        let terms = terms.iter()
+                         .filter(|&&(_, j)| j != 0.)
+                         .map(|&(t, j)| (j, self.get(t).clone()))
+                         .collect();
+        Hamiltonian { dim: self.dim, terms }
+    }
+
+    /// As `Term::expectation_values` without building the operator again.
+    /// Panics if the term is not among the components.
+    pub fn expectation_values(&self, term: Term, vectors: &[Vec<Complex<f64>>])
+                              -> Vec<f64> {
+        let parts = self.get(term);
+        vectors.iter().map(|v| expectation(parts, v)).collect()
+    }
+}
+
+/// A Hamiltonian kept as its separate terms. Adding them up would save
+/// nothing when applying it to a vector, since each term is read once either
+/// way. The terms are shared with the `Components` they came from.
+pub struct Hamiltonian {
+    dim:   usize,
+    terms: Vec<(f64, Arc<CsrParts>)>
+}
+
+impl Hamiltonian {
+    /// The Hamiltonian given by a single matrix built elsewhere, such as one
+    /// handed in from Python. Panics unless the matrix is square.
+    pub fn from_matrix(mat: CsrMatrix<CComplex<f64>>) -> Hamiltonian {
+        Hamiltonian::from_terms(vec![(1., mat)])
+    }
+
+    /// Σ J A over the pairs (J, A). Panics unless there is at least one term
+    /// and every matrix is square of the same size.
+    pub fn from_terms(terms: Vec<(f64, CsrMatrix<CComplex<f64>>)>) -> Hamiltonian {
+        let dim = terms[0].1.nrows;
+        let terms = terms.into_iter()
+                         .map(|(j, mat)| {
+                             assert!(mat.nrows == dim && mat.ncols == dim,
+                                     "a Hamiltonian must be square");
+                             (j, Arc::new(mat.into_parts()))
+                         })
+                         .collect();
+        Hamiltonian { dim: dim as usize, terms }
+    }
+
+    pub fn dim(&self) -> usize { self.dim }
+
+    /// y = H x
+    pub fn apply(&self, x: &[Complex<f64>], y: &mut [Complex<f64>]) {
+        y.par_iter_mut().enumerate().for_each(|(r, y)| {
+            *y = self.terms
+                     .iter()
+                     .fold(Complex::new(0., 0.), |acc, &(j, ref parts)| {
+                         acc + row_dot(parts, r, x) * j
+                     });
+        });
+    }
+
+    /// The matrix stored densely, row by row
+    pub fn dense(&self) -> Vec<Complex<f64>> {
+        let mut a = vec![Complex::new(0., 0.); self.dim * self.dim];
+        for &(j, ref parts) in self.terms.iter() {
+            let (ref data, ref indices, ref indptr) = **parts;
+            for r in 0..self.dim {
+                for e in indptr[r] as usize..indptr[r + 1] as usize {
+                    let (c, col) = (data[e], indices[e] as usize);
+                    a[r * self.dim + col] += Complex::new(c.re, c.im) * j;
+                }
+            }
+        }
+        a
+    }
+
+    /// All eigenvalues in ascending order, by dense diagonalization. See
+    /// `lanczos::hermitian_eigenvalues`
+    pub fn eigenvalues(&self) -> Vec<f64> {
+        lanczos::hermitian_eigenvalues(self.dense(), self.dim)
+    }
+
+    /// The lowest nev eigenvalues in ascending order. See
+    /// `lanczos::lowest_eigenvalues`
+    pub fn lowest_eigenvalues(&self, nev: usize, tol: f64, max_iter: usize)
+                              -> Vec<f64> {
+        with_thread_pool(|| {
+            lanczos::lowest_eigenvalues(|x, y| self.apply(x, y),
+                                        self.dim,
+                                        nev,
+                                        tol,
+                                        max_iter)
+        })
+    }
+
+    /// The lowest nev eigenvalues in ascending order and their normalized
+    /// eigenvectors, iterating from start when it is given. See
+    /// `lanczos::lowest_eigenpairs`
+    pub fn lowest_eigenpairs(&self, start: Option<&[Complex<f64>]>, nev: usize,
+                             tol: f64, max_iter: usize)
+                             -> (Vec<f64>, Vec<Vec<Complex<f64>>>) {
+        let op = |x: &[Complex<f64>], y: &mut [Complex<f64>]| self.apply(x, y);
+        with_thread_pool(|| match start {
+                             Some(start) => {
+                                 lanczos::lowest_eigenpairs_from(op, start, nev,
+                                                                 tol, max_iter)
+                             }
+                             None => {
+                                 lanczos::lowest_eigenpairs(op, self.dim, nev,
+                                                            tol, max_iter)
+                             }
+                         })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn term_test() {
+        let terms = ["ss_xy:1", "ss_z:3", "ss_ppmm:1", "ss_pmz:2", "sss_chi",
+                     "ring"];
+        for s in terms.iter() {
+            assert_eq!(s.parse::<Term>().unwrap().to_string(), *s);
+        }
+        assert_eq!("ss_xy:4".parse::<Term>(), Err(InputError::BondRange(4)));
+        assert!("ss_xy".parse::<Term>().is_err());
+        assert!("ring:1".parse::<Term>().is_err());
+    }
+
+    #[test]
+    fn irreducible_sectors_test() {
+        let model = Model { nx:    Dim(4),
+                            ny:    Dim(3),
+                            terms: vec![(Term::SsXy(I(1)), 1.),
+                                        (Term::SsZ(I(1)), 0.5)] };
+        let sectors = model.irreducible_sectors();
+        // (0, 0), (2, 0), (1, 0), (0, 1), (1, 1), (1, 2), (2, 1) for each nup
+        assert_eq!(sectors.len(), 7 * 7);
+        let model = Model { terms: vec![(Term::SsPpmm(I(1)), 1.)], ..model };
+        assert_eq!(model.irreducible_sectors().len(), 7);
+        assert!(model.check_sector(K(1), K(2), Some(3)).is_err());
+        // the chirality breaks inversion so k and -k are both needed
+        let model = Model { terms: vec![(Term::SssChi, 1.)], ..model };
+        assert_eq!(model.irreducible_sectors().len(), 12 * 7);
+    }
+
+    #[test]
+    fn invert_test() {
+        let model = Model { nx:    Dim(4),
+                            ny:    Dim(3),
+                            terms: vec![(Term::SsXy(I(1)), 1.),
+                                        (Term::SsZ(I(2)), 0.3),
+                                        (Term::SsPpmm(I(1)), 0.2),
+                                        (Term::SsPmz(I(1)), -0.4),
+                                        (Term::Ring, 0.1)] };
+        assert!(model.is_inversion_symmetric());
+        let sector = model.sector(K(1), K(2), None);
+        let h = model.hamiltonian(&sector).unwrap();
+        let (eigs, vectors) = h.lowest_eigenpairs(None, 2, 1e-12, 500);
+        // the inverted vectors are eigenvectors at -k with the same energies
+        let h = model.hamiltonian(&sector.inverse()).unwrap();
+        for (&e, w) in eigs.iter().zip(sector.invert(&vectors).iter()) {
+            let mut hw = vec![Complex::new(0., 0.); w.len()];
+            h.apply(w, &mut hw);
+            let residual = hw.iter()
+                             .zip(w.iter())
+                             .map(|(&a, &b)| (a - b * e).norm_sqr())
+                             .sum::<f64>()
+                             .sqrt();
+            assert!(residual < 1e-4);
+        }
+        let model = Model { terms: vec![(Term::SssChi, 1.)], ..model };
+        assert!(!model.is_inversion_symmetric());
+    }
+
+    #[test]
+    fn from_matrix_test() {
+        let model = Model { nx:    Dim(3),
+                            ny:    Dim(3),
+                            terms: vec![(Term::SsXy(I(1)), 1.)] };
+        let sector = model.sector(K(1), K(0), Some(4));
+        let h = Hamiltonian::from_matrix(sector.h_ss_xy(I(1)));
+        let expected = model.hamiltonian(&sector).unwrap().eigenvalues();
+        assert_eq!(h.dim(), expected.len());
+        let eigs = h.lowest_eigenvalues(3, 1e-12, 500);
+        for (a, b) in eigs.iter().zip(expected.iter()) {
+            assert!((a - b).abs() < 1e-8);
+        }
+    }
+
+    #[test]
+    fn spectrum_test() {
+        // the eigenvalues of the full spectrum sum up to the trace
+        let model = Model { nx:    Dim(3),
+                            ny:    Dim(3),
+                            terms: vec![(Term::SsXy(I(1)), 1.),
+                                        (Term::SsZ(I(1)), 0.7),
+                                        (Term::SssChi, 0.3)] };
+        let sector = model.sector(K(1), K(0), Some(4));
+        let h = model.hamiltonian(&sector).unwrap();
+        let mut trace = 0.;
+        for &(j, ref parts) in h.terms.iter() {
+            let (ref data, ref indices, ref indptr) = **parts;
+            for r in 0..h.dim() {
+                for e in indptr[r] as usize..indptr[r + 1] as usize {
+                    if indices[e] as usize == r {
+                        trace += j * data[e].re;
+                    }
+                }
+            }
+        }
+        let eigs = h.lowest_eigenvalues(h.dim(), 1e-12, 1000);
+        assert_eq!(eigs.len(), h.dim());
+        assert!((eigs.iter().sum::<f64>() - trace).abs() < 1e-8);
+        assert!(eigs.windows(2).all(|w| w[0] <= w[1]));
+    }
+
+    #[test]
+    fn expectation_values_test() {
+        // the energy of an eigenstate is the sum of the expectation values of
+        // the terms weighted by their couplings
+        let model = Model { nx:    Dim(3),
+                            ny:    Dim(3),
+                            terms: vec![(Term::SsXy(I(1)), 1.),
+                                        (Term::SsZ(I(1)), 0.7)] };
+        let sector = model.sector(K(0), K(0), Some(4));
+        let h = model.hamiltonian(&sector).unwrap();
+        let (eigs, vectors) = h.lowest_eigenpairs(None, 2, 1e-12, 500);
+        let xy = Term::SsXy(I(1)).expectation_values(&sector, &vectors).unwrap();
+        let z = Term::SsZ(I(1)).expectation_values(&sector, &vectors).unwrap();
+        for i in 0..2 {
+            assert!((xy[i] + 0.7 * z[i] - eigs[i]).abs() < 1e-8);
+        }
+    }
+
+    #[test]
+    fn sector_spectra_test() {
+        let model = Model { nx:    Dim(3),
+                            ny:    Dim(3),
+                            terms: vec![(Term::SsXy(I(1)), 1.),
+                                        (Term::SsZ(I(1)), 0.7),
+                                        (Term::SsZ(I(2)), 0.2),
+                                        (Term::SssChi, 0.3),
+                                        (Term::Ring, 0.1)] };
+        assert!(model.check_sector_spectra().unwrap() < 1e-8);
+
+        // without Sz conservation only the momentum sectors are summed over
+        let model = Model { nx:    Dim(4),
+                            ny:    Dim(2),
+                            terms: vec![(Term::SsXy(I(1)), 1.),
+                                        (Term::SsZ(I(1)), 0.7),
+                                        (Term::SsPpmm(I(1)), 0.4),
+                                        (Term::SsPmz(I(1)), 0.3),
+                                        (Term::SssChi, 0.2)] };
+        assert!(model.check_sector_spectra().unwrap() < 1e-8);
+    }
+}
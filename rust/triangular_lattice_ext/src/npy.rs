@@ -0,0 +1,660 @@
+/// Readers and writers for NumPy's `.npy` and `.npz` formats, so that bases and
+/// matrices written here could be opened with `numpy.load` and vice versa.
+///
+/// Only what this crate needs is supported: one dimensional and scalar arrays
+/// in little endian byte order, and `.npz` archives whose members are stored
+/// uncompressed, as written by `numpy.savez` (but not `numpy.savez_compressed`).
+/// Archives are always written with zip64 extensions so members may exceed
+/// 4 GiB.
+use fnv::FnvHashMap;
+use num_complex::Complex;
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path
+};
+
+use common::CComplex;
+
+const NPY_MAGIC: &[u8] = b"\x93NUMPY";
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const ZIP64_END: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR: u32 = 0x0706_4b50;
+const END: u32 = 0x0605_4b50;
+// version 4.5 of the zip specification introduced zip64
+const ZIP_VERSION: u16 = 45;
+// 1980-01-01, the earliest date a zip archive can hold
+const DOS_DATE: u16 = 0x21;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Scalar types that could be stored in a `.npy` array
+pub trait NpyElement: Sized {
+    /// The dtype as spelled in the `.npy` header
+    const DESCR: &'static str;
+    fn write_le<W: Write>(&self, w: &mut W) -> io::Result<()>;
+    fn read_le<R: Read>(r: &mut R) -> io::Result<Self>;
+}
+
+macro_rules! npy_element {
+    ($t:ty, $descr:expr, $n:expr) => {
+        impl NpyElement for $t {
+            const DESCR: &'static str = $descr;
+
+            fn write_le<W: Write>(&self, w: &mut W) -> io::Result<()> {
+                w.write_all(&self.to_le_bytes())
+            }
+
+            fn read_le<R: Read>(r: &mut R) -> io::Result<Self> {
+                let mut buf = [0; $n];
+                r.read_exact(&mut buf)?;
+                Ok(<$t>::from_le_bytes(buf))
+            }
+        }
+    };
+}
+
+npy_element!(u32, "<u4", 4);
+npy_element!(u64, "<u8", 8);
+npy_element!(i64, "<i8", 8);
+npy_element!(f32, "<f4", 4);
+npy_element!(f64, "<f8", 8);
+
+impl NpyElement for Complex<f64> {
+    const DESCR: &'static str = "<c16";
+
+    fn write_le<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.re.write_le(w)?;
+        self.im.write_le(w)
+    }
+
+    fn read_le<R: Read>(r: &mut R) -> io::Result<Self> {
+        let re = f64::read_le(r)?;
+        let im = f64::read_le(r)?;
+        Ok(Complex::new(re, im))
+    }
+}
+
+impl NpyElement for CComplex<f64> {
+    const DESCR: &'static str = "<c16";
+
+    fn write_le<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.re.write_le(w)?;
+        self.im.write_le(w)
+    }
+
+    fn read_le<R: Read>(r: &mut R) -> io::Result<Self> {
+        let re = f64::read_le(r)?;
+        let im = f64::read_le(r)?;
+        Ok(CComplex { re, im })
+    }
+}
+
+impl NpyElement for CComplex<f32> {
+    const DESCR: &'static str = "<c8";
+
+    fn write_le<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.re.write_le(w)?;
+        self.im.write_le(w)
+    }
+
+    fn read_le<R: Read>(r: &mut R) -> io::Result<Self> {
+        let re = f32::read_le(r)?;
+        let im = f32::read_le(r)?;
+        Ok(CComplex { re, im })
+    }
+}
+
+/// Writes a version 1.0 `.npy` header. The header is padded so that the data
+/// starts at a multiple of 64 bytes, as numpy does.
+fn write_header<W>(w: &mut W, descr: &str, shape: &[usize]) -> io::Result<()>
+    where W: Write
+{
+    let shape = match shape.len() {
+        1 => format!("({},)", shape[0]),
+        _ => {
+            let dims = shape.iter().map(|d| d.to_string()).collect::<Vec<_>>();
+            format!("({})", dims.join(", "))
+        }
+    };
+    let mut dict = format!("{{'descr': '{}', 'fortran_order': False, \
+                            'shape': {}, }}",
+                           descr, shape);
+    // magic, version and header length take up 10 bytes. The header itself
+    // ends with a newline
+    let padding = 63 - (10 + dict.len()) % 64;
+    dict.extend((0..padding).map(|_| ' '));
+    dict.push('\n');
+
+    w.write_all(NPY_MAGIC)?;
+    w.write_all(&[1, 0])?;
+    w.write_all(&(dict.len() as u16).to_le_bytes())?;
+    w.write_all(dict.as_bytes())
+}
+
+/// Reads a `.npy` header and returns the dtype and shape of the array
+fn read_header<R: Read>(r: &mut R) -> io::Result<(String, Vec<usize>)> {
+    let mut magic = [0; 8];
+    r.read_exact(&mut magic)?;
+    if &magic[..6] != NPY_MAGIC {
+        return Err(invalid("not a .npy file"));
+    }
+    let len = match magic[6] {
+        1 => {
+            let mut buf = [0; 2];
+            r.read_exact(&mut buf)?;
+            u16::from_le_bytes(buf) as usize
+        }
+        2 | 3 => {
+            let mut buf = [0; 4];
+            r.read_exact(&mut buf)?;
+            u32::from_le_bytes(buf) as usize
+        }
+        _ => return Err(invalid("unsupported .npy version"))
+    };
+    let mut dict = vec![0; len];
+    r.read_exact(&mut dict)?;
+    let dict = String::from_utf8(dict)
+        .map_err(|_| invalid("malformed .npy header"))?;
+
+    // the value that follows a key in the header dictionary
+    let value = |key: &str| {
+        dict.find(&format!("'{}':", key))
+            .map(|i| dict[i + key.len() + 3..].trim_start())
+            .ok_or_else(|| invalid("malformed .npy header"))
+    };
+    let descr = value("descr")?;
+    let descr = descr.get(1..)
+                     .ok_or_else(|| invalid("malformed .npy header"))?
+                     .split('\'')
+                     .next()
+                     .unwrap_or("")
+                     .to_string();
+    if value("fortran_order")?.starts_with("True") {
+        return Err(invalid("fortran ordered arrays are not supported"));
+    }
+    let shape = value("shape")?;
+    let end = shape.find(')').ok_or_else(|| invalid("malformed .npy header"))?;
+    let shape = shape.get(1..end)
+                     .ok_or_else(|| invalid("malformed .npy header"))?
+                     .split(',')
+                             .map(|d| d.trim())
+                             .filter(|d| !d.is_empty())
+                             .map(|d| d.parse::<usize>())
+                             .collect::<Result<Vec<_>, _>>()
+                             .map_err(|_| invalid("malformed .npy header"))?;
+    if shape.iter().try_fold(1_usize, |n, &d| n.checked_mul(d)).is_none() {
+        return Err(invalid("malformed .npy header"));
+    }
+    Ok((descr, shape))
+}
+
+/// Writes a one dimensional array in `.npy` format
+pub fn write_npy<W: Write, T: NpyElement>(w: &mut W, data: &[T]) -> io::Result<()> {
+    write_header(w, T::DESCR, &[data.len()])?;
+    for x in data.iter() {
+        x.write_le(w)?;
+    }
+    Ok(())
+}
+
+/// Reads an array in `.npy` format of any shape into a flat vector
+pub fn read_npy<R: Read, T: NpyElement>(r: &mut R) -> io::Result<Vec<T>> {
+    let (descr, shape) = read_header(r)?;
+    if descr != T::DESCR {
+        let msg = format!("expected dtype {}, found {}", T::DESCR, descr);
+        return Err(invalid(&msg));
+    }
+    let len = shape.iter().product::<usize>();
+    (0..len).map(|_| T::read_le(r)).collect()
+}
+
+/// Reads an array of any integer dtype
+fn read_npy_int<R: Read>(r: &mut R) -> io::Result<Vec<i64>> {
+    let (descr, shape) = read_header(r)?;
+    let len = shape.iter().product::<usize>();
+    let read = |r: &mut R| -> io::Result<i64> {
+        match descr.as_str() {
+            "<i4" => {
+                let mut buf = [0; 4];
+                r.read_exact(&mut buf)?;
+                Ok(i32::from_le_bytes(buf) as i64)
+            }
+            "<u4" => Ok(u32::read_le(r)? as i64),
+            "<i8" => i64::read_le(r),
+            "<u8" => {
+                let x = u64::read_le(r)?;
+                if x > i64::MAX as u64 {
+                    Err(invalid("integer out of range"))
+                } else {
+                    Ok(x as i64)
+                }
+            }
+            _ => Err(invalid(&format!("expected an integer dtype, found {}", descr)))
+        }
+    };
+    (0..len).map(|_| read(r)).collect()
+}
+
+/// The CRC-32 used by zip archives
+struct Crc32 {
+    table: [u32; 256],
+    crc:   u32
+}
+
+impl Crc32 {
+    fn new() -> Crc32 {
+        let mut table = [0; 256];
+        for (i, entry) in table.iter_mut().enumerate() {
+            let mut c = i as u32;
+            for _ in 0..8 {
+                c = if c & 1 == 1 { 0xedb8_8320 ^ (c >> 1) } else { c >> 1 };
+            }
+            *entry = c;
+        }
+        Crc32 { table, crc: !0 }
+    }
+
+    fn update(&mut self, buf: &[u8]) {
+        for &b in buf.iter() {
+            let i = ((self.crc ^ b as u32) & 0xff) as usize;
+            self.crc = self.table[i] ^ (self.crc >> 8);
+        }
+    }
+
+    fn finish(&self) -> u32 { !self.crc }
+}
+
+// keeps track of the size and checksum of everything written through it
+struct CrcWriter<'a, W: 'a> {
+    w:    &'a mut W,
+    crc:  Crc32,
+    size: u64
+}
+
+impl<'a, W: Write> Write for CrcWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.w.write(buf)?;
+        self.crc.update(&buf[..n]);
+        self.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> { self.w.flush() }
+}
+
+struct NpzEntry {
+    name:   String,
+    crc:    u32,
+    size:   u64,
+    offset: u64
+}
+
+/// Writes named arrays into an uncompressed `.npz` archive. `finish` must be
+/// called once all arrays are added or the archive will be unreadable.
+pub struct NpzWriter<W: Write + Seek> {
+    w:       W,
+    entries: Vec<NpzEntry>
+}
+
+impl NpzWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(NpzWriter::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write + Seek> NpzWriter<W> {
+    pub fn new(w: W) -> NpzWriter<W> { NpzWriter { w, entries: Vec::new() } }
+
+    // writes a member whose content is produced by f. The checksum and sizes
+    // are only known afterwards so they are patched into the local header
+    fn entry<F>(&mut self, name: &str, f: F) -> io::Result<()>
+        where F: FnOnce(&mut CrcWriter<W>) -> io::Result<()>
+    {
+        let name = format!("{}.npy", name);
+        let offset = self.w.stream_position()?;
+        self.w.write_all(&LOCAL_HEADER.to_le_bytes())?;
+        for &x in [ZIP_VERSION, 0, 0, 0, DOS_DATE].iter() {
+            self.w.write_all(&x.to_le_bytes())?;
+        }
+        // crc followed by the sizes, which live in the zip64 extra field
+        for &x in [0, !0, !0].iter() {
+            self.w.write_all(&(x as u32).to_le_bytes())?;
+        }
+        self.w.write_all(&(name.len() as u16).to_le_bytes())?;
+        self.w.write_all(&20_u16.to_le_bytes())?;
+        self.w.write_all(name.as_bytes())?;
+        self.w.write_all(&1_u16.to_le_bytes())?;
+        self.w.write_all(&16_u16.to_le_bytes())?;
+        self.w.write_all(&[0; 16])?;
+
+        let (crc, size) = {
+            let mut cw = CrcWriter { w: &mut self.w, crc: Crc32::new(), size: 0 };
+            f(&mut cw)?;
+            (cw.crc.finish(), cw.size)
+        };
+
+        let end = self.w.stream_position()?;
+        self.w.seek(SeekFrom::Start(offset + 14))?;
+        self.w.write_all(&crc.to_le_bytes())?;
+        self.w.seek(SeekFrom::Start(offset + 30 + name.len() as u64 + 4))?;
+        self.w.write_all(&size.to_le_bytes())?;
+        self.w.write_all(&size.to_le_bytes())?;
+        self.w.seek(SeekFrom::Start(end))?;
+
+        self.entries.push(NpzEntry { name, crc, size, offset });
+        Ok(())
+    }
+
+    /// Adds a one dimensional array
+    pub fn add<T: NpyElement>(&mut self, name: &str, data: &[T]) -> io::Result<()> {
+        self.entry(name, |w| write_npy(w, data))
+    }
+
+    /// Adds a zero dimensional array
+    pub fn add_scalar<T: NpyElement>(&mut self, name: &str, x: T) -> io::Result<()> {
+        self.entry(name, |w| {
+                       write_header(w, T::DESCR, &[])?;
+                       x.write_le(w)
+                   })
+    }
+
+    /// Adds a zero dimensional byte string, i.e. numpy's `bytes_` type
+    pub fn add_bytes(&mut self, name: &str, s: &[u8]) -> io::Result<()> {
+        self.entry(name, |w| {
+                       write_header(w, &format!("|S{}", s.len()), &[])?;
+                       w.write_all(s)
+                   })
+    }
+
+    /// Writes the central directory and returns the underlying writer
+    pub fn finish(mut self) -> io::Result<W> {
+        let cd_offset = self.w.stream_position()?;
+        for e in self.entries.iter() {
+            self.w.write_all(&CENTRAL_HEADER.to_le_bytes())?;
+            for &x in [ZIP_VERSION, ZIP_VERSION, 0, 0, 0, DOS_DATE].iter() {
+                self.w.write_all(&x.to_le_bytes())?;
+            }
+            self.w.write_all(&e.crc.to_le_bytes())?;
+            self.w.write_all(&(!0_u32).to_le_bytes())?;
+            self.w.write_all(&(!0_u32).to_le_bytes())?;
+            self.w.write_all(&(e.name.len() as u16).to_le_bytes())?;
+            self.w.write_all(&28_u16.to_le_bytes())?;
+            // comment length, disk number and internal attributes
+            self.w.write_all(&[0; 6])?;
+            // external attributes
+            self.w.write_all(&[0; 4])?;
+            self.w.write_all(&(!0_u32).to_le_bytes())?;
+            self.w.write_all(e.name.as_bytes())?;
+            self.w.write_all(&1_u16.to_le_bytes())?;
+            self.w.write_all(&24_u16.to_le_bytes())?;
+            for &x in [e.size, e.size, e.offset].iter() {
+                self.w.write_all(&x.to_le_bytes())?;
+            }
+        }
+        let cd_end = self.w.stream_position()?;
+        let nentries = self.entries.len() as u64;
+
+        self.w.write_all(&ZIP64_END.to_le_bytes())?;
+        self.w.write_all(&44_u64.to_le_bytes())?;
+        self.w.write_all(&ZIP_VERSION.to_le_bytes())?;
+        self.w.write_all(&ZIP_VERSION.to_le_bytes())?;
+        self.w.write_all(&[0; 8])?;
+        for &x in [nentries, nentries, cd_end - cd_offset, cd_offset].iter() {
+            self.w.write_all(&x.to_le_bytes())?;
+        }
+
+        self.w.write_all(&ZIP64_LOCATOR.to_le_bytes())?;
+        self.w.write_all(&0_u32.to_le_bytes())?;
+        self.w.write_all(&cd_end.to_le_bytes())?;
+        self.w.write_all(&1_u32.to_le_bytes())?;
+
+        let n16 = nentries.min(0xffff) as u16;
+        self.w.write_all(&END.to_le_bytes())?;
+        self.w.write_all(&[0; 4])?;
+        self.w.write_all(&n16.to_le_bytes())?;
+        self.w.write_all(&n16.to_le_bytes())?;
+        self.w.write_all(&(!0_u32).to_le_bytes())?;
+        self.w.write_all(&(!0_u32).to_le_bytes())?;
+        self.w.write_all(&0_u16.to_le_bytes())?;
+        self.w.flush()?;
+        Ok(self.w)
+    }
+}
+
+// little endian fields at fixed offsets of a byte buffer
+fn le16(b: &[u8], i: usize) -> u16 { u16::from_le_bytes([b[i], b[i + 1]]) }
+
+fn le32(b: &[u8], i: usize) -> u32 {
+    u32::from_le_bytes([b[i], b[i + 1], b[i + 2], b[i + 3]])
+}
+
+fn le64(b: &[u8], i: usize) -> u64 {
+    let mut buf = [0; 8];
+    buf.copy_from_slice(&b[i..i + 8]);
+    u64::from_le_bytes(buf)
+}
+
+/// Reads named arrays out of an uncompressed `.npz` archive
+pub struct NpzReader<R: Read + Seek> {
+    r:       R,
+    // local header offset, size and compression method of each member
+    entries: FnvHashMap<String, (u64, u64, u16)>
+}
+
+impl NpzReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        NpzReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read + Seek> NpzReader<R> {
+    pub fn new(mut r: R) -> io::Result<NpzReader<R>> {
+        // the end of central directory record sits within the last 64 KiB,
+        // followed only by an optional comment
+        let len = r.seek(SeekFrom::End(0))?;
+        let tail_len = len.min(22 + 0xffff);
+        r.seek(SeekFrom::Start(len - tail_len))?;
+        let mut tail = vec![0; tail_len as usize];
+        r.read_exact(&mut tail)?;
+        let end = (0..tail.len().saturating_sub(21))
+            .rev()
+            .find(|&i| le32(&tail, i) == END)
+            .ok_or_else(|| invalid("not a zip archive"))?;
+        let mut nentries = le16(&tail, end + 10) as u64;
+        let mut cd_size = le32(&tail, end + 12) as u64;
+        let mut cd_offset = le32(&tail, end + 16) as u64;
+        if end >= 20 && le32(&tail, end - 20) == ZIP64_LOCATOR {
+            let mut record = [0; 56];
+            r.seek(SeekFrom::Start(le64(&tail, end - 12)))?;
+            r.read_exact(&mut record)?;
+            if le32(&record, 0) != ZIP64_END {
+                return Err(invalid("malformed zip64 archive"));
+            }
+            nentries = le64(&record, 32);
+            cd_size = le64(&record, 40);
+            cd_offset = le64(&record, 48);
+        }
+
+        if cd_offset.checked_add(cd_size).filter(|&e| e <= len).is_none() {
+            return Err(invalid("malformed central directory"));
+        }
+        let mut cd = vec![0; cd_size as usize];
+        r.seek(SeekFrom::Start(cd_offset))?;
+        r.read_exact(&mut cd)?;
+        let mut entries = FnvHashMap::default();
+        let mut i = 0;
+        for _ in 0..nentries {
+            if i + 46 > cd.len() || le32(&cd, i) != CENTRAL_HEADER {
+                return Err(invalid("malformed central directory"));
+            }
+            let method = le16(&cd, i + 10);
+            let compressed_size = le32(&cd, i + 20);
+            let mut size = le32(&cd, i + 24) as u64;
+            let mut offset = le32(&cd, i + 42) as u64;
+            let name_len = le16(&cd, i + 28) as usize;
+            let extra_len = le16(&cd, i + 30) as usize;
+            let comment_len = le16(&cd, i + 32) as usize;
+            let name_end = i + 46 + name_len;
+            let extra_end = name_end + extra_len;
+            if extra_end + comment_len > cd.len() {
+                return Err(invalid("malformed central directory"));
+            }
+            let name = String::from_utf8_lossy(&cd[i + 46..name_end]);
+            let name = name.trim_end_matches(".npy").to_string();
+
+            // the zip64 extra field holds, in order, whichever of the
+            // uncompressed size, compressed size and offset overflowed
+            let extra = &cd[name_end..extra_end];
+            let mut j = 0;
+            while j + 4 <= extra.len() {
+                let (id, len) = (le16(extra, j), le16(extra, j + 2) as usize);
+                let data = extra.get(j + 4..j + 4 + len)
+                                .ok_or_else(|| invalid("malformed extra field"))?;
+                if id == 1 {
+                    let mut values = data.chunks_exact(8).map(|b| le64(b, 0));
+                    let mut next = || {
+                        values.next()
+                              .ok_or_else(|| invalid("malformed zip64 extra field"))
+                    };
+                    if size == 0xffff_ffff {
+                        size = next()?;
+                    }
+                    if compressed_size == 0xffff_ffff {
+                        next()?;
+                    }
+                    if offset == 0xffff_ffff {
+                        offset = next()?;
+                    }
+                }
+                j += 4 + len;
+            }
+
+            entries.insert(name, (offset, size, method));
+            i += 46 + name_len + extra_len + comment_len;
+        }
+        Ok(NpzReader { r, entries })
+    }
+
+    pub fn contains(&self, name: &str) -> bool { self.entries.contains_key(name) }
+
+    // positions the reader at the start of the named member
+    fn seek_to(&mut self, name: &str) -> io::Result<io::Take<&mut R>> {
+        let (offset, size, method) = match self.entries.get(name) {
+            Some(&e) => e,
+            None => {
+                let msg = format!("no array named {} in archive", name);
+                return Err(io::Error::new(io::ErrorKind::NotFound, msg));
+            }
+        };
+        if method != 0 {
+            return Err(invalid("compressed archives are not supported"));
+        }
+        let mut header = [0; 30];
+        self.r.seek(SeekFrom::Start(offset))?;
+        self.r.read_exact(&mut header)?;
+        if le32(&header, 0) != LOCAL_HEADER {
+            return Err(invalid("malformed local header"));
+        }
+        let skip = le16(&header, 26) as i64 + le16(&header, 28) as i64;
+        self.r.seek(SeekFrom::Current(skip))?;
+        Ok((&mut self.r).take(size))
+    }
+
+    /// Reads the named array into a flat vector
+    pub fn array<T: NpyElement>(&mut self, name: &str) -> io::Result<Vec<T>> {
+        let mut r = self.seek_to(name)?;
+        read_npy(&mut r)
+    }
+
+    /// Reads the named array of any integer dtype
+    pub fn int_array(&mut self, name: &str) -> io::Result<Vec<i64>> {
+        let mut r = self.seek_to(name)?;
+        read_npy_int(&mut r)
+    }
+
+    /// Reads the named integer scalar
+    pub fn int(&mut self, name: &str) -> io::Result<i64> {
+        match self.int_array(name)?.as_slice() {
+            &[x] => Ok(x),
+            _ => Err(invalid(&format!("{} is not a scalar", name)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn crc32_test() {
+        let mut crc = Crc32::new();
+        crc.update(b"123456789");
+        assert_eq!(crc.finish(), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn npz_round_trip_test() {
+        let a = vec![1_u64, 2, 1 << 40];
+        let b = vec![Complex::new(0.5, -1.), Complex::new(3., 0.)];
+        let mut w = NpzWriter::new(Cursor::new(Vec::new()));
+        w.add("a", &a).unwrap();
+        w.add("b", &b).unwrap();
+        w.add_scalar("n", -1_i64).unwrap();
+        w.add_bytes("format", b"csr").unwrap();
+        let buf = w.finish().unwrap().into_inner();
+
+        let mut r = NpzReader::new(Cursor::new(buf)).unwrap();
+        assert!(r.contains("format"));
+        assert_eq!(r.array::<u64>("a").unwrap(), a);
+        assert_eq!(r.int_array("a").unwrap(), vec![1, 2, 1 << 40]);
+        assert_eq!(r.array::<Complex<f64>>("b").unwrap(), b);
+        assert_eq!(r.int("n").unwrap(), -1);
+        assert!(r.array::<f64>("a").is_err());
+        assert!(r.array::<f64>("c").is_err());
+    }
+
+    #[test]
+    fn malformed_npz_test() {
+        let mut w = NpzWriter::new(Cursor::new(Vec::new()));
+        w.add("a", &[1_u64, 2]).unwrap();
+        let buf = w.finish().unwrap().into_inner();
+        let rejected = |buf: Vec<u8>| match NpzReader::new(Cursor::new(buf)) {
+            Err(e) => e.kind() == io::ErrorKind::InvalidData,
+            Ok(_) => false
+        };
+
+        // a name length that runs past the central directory
+        let cd = (0..buf.len() - 4).find(|&i| le32(&buf, i) == CENTRAL_HEADER)
+                                   .unwrap();
+        let mut long_name = buf.clone();
+        long_name[cd + 28] = 0xff;
+        long_name[cd + 29] = 0xff;
+        assert!(rejected(long_name));
+
+        // a zip64 extra field too short for the values it should hold
+        let mut short_extra = buf.clone();
+        short_extra[cd + 46 + 5 + 2] = 8;
+        assert!(rejected(short_extra));
+
+        // a central directory larger than the file
+        let z = (0..buf.len() - 4).find(|&i| le32(&buf, i) == ZIP64_END).unwrap();
+        let mut large_cd = buf.clone();
+        large_cd[z + 47] = 0x7f;
+        assert!(rejected(large_cd));
+
+        // truncated and empty header values
+        for header in &["{'descr': '<u8', 'fortran_order': False, 'shape': )}",
+                        "{'descr':"] {
+            let mut npy = b"\x93NUMPY\x01\x00".to_vec();
+            npy.extend_from_slice(&(header.len() as u16).to_le_bytes());
+            npy.extend_from_slice(header.as_bytes());
+            let err = read_npy::<_, u64>(&mut Cursor::new(npy)).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
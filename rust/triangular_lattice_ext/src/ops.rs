@@ -1,14 +1,16 @@
 use blochfunc::{BlochFunc, BlochFuncSet};
 use common::*;
 use fnv::FnvHashMap;
+use rayon;
+use rayon::prelude::*;
 /// Operators generated by functions in this module assume translational
 /// symmetry and will work with systems regardless of whether total Sz is a good
 /// quantum number.
 use num_complex::Complex;
 
-pub fn ss_z_elements(sites: &(Vec<BinaryBasis>, Vec<BinaryBasis>),
-                     orig_state: &BlochFunc)
-                     -> f64 {
+pub fn ss_z_elements<P>(sites: &(Vec<BinaryBasis>, Vec<BinaryBasis>),
+                        orig_state: &BlochFunc<P>)
+                        -> f64 {
     let (ref site1, ref site2) = *sites;
     let mut same_dir = 0_i32;
     for (&s1, &s2) in site1.iter().zip(site2.iter()) {
@@ -29,13 +31,13 @@ pub fn ss_z_elements(sites: &(Vec<BinaryBasis>, Vec<BinaryBasis>),
 /// of s+ and s-, the 1/2 is already included in the output
 #[allow(non_snake_case)]
 #[allow(unused)]
-pub fn ss_xy_elements(nx: Dim, ny: Dim,
-                      sites: &(Vec<BinaryBasis>, Vec<BinaryBasis>),
-                      orig_state: &BlochFunc,
-                      dec_to_ind: &FnvHashMap<BinaryBasis, u32>,
-                      hashtable: &FnvHashMap<&BinaryBasis, &BlochFunc>)
-                      -> FnvHashMap<u32, Complex<f64>> {
-    let J = Complex::new(0.5, 0.);
+pub fn ss_xy_elements<P: Phase>(nx: Dim, ny: Dim,
+                                sites: &(Vec<BinaryBasis>, Vec<BinaryBasis>),
+                                orig_state: &BlochFunc<P>,
+                                dec_to_ind: &FnvHashMap<BinaryBasis, u32>,
+                                hashtable: &FnvHashMap<&BinaryBasis, &BlochFunc<P>>)
+                                -> FnvHashMap<u32, P> {
+    let J = P::from_real(0.5);
     let mut j_element = FnvHashMap::default();
     let (ref site1, ref site2) = *sites;
     for (&s1, &s2) in site1.iter().zip(site2.iter()) {
@@ -151,142 +153,531 @@ pub fn ss_pmz_elements(nx: Dim, ny: Dim,
     j_element
 }
 
-/// Generate the elements of the chiral term (\vec{S_1} \times \vec{S_2}) \cdot
-/// \vec{S_3} which could be written as 1/2 i Σ_{ijk} S^z_i (S^+_j S^-_k - S^-_j
-/// S^+_k). The factor of 1/2 is already included in the output
-#[allow(unused)]
-#[allow(non_snake_case)]
-pub fn sss_chi_elements(nx: Dim, ny: Dim,
-                        sites: &(Vec<BinaryBasis>,
-                         Vec<BinaryBasis>,
-                         Vec<BinaryBasis>),
-                        orig_state: &BlochFunc,
-                        dec_to_ind: &FnvHashMap<BinaryBasis, u32>,
-                        hashtable: &FnvHashMap<&BinaryBasis, &BlochFunc>)
-                        -> FnvHashMap<u32, Complex<f64>> {
-    let J = Complex::new(0., 0.5);
-    let mut j_element = FnvHashMap::default();
-    let (ref site1, ref site2, ref site3) = *sites;
-
-    let zip3 = site1.iter()
-                    .zip(site2.iter())
-                    .zip(site3.iter())
-                    .map(|((x, y), z)| (x, y, z));
-
-    for (&s1, &s2, &s3) in zip3 {
-        for _ in 0..3 {
-            let (mut si, mut sj, mut sk) = (s1, s2, s3);
-            let mut s_tmp: BinaryBasis;
-            for _ in 0..3 {
-                // switch ijk orders
-                s_tmp = si;
-                si = sj;
-                sj = sk;
-                sk = s_tmp;
-
-                let (updown, downup) = exchange_spin_flips(orig_state.lead, sj, sk);
-                let mut new_dec: BinaryBasis;
-                match (updown, downup) {
-                    (true, false) => new_dec = orig_state.lead - sj + sk,
-                    (false, true) => new_dec = orig_state.lead + sj - sk,
-                    _ => continue
-                }
-                match find_leading_state(new_dec, &hashtable) {
-                    None => (),
-                    Some((cntd_state, phase)) => {
-                        let j = *(dec_to_ind.get(&(cntd_state.lead)).unwrap());
-                        let coeff = phase * coeff(&orig_state, &cntd_state);
-
-                        let z_contrib = if orig_state.lead | si == orig_state.lead {
-                            0.5
-                        } else {
-                            -0.5
-                        };
-
-                        let element = match j_element.get(&j) {
-                            Some(&c) => c + J * z_contrib * coeff,
-                            None => J * z_contrib * coeff
-                        };
-                        j_element.insert(j, element);
-                    }
-                }
-            }
-        }
-    }
-    j_element
-}
-
-pub fn ss_z(sites: &(Vec<BinaryBasis>, Vec<BinaryBasis>), bfuncs: &BlochFuncSet)
-            -> CoordMatrix<CComplex<f64>> {
+pub fn ss_z<P, E>(sites: &(Vec<BinaryBasis>, Vec<BinaryBasis>),
+                  bfuncs: &BlochFuncSet<P>)
+                  -> CsrMatrix<E>
+    where P: Phase,
+          E: Element<P>
+{
     let dims = bfuncs.nonzero;
     let (ind_to_dec, _) = gen_ind_dec_conv_dicts(&bfuncs);
 
-    let mut data: Vec<CComplex<f64>> = Vec::with_capacity(dims as usize);
-    let cols = (0..dims as u32).collect::<Vec<u32>>();
+    let mut data: Vec<E> = Vec::with_capacity(dims as usize);
     let rows = (0..dims as u32).collect::<Vec<u32>>();
+    let cols = (0..dims).collect::<Vec<u32>>();
     for i in 0..dims as u32 {
         let orig_state = ind_to_dec.get(&i).unwrap();
         let i_element = ss_z_elements(&sites, &orig_state);
-        let re = i_element;
-        let im = 0.;
-        data.push(CComplex { re, im });
+        data.push(E::from_phase(P::from_real(i_element)));
     }
-    CoordMatrix::new(data, cols, rows, dims, dims)
+    CsrMatrix::from_triplets(data, rows, cols, dims, dims)
 }
 
-fn off_diag_ops<T>(element_f: fn(nx: Dim,
-                    ny: Dim,
-                    sites: &T,
-                    orig_state: &BlochFunc,
-                    dec_to_ind: &FnvHashMap<BinaryBasis, u32>,
-                    hashtable: &FnvHashMap<&BinaryBasis,
-                                &BlochFunc>)
-                    -> FnvHashMap<u32, Complex<f64>>,
-                   sites: &T, bfuncs: &BlochFuncSet)
-                   -> CoordMatrix<CComplex<f64>> {
+fn off_diag_ops<T, P, E>(element_f: fn(nx: Dim,
+                          ny: Dim,
+                          sites: &T,
+                          orig_state: &BlochFunc<P>,
+                          dec_to_ind: &FnvHashMap<BinaryBasis, u32>,
+                          hashtable: &FnvHashMap<&BinaryBasis,
+                                      &BlochFunc<P>>)
+                          -> FnvHashMap<u32, P>,
+                         sites: &T, bfuncs: &BlochFuncSet<P>)
+                         -> CsrMatrix<E>
+    where T: Sync + ?Sized,
+          P: Phase,
+          E: Element<P>
+{
     let dims = bfuncs.nonzero;
     let hashtable = BlochFuncSet::build_dict(&bfuncs);
     let (ind_to_dec, dec_to_ind) = gen_ind_dec_conv_dicts(&bfuncs);
 
-    let alloc_size = dims * (1 + 8 * (bfuncs.nx * bfuncs.ny).raw_int());
-    let mut data: Vec<CComplex<f64>> = Vec::with_capacity(alloc_size as usize);
-    let mut cols: Vec<u32> = Vec::with_capacity(alloc_size as usize);
-    let mut rows: Vec<u32> = Vec::with_capacity(alloc_size as usize);
-    for i in 0..dims as u32 {
-        let orig_state = ind_to_dec.get(&i).unwrap();
-        let ij_elements = element_f(bfuncs.nx,
-                                    bfuncs.ny,
-                                    sites,
-                                    &orig_state,
-                                    &dec_to_ind,
-                                    &hashtable);
-        for (j, entry) in ij_elements.into_iter() {
-            rows.push(i);
-            cols.push(j);
-            data.push(CComplex::from_num_complex(entry));
-        }
+    // original states are split into contiguous blocks that are handled in
+    // parallel, each into its own buffers. The buffers are then concatenated in
+    // block order so the output does not depend on the number of threads or on
+    // scheduling. <j|H|i> goes into row j and column i
+    let blocks = with_thread_pool(|| {
+        let nblocks = 4 * rayon::current_num_threads() as u32;
+        let block_size = dims.div_ceil(nblocks);
+        (0..nblocks).into_par_iter()
+                    .map(|b| {
+                        let start = (b * block_size).min(dims);
+                        let end = (start + block_size).min(dims);
+                        let mut data: Vec<E> = Vec::new();
+                        let mut cols: Vec<u32> = Vec::new();
+                        let mut rows: Vec<u32> = Vec::new();
+                        for i in start..end {
+                            let orig_state = ind_to_dec.get(&i).unwrap();
+                            let ij_elements = element_f(bfuncs.nx,
+                                                        bfuncs.ny,
+                                                        sites,
+                                                        orig_state,
+                                                        &dec_to_ind,
+                                                        &hashtable);
+                            for (j, entry) in ij_elements.into_iter() {
+                                rows.push(j);
+                                cols.push(i);
+                                data.push(E::from_phase(entry));
+                            }
+                        }
+                        (data, cols, rows)
+                    })
+                    .collect::<Vec<_>>()
+    });
+
+    let nnz = blocks.iter().map(|(d, _, _)| d.len()).sum::<usize>();
+    let mut data: Vec<E> = Vec::with_capacity(nnz);
+    let mut cols: Vec<u32> = Vec::with_capacity(nnz);
+    let mut rows: Vec<u32> = Vec::with_capacity(nnz);
+    for (d, c, r) in blocks.into_iter() {
+        data.extend(d);
+        cols.extend(c);
+        rows.extend(r);
     }
-    CoordMatrix::new(data, cols, rows, dims, dims)
+    CsrMatrix::from_triplets(data, rows, cols, dims, dims)
 }
 
-pub fn ss_xy(sites: &(Vec<BinaryBasis>, Vec<BinaryBasis>), bfuncs: &BlochFuncSet)
-             -> CoordMatrix<CComplex<f64>> {
+pub fn ss_xy<P, E>(sites: &(Vec<BinaryBasis>, Vec<BinaryBasis>),
+                   bfuncs: &BlochFuncSet<P>)
+                   -> CsrMatrix<E>
+    where P: Phase,
+          E: Element<P>
+{
     off_diag_ops(ss_xy_elements, &sites, &bfuncs)
 }
 
-pub fn ss_ppmm(sites: &(Vec<BinaryBasis>, Vec<BinaryBasis>),
-               bfuncs: &BlochFuncSet)
-               -> CoordMatrix<CComplex<f64>> {
+pub fn ss_ppmm<E>(sites: &(Vec<BinaryBasis>, Vec<BinaryBasis>),
+                  bfuncs: &BlochFuncSet)
+                  -> CsrMatrix<E>
+    where E: Element<Complex<f64>>
+{
     off_diag_ops(ss_ppmm_elements, &sites, &bfuncs)
 }
 
-pub fn ss_pmz(sites: &(Vec<BinaryBasis>, Vec<BinaryBasis>), bfuncs: &BlochFuncSet)
-              -> CoordMatrix<CComplex<f64>> {
+pub fn ss_pmz<E>(sites: &(Vec<BinaryBasis>, Vec<BinaryBasis>), bfuncs: &BlochFuncSet)
+                 -> CsrMatrix<E>
+    where E: Element<Complex<f64>>
+{
     off_diag_ops(ss_pmz_elements, &sites, &bfuncs)
 }
 
-pub fn sss_chi(sites: &(Vec<BinaryBasis>, Vec<BinaryBasis>, Vec<BinaryBasis>),
-               bfuncs: &BlochFuncSet)
-               -> CoordMatrix<CComplex<f64>> {
-    off_diag_ops(sss_chi_elements, &sites, &bfuncs)
+/// The scalar chirality Σ_Δ \vec{S_1} \cdot (\vec{S_2} \times \vec{S_3}) summed
+/// over the triangles given by `triangular_vert_sites`
+pub fn sss_chi<E>(sites: &(Vec<BinaryBasis>, Vec<BinaryBasis>, Vec<BinaryBasis>),
+                  bfuncs: &BlochFuncSet)
+                  -> CsrMatrix<E>
+    where E: Element<Complex<f64>>
+{
+    let (ref site1, ref site2, ref site3) = *sites;
+    let mut terms = Vec::with_capacity(6 * site1.len());
+    for ((&s1, &s2), &s3) in site1.iter().zip(site2.iter()).zip(site3.iter()) {
+        terms.extend(chirality_strings(s1, s2, s3));
+    }
+    op_strings(&terms, bfuncs)
+}
+
+/// Single-site spin operators used to build up the terms in an `OpString`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SpinOp {
+    Z,
+    Plus,
+    Minus
+}
+
+/// A product of single-site spin operators multiplied by a coefficient. The
+/// operators act on a state from right to left, i.e. the last operator in
+/// "ops" is applied first.
+#[derive(Clone, Debug)]
+pub struct OpString {
+    pub coeff: Complex<f64>,
+    pub ops:   Vec<(BinaryBasis, SpinOp)>
+}
+
+impl OpString {
+    pub fn new(coeff: Complex<f64>, ops: Vec<(BinaryBasis, SpinOp)>) -> OpString {
+        OpString { coeff, ops }
+    }
+
+    /// The operator product self * other
+    pub fn product(&self, other: &OpString) -> OpString {
+        let coeff = self.coeff * other.coeff;
+        let mut ops = self.ops.clone();
+        ops.extend(other.ops.iter().cloned());
+        OpString { coeff, ops }
+    }
+
+    /// Act on a single configuration. Returns None if the configuration is
+    /// annihilated by the operator string.
+    pub fn act_on(&self, dec: BinaryBasis) -> Option<(BinaryBasis, Complex<f64>)> {
+        let mut new_dec = dec;
+        let mut c = self.coeff;
+        for &(s, op) in self.ops.iter().rev() {
+            let up = new_dec | s == new_dec;
+            match (op, up) {
+                (SpinOp::Z, true) => c *= 0.5,
+                (SpinOp::Z, false) => c *= -0.5,
+                (SpinOp::Plus, false) => new_dec += s,
+                (SpinOp::Minus, true) => new_dec -= s,
+                _ => return None
+            }
+        }
+        Some((new_dec, c))
+    }
+}
+
+/// The terms of J S^z_i S^z_j
+pub fn ss_z_strings(si: BinaryBasis, sj: BinaryBasis, j: f64) -> Vec<OpString> {
+    let c = Complex::new(j, 0.);
+    vec![OpString::new(c, vec![(si, SpinOp::Z), (sj, SpinOp::Z)])]
+}
+
+/// The terms of J (S^x_i S^x_j + S^y_i S^y_j) = 1/2 J (S^+_i S^-_j + S^-_i S^+_j)
+pub fn ss_xy_strings(si: BinaryBasis, sj: BinaryBasis, j: f64) -> Vec<OpString> {
+    let c = Complex::new(0.5 * j, 0.);
+    vec![OpString::new(c, vec![(si, SpinOp::Plus), (sj, SpinOp::Minus)]),
+         OpString::new(c, vec![(si, SpinOp::Minus), (sj, SpinOp::Plus)])]
+}
+
+/// The terms of the scalar chirality \vec{S_1} \cdot (\vec{S_2} \times
+/// \vec{S_3}) = 1/2 i Σ_{ijk} S^z_i (S^+_j S^-_k - S^-_j S^+_k) where ijk runs
+/// over the cyclic permutations of 123
+pub fn chirality_strings(s1: BinaryBasis, s2: BinaryBasis, s3: BinaryBasis)
+                         -> Vec<OpString> {
+    let c = Complex::new(0., 0.5);
+    let mut strings = Vec::with_capacity(6);
+    for &(si, sj, sk) in [(s1, s2, s3), (s2, s3, s1), (s3, s1, s2)].iter() {
+        strings.push(OpString::new(c,
+                                   vec![(si, SpinOp::Z),
+                                        (sj, SpinOp::Plus),
+                                        (sk, SpinOp::Minus)]));
+        strings.push(OpString::new(-c,
+                                   vec![(si, SpinOp::Z),
+                                        (sj, SpinOp::Minus),
+                                        (sk, SpinOp::Plus)]));
+    }
+    strings
+}
+
+/// Generate the elements of an arbitrary translationally invariant sum of
+/// operator strings
+#[allow(unused)]
+pub fn op_string_elements(nx: Dim, ny: Dim, terms: &[OpString],
+                          orig_state: &BlochFunc,
+                          dec_to_ind: &FnvHashMap<BinaryBasis, u32>,
+                          hashtable: &FnvHashMap<&BinaryBasis, &BlochFunc>)
+                          -> FnvHashMap<u32, Complex<f64>> {
+    let mut j_element = FnvHashMap::default();
+    for term in terms.iter() {
+        let (new_dec, c) = match term.act_on(orig_state.lead) {
+            Some(x) => x,
+            None => continue
+        };
+        match find_leading_state(new_dec, hashtable) {
+            None => (),
+            Some((cntd_state, phase)) => {
+                let j = *(dec_to_ind.get(&(cntd_state.lead)).unwrap());
+                let coeff = phase * coeff(orig_state, cntd_state);
+
+                let element = match j_element.get(&j) {
+                    Some(&e) => e + c * coeff,
+                    None => c * coeff
+                };
+                j_element.insert(j, element);
+            }
+        }
+    }
+    j_element
+}
+
+pub fn op_strings<E>(terms: &[OpString], bfuncs: &BlochFuncSet)
+                     -> CsrMatrix<E>
+    where E: Element<Complex<f64>>
+{
+    off_diag_ops(op_string_elements, terms, bfuncs)
+}
+
+/// The chirality-chirality correlator Σ_r χ_Δ(r) χ_Δ'(r + d) where the pairs
+/// of triangles are given by `triangle_pairs`
+pub fn chi_chi<E>(pairs: &(Vec<Triangle>, Vec<Triangle>), bfuncs: &BlochFuncSet)
+                  -> CsrMatrix<E>
+    where E: Element<Complex<f64>>
+{
+    let (ref tri1, ref tri2) = *pairs;
+    let mut terms = Vec::new();
+    for (&(s1, s2, s3), &(s4, s5, s6)) in tri1.iter().zip(tri2.iter()) {
+        let chi1 = chirality_strings(s1, s2, s3);
+        let chi2 = chirality_strings(s4, s5, s6);
+        for a in chi1.iter() {
+            for b in chi2.iter() {
+                terms.push(a.product(b));
+            }
+        }
+    }
+    op_strings(&terms, bfuncs)
+}
+
+/// The chiral order parameter Σ_Δ χ_Δ summed over all upright and inverted
+/// triangles. When "staggered" is set the inverted triangles enter with a
+/// minus sign.
+pub fn chi_order<E>(triangles: &(Vec<Triangle>, Vec<Triangle>), staggered: bool,
+                    bfuncs: &BlochFuncSet)
+                    -> CsrMatrix<E>
+    where E: Element<Complex<f64>>
+{
+    let (ref up, ref down) = *triangles;
+    let sign = if staggered { -1. } else { 1. };
+    let mut terms = Vec::new();
+    for &(s1, s2, s3) in up.iter() {
+        terms.extend(chirality_strings(s1, s2, s3));
+    }
+    for &(s1, s2, s3) in down.iter() {
+        for mut t in chirality_strings(s1, s2, s3).into_iter() {
+            t.coeff *= sign;
+            terms.push(t);
+        }
+    }
+    op_strings(&terms, bfuncs)
+}
+
+/// The site-dependent longitudinal field Σ_r h_r S^z_r
+pub fn s_z<E>(fields: &[(BinaryBasis, f64)], bfuncs: &BlochFuncSet)
+              -> CsrMatrix<E>
+    where E: Element<Complex<f64>>
+{
+    let terms = fields.iter()
+                      .map(|&(s, h)| {
+                               let c = Complex::new(h, 0.);
+                               OpString::new(c, vec![(s, SpinOp::Z)])
+                           })
+                      .collect::<Vec<OpString>>();
+    op_strings(&terms, bfuncs)
+}
+
+/// The site-dependent transverse field Σ_r (hx_r S^x_r + hy_r S^y_r) which is
+/// written as 1/2 Σ_r [(hx_r - i hy_r) S^+_r + (hx_r + i hy_r) S^-_r]
+pub fn s_xy<E>(fields: &[(BinaryBasis, (f64, f64))], bfuncs: &BlochFuncSet)
+               -> CsrMatrix<E>
+    where E: Element<Complex<f64>>
+{
+    let mut terms = Vec::with_capacity(2 * fields.len());
+    for &(s, (hx, hy)) in fields.iter() {
+        terms.push(OpString::new(Complex::new(0.5 * hx, -0.5 * hy),
+                                 vec![(s, SpinOp::Plus)]));
+        terms.push(OpString::new(Complex::new(0.5 * hx, 0.5 * hy),
+                                 vec![(s, SpinOp::Minus)]));
+    }
+    op_strings(&terms, bfuncs)
+}
+
+/// The terms of the Dzyaloshinskii-Moriya interaction D · (\vec{S_i} \times
+/// \vec{S_j}). The z-component is 1/2 i D_z (S^+_i S^-_j - S^-_i S^+_j) while
+/// the in-plane components are S^z_j (a S^+_i + a* S^-_i) - S^z_i (a S^+_j + a*
+/// S^-_j) with a = -(D_y + i D_x) / 2
+pub fn dm_strings(si: BinaryBasis, sj: BinaryBasis, d: &[f64; 3]) -> Vec<OpString> {
+    let (z, p, m) = (SpinOp::Z, SpinOp::Plus, SpinOp::Minus);
+    let mut strings = Vec::with_capacity(6);
+    if d[2] != 0. {
+        let c = Complex::new(0., 0.5 * d[2]);
+        strings.push(OpString::new(c, vec![(si, p), (sj, m)]));
+        strings.push(OpString::new(-c, vec![(si, m), (sj, p)]));
+    }
+    if d[0] != 0. || d[1] != 0. {
+        let a = Complex::new(-0.5 * d[1], -0.5 * d[0]);
+        strings.push(OpString::new(a, vec![(sj, z), (si, p)]));
+        strings.push(OpString::new(a.conj(), vec![(sj, z), (si, m)]));
+        strings.push(OpString::new(-a, vec![(si, z), (sj, p)]));
+        strings.push(OpString::new(-a.conj(), vec![(si, z), (sj, m)]));
+    }
+    strings
+}
+
+/// The Dzyaloshinskii-Moriya interaction Σ_<ij> D_ij · (\vec{S_i} \times
+/// \vec{S_j}) where D_ij is taken from "dvecs" according to the direction of
+/// the bond as given by `directed_interacting_sites`
+pub fn ss_dm<E>(sites: &DirectedBonds, dvecs: &[[f64; 3]; 3], bfuncs: &BlochFuncSet)
+                -> CsrMatrix<E>
+    where E: Element<Complex<f64>>
+{
+    let (ref site1, ref site2, ref dirs) = *sites;
+    let mut terms = Vec::new();
+    for ((&si, &sj), &d) in site1.iter().zip(site2.iter()).zip(dirs.iter()) {
+        terms.extend(dm_strings(si, sj, &dvecs[d]));
+    }
+    op_strings(&terms, bfuncs)
+}
+
+/// Cyclically permute the spins on the sites s1 -> s2 -> s3 -> s4 -> s1
+pub fn ring_permute(dec: BinaryBasis, s: &[BinaryBasis; 4]) -> BinaryBasis {
+    let mut new_dec = dec;
+    for i in 0..4 {
+        let (from, to) = (s[i], s[(i + 1) % 4]);
+        let up = dec | from == dec;
+        let up_now = new_dec | to == new_dec;
+        match (up, up_now) {
+            (true, false) => new_dec += to,
+            (false, true) => new_dec -= to,
+            _ => ()
+        }
+    }
+    new_dec
+}
+
+/// Generate the elements of the four-spin ring exchange P_ijkl + P_ijkl^-1 on
+/// every rhombus, where P_ijkl cyclically permutes the spins on sites ijkl
+#[allow(non_snake_case)]
+#[allow(unused)]
+pub fn ring_elements<P: Phase>(nx: Dim, ny: Dim, sites: &RhombusSites,
+                               orig_state: &BlochFunc<P>,
+                               dec_to_ind: &FnvHashMap<BinaryBasis, u32>,
+                               hashtable: &FnvHashMap<&BinaryBasis, &BlochFunc<P>>)
+                               -> FnvHashMap<u32, P> {
+    let J = P::from_real(1.);
+    let mut j_element = FnvHashMap::default();
+    let (ref site1, ref site2, ref site3, ref site4) = *sites;
+
+    let zip4 = site1.iter()
+                    .zip(site2.iter())
+                    .zip(site3.iter())
+                    .zip(site4.iter())
+                    .map(|(((w, x), y), z)| (w, x, y, z));
+
+    for (&s1, &s2, &s3, &s4) in zip4 {
+        // P_ijkl and its inverse P_ilkj
+        for s in [[s1, s2, s3, s4], [s1, s4, s3, s2]].iter() {
+            let new_dec = ring_permute(orig_state.lead, s);
+            match find_leading_state(new_dec, hashtable) {
+                None => (),
+                Some((cntd_state, phase)) => {
+                    let j = *(dec_to_ind.get(&(cntd_state.lead)).unwrap());
+                    let coeff = phase * coeff(orig_state, cntd_state);
+
+                    let element = match j_element.get(&j) {
+                        Some(&c) => c + J * coeff,
+                        None => J * coeff
+                    };
+                    j_element.insert(j, element);
+                }
+            }
+        }
+    }
+    j_element
+}
+
+pub fn ring<P, E>(sites: &RhombusSites, bfuncs: &BlochFuncSet<P>) -> CsrMatrix<E>
+    where P: Phase,
+          E: Element<P>
+{
+    off_diag_ops(ring_elements, sites, bfuncs)
+}
+
+/// The terms of Σ_ab J^ab S^a_i S^b_j for a general exchange matrix J. Writing
+/// S^a = Σ_μ U_aμ S^μ with μ = +, -, z gives the coefficient (U^T J U)_μν for
+/// each S^μ_i S^ν_j
+pub fn exchange_strings(si: BinaryBasis, sj: BinaryBasis, jmat: &[[f64; 3]; 3])
+                        -> Vec<OpString> {
+    let zero = Complex::new(0., 0.);
+    let half = Complex::new(0.5, 0.);
+    let ihalf = Complex::new(0., 0.5);
+    // rows x, y, z and columns +, -, z
+    let u = [[half, half, zero],
+             [-ihalf, ihalf, zero],
+             [zero, zero, Complex::new(1., 0.)]];
+    let ops = [SpinOp::Plus, SpinOp::Minus, SpinOp::Z];
+    let mut strings = Vec::new();
+    for mu in 0..3 {
+        for nu in 0..3 {
+            let mut c = zero;
+            for a in 0..3 {
+                for b in 0..3 {
+                    c += u[a][mu] * jmat[a][b] * u[b][nu];
+                }
+            }
+            if c.norm() > 1e-14 {
+                strings.push(OpString::new(c, vec![(si, ops[mu]), (sj, ops[nu])]));
+            }
+        }
+    }
+    strings
+}
+
+/// The exchange interaction Σ_<ij> Σ_ab J_ij^ab S^a_i S^b_j where the 3x3
+/// matrix J_ij is taken from "jmats" according to the direction of the bond as
+/// given by `directed_interacting_sites`
+pub fn ss_tensor<E>(sites: &DirectedBonds, jmats: &[[[f64; 3]; 3]; 3],
+                    bfuncs: &BlochFuncSet)
+                    -> CsrMatrix<E>
+    where E: Element<Complex<f64>>
+{
+    let (ref site1, ref site2, ref dirs) = *sites;
+    let mut terms = Vec::new();
+    for ((&si, &sj), &d) in site1.iter().zip(site2.iter()).zip(dirs.iter()) {
+        terms.extend(exchange_strings(si, sj, &jmats[d]));
+    }
+    op_strings(&terms, bfuncs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use consv;
+
+    fn dense(mat: &CsrMatrix<CComplex<f64>>) -> Vec<Vec<Complex<f64>>> {
+        let n = mat.nrows as usize;
+        let mut a = vec![vec![Complex::new(0., 0.); n]; n];
+        for (i, j, c) in mat.entries() {
+            a[i as usize][j as usize] = Complex::new(c.re, c.im);
+        }
+        a
+    }
+
+    #[test]
+    fn sss_chi_hermitian_test() {
+        let (nx, ny) = (Dim(3), Dim(3));
+        let sites = triangular_vert_sites(nx, ny);
+        let bfuncs = consv::k::bloch_states(nx, ny, K(1), K(2));
+        let a = dense(&sss_chi(&sites, &bfuncs));
+        for (i, row) in a.iter().enumerate() {
+            for (j, &c) in row.iter().enumerate() {
+                assert!((c - a[j][i].conj()).norm() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn sss_chi_triangle_test() {
+        // every configuration of three sites as a Bloch function of its own,
+        // i.e. the plain basis without translations
+        let bfuncs = (0..8).map(|d| {
+                               let mut decs = FnvHashMap::default();
+                               decs.insert(BinaryBasis(d), Complex::new(1., 0.));
+                               BlochFunc { lead: BinaryBasis(d), decs, norm: 1. }
+                           })
+                           .collect();
+        let bfuncs = BlochFuncSet::create(Dim(3), Dim(1), bfuncs);
+        let sites = (vec![BinaryBasis(1)],
+                     vec![BinaryBasis(2)],
+                     vec![BinaryBasis(4)]);
+        let a = dense(&sss_chi(&sites, &bfuncs));
+
+        // spin-1/2 matrices indexed by [out][in] with 1 for spin up
+        let (h, i) = (Complex::new(0.5, 0.), Complex::new(0., 0.5));
+        let zero = Complex::new(0., 0.);
+        let s = [[[zero, h], [h, zero]],
+                 [[zero, i], [-i, zero]],
+                 [[-h, zero], [zero, h]]];
+        // S1 · (S2 × S3) = Σ ε_abc S1^a S2^b S3^c
+        let eps = [(0, 1, 2, 1.), (1, 2, 0, 1.), (2, 0, 1, 1.),
+                   (0, 2, 1, -1.), (2, 1, 0, -1.), (1, 0, 2, -1.)];
+        for out in 0..8_usize {
+            for inp in 0..8_usize {
+                let bit = |d: usize, k: usize| (d >> k) & 1;
+                let expected = eps.iter().fold(zero, |acc, &(x, y, z, sign)| {
+                    acc + s[x][bit(out, 0)][bit(inp, 0)]
+                          * s[y][bit(out, 1)][bit(inp, 1)]
+                          * s[z][bit(out, 2)][bit(inp, 2)]
+                          * sign
+                });
+                assert!((a[out][inp] - expected).norm() < 1e-12);
+            }
+        }
+    }
 }
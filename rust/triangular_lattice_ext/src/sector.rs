@@ -0,0 +1,640 @@
+/// A momentum sector whose Bloch basis is built once and shared by every
+/// operator constructed against it. Building the basis dominates the cost of
+/// small operators, so sweeps over many observables in the same sector should
+/// go through here rather than through the functions in `consv`.
+use fnv::FnvHashMap;
+use num_complex::Complex;
+use rayon::prelude::*;
+use std::{io, path::Path};
+
+use blochfunc::{BlochFunc, BlochFuncSet};
+use common::*;
+use consv;
+use npy::{NpyElement, NpzReader, NpzWriter};
+use ops;
+
+/// The quantum numbers a basis or matrix was built for. Saved alongside them
+/// so files could be told apart without recomputing anything.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SectorInfo {
+    pub nx:  Dim,
+    pub ny:  Dim,
+    pub kx:  K,
+    pub ky:  K,
+    pub nup: Option<u32>
+}
+
+impl SectorInfo {
+    // stored as integer scalars, with nup = -1 when total Sz is not fixed
+    fn write<W>(&self, w: &mut NpzWriter<W>) -> io::Result<()>
+        where W: io::Write + io::Seek
+    {
+        w.add_scalar("nx", self.nx.raw_int() as i64)?;
+        w.add_scalar("ny", self.ny.raw_int() as i64)?;
+        w.add_scalar("kx", self.kx.raw_int() as i64)?;
+        w.add_scalar("ky", self.ky.raw_int() as i64)?;
+        w.add_scalar("nup", self.nup.map_or(-1, |n| n as i64))
+    }
+
+    // the quantum numbers are checked as they would be by the functions that
+    // build a sector, so a bad file is caught here and not by an operator
+    fn read<R>(r: &mut NpzReader<R>) -> io::Result<SectorInfo>
+        where R: io::Read + io::Seek
+    {
+        // -1 stands for no nup, and any other negative number is invalid
+        let mut int = |name: &str| match r.int(name)? {
+            n if n < -1 || n > u32::MAX as i64 => {
+                Err(invalid(&format!("{} = {} is out of range", name, n)))
+            }
+            n => Ok(n)
+        };
+        let (nx, ny) = (Dim(int("nx")? as u32), Dim(int("ny")? as u32));
+        let (kx, ky) = (K(int("kx")? as u32), K(int("ky")? as u32));
+        let nup = match int("nup")? {
+            -1 => None,
+            n => Some(n as u32)
+        };
+        let check = || {
+            check_lattice(nx, ny)?;
+            check_momentum(nx, ny, kx, ky)?;
+            nup.map_or(Ok(()), |n| check_nup(nx, ny, n))
+        };
+        check().map_err(|e| invalid(&e.to_string()))?;
+        Ok(SectorInfo { nx, ny, kx, ky, nup })
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+pub struct Sector {
+    nx:     Dim,
+    ny:     Dim,
+    kx:     K,
+    ky:     K,
+    nup:    Option<u32>,
+    bfuncs: BlochFuncSet
+}
+
+impl Sector {
+    /// The sector with lattice momentum (kx, ky)
+    pub fn k(nx: Dim, ny: Dim, kx: K, ky: K) -> Sector {
+        let bfuncs = consv::k::bloch_states(nx, ny, kx, ky);
+        Sector { nx, ny, kx, ky, nup: None, bfuncs }
+    }
+
+    /// The sector with lattice momentum (kx, ky) and nup spins pointing up
+    pub fn ks(nx: Dim, ny: Dim, kx: K, ky: K, nup: u32) -> Sector {
+        let bfuncs = consv::ks::bloch_states(nx, ny, kx, ky, nup);
+        Sector { nx, ny, kx, ky, nup: Some(nup), bfuncs }
+    }
+
+    fn from_bfuncs(info: SectorInfo, bfuncs: Vec<BlochFunc>) -> Sector {
+        let SectorInfo { nx, ny, kx, ky, nup } = info;
+        let mut bfuncs = BlochFuncSet::create(nx, ny, bfuncs);
+        bfuncs.sort();
+        Sector { nx, ny, kx, ky, nup, bfuncs }
+    }
+
+    pub fn dim(&self) -> u32 { self.bfuncs.nonzero }
+
+    pub fn info(&self) -> SectorInfo {
+        SectorInfo { nx:  self.nx,
+                     ny:  self.ny,
+                     kx:  self.kx,
+                     ky:  self.ky,
+                     nup: self.nup }
+    }
+
+    /// The sector at momentum -k. The Bloch functions there are the complex
+    /// conjugates of those at k, so it is built from this one without
+    /// enumerating any orbits and its basis is ordered the same way.
+    pub fn inverse(&self) -> Sector {
+        let (kx, ky) = inverse_momentum(self.nx, self.ny, self.kx, self.ky);
+        let bfuncs = self.bfuncs
+                         .iter()
+                         .map(|b| {
+                                  let decs = b.decs
+                                              .iter()
+                                              .map(|(&dec, p)| (dec, p.conj()))
+                                              .collect();
+                                  BlochFunc { lead: b.lead, decs, norm: b.norm }
+                              })
+                         .collect();
+        Sector::from_bfuncs(SectorInfo { kx, ky, ..self.info() }, bfuncs)
+    }
+
+    /// The images of vectors in this sector under the inversion r -> -r,
+    /// written in the basis of `inverse`. When the Hamiltonian commutes with
+    /// inversion (see `model::Model::is_inversion_symmetric`) eigenvectors at k
+    /// are carried onto eigenvectors at -k with the same energies, so only one
+    /// of the two sectors needs to be diagonalized.
+    pub fn invert(&self, vectors: &[Vec<Complex<f64>>]) -> Vec<Vec<Complex<f64>>> {
+        let hashtable = BlochFuncSet::build_dict(&self.bfuncs);
+        let (_, dec_to_ind) = gen_ind_dec_conv_dicts(&self.bfuncs);
+        // inversion sends the lead of each Bloch function into another orbit,
+        // which fixes the Bloch function at -k it is carried onto and, by
+        // comparing the coefficients of the inverted lead, the factor between
+        // the two. Coefficients at -k are the conjugates of those stored here
+        let images = self.bfuncs
+                         .iter()
+                         .map(|b| {
+                                  let dec = invert(b.lead, self.nx, self.ny);
+                                  let image = hashtable[&dec];
+                                  let p = b.decs[&b.lead] / b.norm;
+                                  let q = image.decs[&dec].conj() / image.norm;
+                                  (dec_to_ind[&image.lead] as usize, p / q)
+                              })
+                         .collect::<Vec<_>>();
+        vectors.iter()
+               .map(|v| {
+                        assert_eq!(v.len(), images.len());
+                        let mut w = vec![Complex::new(0., 0.); v.len()];
+                        for (&(j, phase), &x) in images.iter().zip(v) {
+                            w[j] = phase * x;
+                        }
+                        w
+                    })
+               .collect()
+    }
+
+    /// Saves the basis as an `.npz` archive. Besides the quantum numbers in
+    /// `SectorInfo` the archive holds
+    ///
+    /// ```text
+    /// leads    <u8 (dim,)      the leading state of each Bloch function
+    /// norms    <f8 (dim,)      the norm of each Bloch function
+    /// offsets  <u8 (dim + 1,)  where each Bloch function starts in
+    ///                          states and phases
+    /// states   <u8             the states making up the Bloch functions
+    /// phases   <c16            the coefficients of the states
+    /// ```
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut leads = Vec::with_capacity(self.dim() as usize);
+        let mut norms = Vec::with_capacity(self.dim() as usize);
+        let mut offsets = vec![0_u64];
+        let mut states = Vec::new();
+        let mut phases = Vec::new();
+        for bfunc in self.bfuncs.iter() {
+            leads.push(bfunc.lead.raw_int());
+            norms.push(bfunc.norm);
+            for (&dec, &p) in bfunc.decs.iter() {
+                states.push(dec.raw_int());
+                phases.push(p);
+            }
+            offsets.push(states.len() as u64);
+        }
+
+        let mut w = NpzWriter::create(path)?;
+        self.info().write(&mut w)?;
+        w.add("leads", &leads)?;
+        w.add("norms", &norms)?;
+        w.add("offsets", &offsets)?;
+        w.add("states", &states)?;
+        w.add("phases", &phases)?;
+        w.finish()?;
+        Ok(())
+    }
+
+    /// Loads a basis saved by `save`
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Sector> {
+        let mut r = NpzReader::open(path)?;
+        let info = SectorInfo::read(&mut r)?;
+        let leads = r.array::<u64>("leads")?;
+        let norms = r.array::<f64>("norms")?;
+        let offsets = r.array::<u64>("offsets")?;
+        let states = r.array::<u64>("states")?;
+        let phases = r.array::<Complex<f64>>("phases")?;
+        if norms.len() != leads.len()
+           || offsets.len() != leads.len() + 1
+           || phases.len() != states.len()
+           || offsets.first() != Some(&0)
+           || offsets.last() != Some(&(states.len() as u64))
+           || offsets.windows(2).any(|w| w[0] > w[1])
+        {
+            return Err(invalid("inconsistent array lengths in saved sector"));
+        }
+        let nstates = 1_u64 << (info.nx * info.ny).raw_int();
+        if leads.iter().chain(states.iter()).any(|&dec| dec >= nstates) {
+            return Err(invalid("a saved state does not fit on the lattice"));
+        }
+
+        let bfuncs = (0..leads.len())
+            .map(|i| {
+                let (a, b) = (offsets[i] as usize, offsets[i + 1] as usize);
+                let decs = (a..b).map(|j| (BinaryBasis(states[j]), phases[j]))
+                                  .collect::<FnvHashMap<_, _>>();
+                BlochFunc { lead: BinaryBasis(leads[i]),
+                            decs,
+                            norm: norms[i] }
+            })
+            .collect();
+        Ok(Sector::from_bfuncs(info, bfuncs))
+    }
+
+    // operators that do not conserve total Sz would be silently truncated in a
+    // sector with a fixed number of up spins
+    fn check_sz_free(&self) -> Result<(), InputError> {
+        match self.nup {
+            Some(_) => Err(InputError::SzConservation(false)),
+            None => Ok(())
+        }
+    }
+
+    pub fn h_ss_z<E>(&self, l: I) -> CsrMatrix<E>
+        where E: Element<Complex<f64>>
+    {
+        let sites = interacting_sites(self.nx, self.ny, l);
+        ops::ss_z(&sites, &self.bfuncs)
+    }
+
+    pub fn h_ss_xy<E>(&self, l: I) -> CsrMatrix<E>
+        where E: Element<Complex<f64>>
+    {
+        let sites = interacting_sites(self.nx, self.ny, l);
+        ops::ss_xy(&sites, &self.bfuncs)
+    }
+
+    /// Fails if the sector fixes nup, since the operator does not conserve
+    /// total Sz
+    pub fn h_ss_ppmm<E>(&self, l: I) -> Result<CsrMatrix<E>, InputError>
+        where E: Element<Complex<f64>>
+    {
+        self.check_sz_free()?;
+        let sites = interacting_sites(self.nx, self.ny, l);
+        Ok(ops::ss_ppmm(&sites, &self.bfuncs))
+    }
+
+    /// Fails if the sector fixes nup, since the operator does not conserve
+    /// total Sz
+    pub fn h_ss_pmz<E>(&self, l: I) -> Result<CsrMatrix<E>, InputError>
+        where E: Element<Complex<f64>>
+    {
+        self.check_sz_free()?;
+        let sites = interacting_sites(self.nx, self.ny, l);
+        Ok(ops::ss_pmz(&sites, &self.bfuncs))
+    }
+
+    pub fn h_sss_chi<E>(&self) -> CsrMatrix<E>
+        where E: Element<Complex<f64>>
+    {
+        let sites = triangular_vert_sites(self.nx, self.ny);
+        ops::sss_chi(&sites, &self.bfuncs)
+    }
+
+    pub fn h_ring<E>(&self) -> CsrMatrix<E>
+        where E: Element<Complex<f64>>
+    {
+        let sites = rhombus_sites(self.nx, self.ny);
+        ops::ring(&sites, &self.bfuncs)
+    }
+
+    /// See `consv::k::h_ss_tensor`. Fails if the sector fixes nup and the
+    /// exchange matrices are not symmetric under rotations about the z-axis.
+    pub fn h_ss_tensor<E>(&self, l: I, jmats: &[[[f64; 3]; 3]; 3])
+                          -> Result<CsrMatrix<E>, InputError>
+        where E: Element<Complex<f64>>
+    {
+        let conserves_sz = jmats.iter().all(|j| {
+            j[0][0] == j[1][1] && j[0][1] == -j[1][0] && j[0][2] == 0.
+            && j[1][2] == 0. && j[2][0] == 0. && j[2][1] == 0.
+        });
+        if !conserves_sz {
+            self.check_sz_free()?;
+        }
+        let sites = directed_interacting_sites(self.nx, self.ny, l);
+        Ok(ops::ss_tensor(&sites, jmats, &self.bfuncs))
+    }
+
+    /// See `consv::k::h_ss_dm`. Fails if the sector fixes nup and the in-plane
+    /// components of D do not vanish.
+    pub fn h_ss_dm<E>(&self, l: I, dvecs: &[[f64; 3]; 3])
+                      -> Result<CsrMatrix<E>, InputError>
+        where E: Element<Complex<f64>>
+    {
+        if dvecs.iter().any(|d| d[0] != 0. || d[1] != 0.) {
+            self.check_sz_free()?;
+        }
+        let sites = directed_interacting_sites(self.nx, self.ny, l);
+        Ok(ops::ss_dm(&sites, dvecs, &self.bfuncs))
+    }
+
+    pub fn ss_z<E>(&self, l: I) -> CsrMatrix<E>
+        where E: Element<Complex<f64>>
+    {
+        let sites = all_sites(self.nx, self.ny, l);
+        ops::ss_z(&sites, &self.bfuncs)
+    }
+
+    pub fn ss_xy<E>(&self, l: I) -> CsrMatrix<E>
+        where E: Element<Complex<f64>>
+    {
+        let sites = all_sites(self.nx, self.ny, l);
+        ops::ss_xy(&sites, &self.bfuncs)
+    }
+
+    pub fn chi_chi<E>(&self, dx: I, dy: I, o1: Orientation, o2: Orientation)
+                      -> CsrMatrix<E>
+        where E: Element<Complex<f64>>
+    {
+        let pairs = triangle_pairs(self.nx, self.ny, dx, dy, o1, o2);
+        ops::chi_chi(&pairs, &self.bfuncs)
+    }
+
+    pub fn chi_order<E>(&self, staggered: bool) -> CsrMatrix<E>
+        where E: Element<Complex<f64>>
+    {
+        let triangles = oriented_triangles(self.nx, self.ny);
+        ops::chi_order(&triangles, staggered, &self.bfuncs)
+    }
+}
+
+/// Saves a matrix built in the sector described by info as an `.npz` archive
+/// laid out the way `scipy.sparse.save_npz` does, so `scipy.sparse.load_npz`
+/// reads it directly. The quantum numbers in `SectorInfo` are stored alongside.
+pub fn save_matrix<P, T>(path: P, mat: &CsrMatrix<T>, info: &SectorInfo)
+                         -> io::Result<()>
+    where P: AsRef<Path>,
+          T: NpyElement
+{
+    let (data, indices, indptr) = mat.as_slices();
+    let mut w = NpzWriter::create(path)?;
+    w.add_bytes("format", b"csr")?;
+    w.add("shape", &[mat.nrows as i64, mat.ncols as i64])?;
+    w.add("data", data)?;
+    w.add("indices", indices)?;
+    w.add("indptr", indptr)?;
+    info.write(&mut w)?;
+    w.finish()?;
+    Ok(())
+}
+
+/// Loads a matrix saved by `save_matrix`, or by the Python side
+pub fn load_matrix<P, T>(path: P) -> io::Result<(CsrMatrix<T>, SectorInfo)>
+    where P: AsRef<Path>,
+          T: NpyElement
+{
+    let mut r = NpzReader::open(path)?;
+    let info = SectorInfo::read(&mut r)?;
+    let shape = r.int_array("shape")?;
+    let data = r.array::<T>("data")?;
+    let indices = r.int_array("indices")?;
+    let indptr = r.int_array("indptr")?;
+    let in_range = |n: i64| 0 <= n && n <= u32::MAX as i64;
+    let (nrows, ncols) = match shape[..] {
+        [nrows, ncols] if in_range(nrows) && in_range(ncols) => (nrows, ncols),
+        _ => return Err(invalid("the shape of the saved matrix is invalid"))
+    };
+    if indptr.len() as i64 != nrows + 1
+       || indices.len() != data.len()
+       || indptr.first() != Some(&0)
+       || indptr.last() != Some(&(data.len() as i64))
+       || indptr.windows(2).any(|w| w[0] > w[1])
+    {
+        return Err(invalid("inconsistent array lengths in saved matrix"));
+    }
+    if indices.iter().any(|&c| c < 0 || c >= ncols) {
+        return Err(invalid("a column index of the saved matrix is out of range"));
+    }
+    let indices = indices.into_iter().map(|i| i as u32).collect();
+    let indptr = indptr.into_iter().map(|i| i as u64).collect();
+    let mat =
+        CsrMatrix::from_parts(data, indices, indptr, nrows as u32, ncols as u32);
+    Ok((mat, info))
+}
+
+/// The translation orbits of the lattice. These are the same for every
+/// momentum; only the phases and which orbits survive depend on k. Enumerating
+/// them once and building each sector from them means scanning the whole
+/// Brillouin zone costs roughly one enumeration.
+pub struct Orbits {
+    nx:    Dim,
+    ny:    Dim,
+    nup:   Option<u32>,
+    leads: Vec<BinaryBasis>
+}
+
+impl Orbits {
+    pub fn new(nx: Dim, ny: Dim) -> Orbits {
+        let nstates = 2_usize.pow((nx * ny).raw_int());
+        let trans = Translations::new(nx, ny);
+        let leads = with_thread_pool(|| {
+            (0..nstates).into_par_iter()
+                        .map(|dec| BinaryBasis(dec as u64))
+                        .filter(|&dec| is_orbit_lead(dec, &trans))
+                        .collect()
+        });
+        Orbits { nx, ny, nup: None, leads }
+    }
+
+    /// Orbits of states with nup spins pointing up
+    pub fn with_nup(nx: Dim, ny: Dim, nup: u32) -> Orbits {
+        let states = sz_basis(nx * ny, nup);
+        let trans = Translations::new(nx, ny);
+        let leads = with_thread_pool(|| {
+            states.into_par_iter()
+                  .filter(|&dec| is_orbit_lead(dec, &trans))
+                  .collect()
+        });
+        Orbits { nx, ny, nup: Some(nup), leads }
+    }
+
+    pub fn norbits(&self) -> usize { self.leads.len() }
+
+    pub fn lattice(&self) -> (Dim, Dim) { (self.nx, self.ny) }
+
+    /// Every momentum in the Brillouin zone
+    pub fn momenta(&self) -> Vec<(K, K)> {
+        let (nx, ny) = (self.nx.raw_int(), self.ny.raw_int());
+        (0..nx).flat_map(|kx| (0..ny).map(move |ky| (K(kx), K(ky))))
+               .collect()
+    }
+
+    pub fn sector(&self, kx: K, ky: K) -> Sector {
+        let trans = Translations::new(self.nx, self.ny);
+        let bfuncs = with_thread_pool(|| {
+            self.leads
+                .par_iter()
+                .filter_map(|&dec| bloch_func(dec, &trans, kx, ky))
+                .collect()
+        });
+        let info = SectorInfo { nx: self.nx,
+                                ny: self.ny,
+                                kx,
+                                ky,
+                                nup: self.nup };
+        Sector::from_bfuncs(info, bfuncs)
+    }
+
+    /// The sectors at the given momenta, built one at a time as the iterator
+    /// is advanced
+    pub fn sectors<'a>(&'a self, momenta: &'a [(K, K)])
+                       -> impl Iterator<Item = Sector> + 'a {
+        momenta.iter().map(move |&(kx, ky)| self.sector(kx, ky))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(mat: CsrMatrix<CComplex<f64>>) -> Vec<(u32, u32, f64, f64)> {
+        mat.entries()
+           .into_iter()
+           .map(|(i, j, c)| (i, j, c.re, c.im))
+           .collect()
+    }
+
+    #[test]
+    fn sector_matches_consv_test() {
+        let (nx, ny) = (Dim(4), Dim(3));
+        let sector = Sector::k(nx, ny, K(1), K(2));
+        for l in 1..4 {
+            assert_eq!(entries(sector.ss_xy(I(l))),
+                       entries(consv::k::ss_xy(nx, ny, K(1), K(2), I(l))));
+        }
+        let sector = Sector::ks(nx, ny, K(1), K(2), 5);
+        assert_eq!(entries(sector.h_ss_z(I(1))),
+                   entries(consv::ks::h_ss_z(nx, ny, K(1), K(2), 5, I(1))));
+    }
+
+    #[test]
+    fn sz_conservation_test() {
+        let sector = Sector::ks(Dim(4), Dim(3), K(1), K(2), 5);
+        let err = Err(InputError::SzConservation(false));
+        assert_eq!(sector.h_ss_ppmm::<CComplex<f64>>(I(1)).map(|_| ()), err);
+        let dvecs = [[0., 0., 1.], [0., 0., 1.], [0., 0., 1.]];
+        assert!(sector.h_ss_dm::<CComplex<f64>>(I(1), &dvecs).is_ok());
+        let dvecs = [[1., 0., 0.], [0., 0., 1.], [0., 0., 1.]];
+        assert_eq!(sector.h_ss_dm::<CComplex<f64>>(I(1), &dvecs).map(|_| ()), err);
+    }
+
+    #[test]
+    fn single_precision_test() {
+        let sector = Sector::k(Dim(4), Dim(3), K(1), K(2));
+        let single = sector.h_ss_pmz::<CComplex<f32>>(I(1))
+                           .unwrap()
+                           .entries()
+                           .into_iter()
+                           .map(|(i, j, c)| (i, j, c.re, c.im))
+                           .collect::<Vec<_>>();
+        let double = entries(sector.h_ss_pmz(I(1)).unwrap()).into_iter()
+                                                  .map(|(i, j, re, im)| {
+                                                      (i, j, re as f32, im as f32)
+                                                  })
+                                                  .collect::<Vec<_>>();
+        assert_eq!(single, double);
+    }
+
+    #[test]
+    fn orbits_test() {
+        let (nx, ny) = (Dim(4), Dim(3));
+        let orbits = Orbits::new(nx, ny);
+        let momenta = orbits.momenta();
+        let mut total = 0;
+        for (sector, &(kx, ky)) in orbits.sectors(&momenta).zip(momenta.iter()) {
+            assert_eq!(sector.dim(), consv::k::bloch_states(nx, ny, kx, ky).nonzero);
+            assert_eq!(entries(sector.h_ss_xy(I(1))),
+                       entries(consv::k::h_ss_xy(nx, ny, kx, ky, I(1))));
+            total += sector.dim();
+        }
+        assert_eq!(total, 4096);
+
+        let orbits = Orbits::with_nup(nx, ny, 6);
+        let total = orbits.momenta()
+                          .into_iter()
+                          .map(|(kx, ky)| orbits.sector(kx, ky).dim())
+                          .sum::<u32>();
+        assert_eq!(total as u64, choose(nx * ny, 6));
+    }
+
+    #[test]
+    fn inverse_test() {
+        let close = |a: Vec<(u32, u32, f64, f64)>, b: Vec<(u32, u32, f64, f64)>| {
+            a.len() == b.len()
+            && a.iter().zip(b.iter()).all(|(x, y)| {
+                   (x.0, x.1) == (y.0, y.1)
+                   && (x.2 - y.2).abs() < 1e-12
+                   && (x.3 - y.3).abs() < 1e-12
+               })
+        };
+        let sector = Sector::k(Dim(4), Dim(3), K(1), K(2));
+        let inverse = sector.inverse();
+        let expected = Sector::k(Dim(4), Dim(3), K(3), K(1));
+        assert_eq!(inverse.info(), expected.info());
+        assert!(close(entries(inverse.h_ss_pmz(I(1)).unwrap()),
+                      entries(expected.h_ss_pmz(I(1)).unwrap())));
+        assert!(close(entries(inverse.h_sss_chi()), entries(expected.h_sss_chi())));
+    }
+
+    #[test]
+    fn save_load_test() {
+        let dir = ::std::env::temp_dir();
+        let (nx, ny) = (Dim(4), Dim(3));
+        let sector = Sector::ks(nx, ny, K(1), K(2), 5);
+        let path = dir.join("triangular_lattice_ext_sector_test.npz");
+        sector.save(&path).unwrap();
+        let loaded = Sector::load(&path).unwrap();
+        assert_eq!(loaded.info(), sector.info());
+        assert_eq!(loaded.dim(), sector.dim());
+        assert_eq!(entries(loaded.h_ss_xy(I(1))), entries(sector.h_ss_xy(I(1))));
+
+        let mat = sector.h_ss_xy(I(1));
+        let path = dir.join("triangular_lattice_ext_matrix_test.npz");
+        save_matrix(&path, &mat, &sector.info()).unwrap();
+        let (loaded, info) = load_matrix::<_, CComplex<f64>>(&path).unwrap();
+        assert_eq!(info, sector.info());
+        assert_eq!((loaded.nrows, loaded.ncols), (mat.nrows, mat.ncols));
+        assert_eq!(entries(loaded), entries(mat));
+    }
+
+    #[test]
+    fn malformed_load_test() {
+        let dir = ::std::env::temp_dir();
+        let path = dir.join("triangular_lattice_ext_malformed_test.npz");
+        let good = SectorInfo { nx:  Dim(2),
+                                ny:  Dim(2),
+                                kx:  K(0),
+                                ky:  K(0),
+                                nup: None };
+        let kind = |r: io::Result<()>| r.err().map(|e| e.kind());
+        let bad = Some(io::ErrorKind::InvalidData);
+
+        let save = |info: &SectorInfo, offsets: &[u64]| {
+            let mut w = NpzWriter::create(&path)?;
+            info.write(&mut w)?;
+            w.add("leads", &[0_u64, 1])?;
+            w.add("norms", &[1., 1.])?;
+            w.add("offsets", offsets)?;
+            w.add("states", &[0_u64, 1, 2, 4, 8])?;
+            w.add("phases", &[Complex::new(1., 0.); 5])?;
+            w.finish()?;
+            Sector::load(&path).map(|_| ())
+        };
+        assert_eq!(kind(save(&good, &[0, 1, 5])), None);
+        assert_eq!(kind(save(&good, &[0, 100, 5])), bad);
+        let info = SectorInfo { kx: K(2), ..good };
+        assert_eq!(kind(save(&info, &[0, 1, 5])), bad);
+        let info = SectorInfo { nup: Some(5), ..good };
+        assert_eq!(kind(save(&info, &[0, 1, 5])), bad);
+
+        let save = |shape: &[i64], indices: &[i64], indptr: &[i64]| {
+            let mut w = NpzWriter::create(&path)?;
+            w.add("shape", shape)?;
+            w.add("data", &[1., 2.])?;
+            w.add("indices", indices)?;
+            w.add("indptr", indptr)?;
+            good.write(&mut w)?;
+            w.finish()?;
+            load_matrix::<_, f64>(&path).map(|_| ())
+        };
+        assert_eq!(kind(save(&[2, 2], &[0, 1], &[0, 1, 2])), None);
+        // a negative shape, indptr not starting at 0 or decreasing and a
+        // column out of range
+        assert_eq!(kind(save(&[-1, 2], &[0, 1], &[0, 1, 2])), bad);
+        assert_eq!(kind(save(&[2, 2], &[0, 1], &[1, 1, 2])), bad);
+        assert_eq!(kind(save(&[2, 2], &[0, 1], &[0, 3, 2])), bad);
+        assert_eq!(kind(save(&[2, 2], &[0, 2], &[0, 1, 2])), bad);
+    }
+}
@@ -0,0 +1,509 @@
+/// Model specification files, which describe an exact diagonalization run
+/// declaratively so that it could be reviewed and repeated. They are written
+/// in TOML, or in JSON with the same layout when the file name ends in
+/// ".json":
+///
+/// ```toml
+/// [lattice]
+/// nx = 4
+/// ny = 3
+/// boundary = "periodic"      # optional, the only one supported
+///
+/// [couplings]                # all optional and zero by default
+/// J_pm = 0.5
+/// J_z = 1.0
+/// J_ppmm = 0.1
+/// J_pmz = 0.0
+/// J2 = 0.0
+/// J3 = 0.0
+/// chi = 0.0
+/// ring = 0.0
+///
+/// [sectors]                  # either all = true or a list of sectors
+/// list = [[0, 0, 6], [2, 0, 6]]
+///
+/// [solver]                   # optional
+/// nev = 2
+/// tol = 1e-10
+/// max_iter = 500
+/// threads = 0
+///
+/// [observables]              # optional, measured in every eigenstate
+/// terms = ["ss_z:1", "sss_chi"]
+///
+/// [sweep]                    # optional, couplings to vary over a grid
+/// J_ppmm = [0.0, 0.1, 0.2]
+/// J_pmz = { start = -1.0, stop = 1.0, steps = 21 }
+/// ```
+///
+/// A sweep replaces the swept couplings by each point of the grid spanned by
+/// the listed values in turn. Every point is solved in every sector, and total
+/// Sz counts as conserved only if it is at every point.
+///
+/// The couplings follow `hamiltonian_dp` on the Python side: J_pm multiplies
+/// Σ (S^+_i S^-_j + h.c.) and J_z Σ S^z_i S^z_j on nearest neighbor bonds,
+/// and J2 and J3 scale the same XXZ coupling, with anisotropy J_z / J_pm, on
+/// second and third nearest neighbor bonds.
+use serde_json;
+use std::{collections::BTreeMap, error::Error as StdError, fmt, fs, io, path::Path};
+use toml;
+
+use common::*;
+use model::{Model, Term};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// The file is not valid TOML or JSON, or its layout is not that of a spec
+    Syntax(String),
+    Input(InputError),
+    /// A spec that is well formed but inconsistent
+    Invalid(String)
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "{}", e),
+            Error::Syntax(ref msg) | Error::Invalid(ref msg) => write!(f, "{}", msg),
+            Error::Input(ref e) => write!(f, "{}", e)
+        }
+    }
+}
+
+impl StdError for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self { Error::Io(e) }
+}
+
+impl From<InputError> for Error {
+    fn from(e: InputError) -> Self { Error::Input(e) }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Boundary {
+    /// The only boundary condition with the translation symmetry momentum
+    /// sectors rely on
+    #[default]
+    Periodic
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Lattice {
+    pub nx:       u32,
+    pub ny:       u32,
+    #[serde(default)]
+    pub boundary: Boundary
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Couplings {
+    #[serde(rename = "J_pm")]
+    pub j_pm:   f64,
+    #[serde(rename = "J_z")]
+    pub j_z:    f64,
+    #[serde(rename = "J_ppmm")]
+    pub j_ppmm: f64,
+    #[serde(rename = "J_pmz")]
+    pub j_pmz:  f64,
+    #[serde(rename = "J2")]
+    pub j2:     f64,
+    #[serde(rename = "J3")]
+    pub j3:     f64,
+    pub chi:    f64,
+    pub ring:   f64
+}
+
+impl Couplings {
+    /// Sets the coupling spelled as in a spec file
+    pub fn set(&mut self, name: &str, value: f64) -> Result<(), Error> {
+        let j = match name {
+            "J_pm" => &mut self.j_pm,
+            "J_z" => &mut self.j_z,
+            "J_ppmm" => &mut self.j_ppmm,
+            "J_pmz" => &mut self.j_pmz,
+            "J2" => &mut self.j2,
+            "J3" => &mut self.j3,
+            "chi" => &mut self.chi,
+            "ring" => &mut self.ring,
+            _ => return Err(Error::Invalid(format!("unknown coupling {}", name)))
+        };
+        *j = value;
+        Ok(())
+    }
+
+    /// The couplings as Hamiltonian terms. `Sector` operators are written in
+    /// S^x and S^y, so J_pm is doubled on the way.
+    pub fn terms(&self) -> Result<Vec<(Term, f64)>, Error> {
+        let mut terms = vec![(Term::SsXy(I(1)), 2. * self.j_pm),
+                             (Term::SsZ(I(1)), self.j_z),
+                             (Term::SsPpmm(I(1)), self.j_ppmm),
+                             (Term::SsPmz(I(1)), self.j_pmz),
+                             (Term::SssChi, self.chi),
+                             (Term::Ring, self.ring)];
+        for &(l, j) in [(2, self.j2), (3, self.j3)].iter() {
+            if j == 0. {
+                continue;
+            }
+            if self.j_pm == 0. {
+                return Err(Error::Invalid(format!("J{} needs a nonzero J_pm to \
+                                                   fix the anisotropy",
+                                                  l)));
+            }
+            terms.push((Term::SsXy(I(l)), 2. * j));
+            terms.push((Term::SsZ(I(l)), j * self.j_z / self.j_pm));
+        }
+        Ok(terms.into_iter().filter(|&(_, j)| j != 0.).collect())
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+struct Sectors {
+    all:  bool,
+    /// Each either [kx, ky] or [kx, ky, nup]
+    list: Vec<Vec<u32>>
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Solver {
+    /// Eigenvalues per sector
+    pub nev:        usize,
+    pub tol:        f64,
+    /// Maximum number of Lanczos steps per sector. Every step keeps a vector
+    /// of the sector dimension, so this also bounds memory
+    pub max_iter:   usize,
+    /// Worker threads, 0 for one per core
+    pub threads:    usize,
+    /// Whether each point of a sweep starts the Lanczos iteration from the
+    /// ground state at the previous point
+    pub warm_start: bool
+}
+
+impl Default for Solver {
+    fn default() -> Self {
+        Solver { nev: 1, tol: 1e-10, max_iter: 500, threads: 0, warm_start: false }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+struct Observables {
+    /// Spelled as in `model::Term`
+    terms: Vec<String>
+}
+
+/// The values a coupling takes in a sweep, either listed or evenly spaced
+/// from start to stop inclusive
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+enum RawAxis {
+    Values(Vec<f64>),
+    Range { start: f64, stop: f64, steps: usize }
+}
+
+impl RawAxis {
+    fn values(&self, name: &str) -> Result<Vec<f64>, Error> {
+        match *self {
+            RawAxis::Values(ref values) => Ok(values.clone()),
+            RawAxis::Range { steps: 0, .. } => {
+                Err(Error::Invalid(format!("the range of {} has no steps", name)))
+            }
+            RawAxis::Range { start, steps: 1, .. } => Ok(vec![start]),
+            RawAxis::Range { start, stop, steps } => {
+                let step = (stop - start) / (steps - 1) as f64;
+                Ok((0..steps).map(|i| start + step * i as f64).collect())
+            }
+        }
+    }
+}
+
+/// The layout of a spec file
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct RawSpec {
+    lattice:     Lattice,
+    #[serde(default)]
+    couplings:   Couplings,
+    sectors:     Sectors,
+    #[serde(default)]
+    solver:      Solver,
+    #[serde(default)]
+    observables: Observables,
+    #[serde(default)]
+    sweep:       BTreeMap<String, RawAxis>
+}
+
+/// A coupling varied in a sweep
+#[derive(Clone, Debug, PartialEq)]
+pub struct Axis {
+    pub name:   String,
+    pub values: Vec<f64>
+}
+
+/// A point of a sweep
+#[derive(Clone, Debug, PartialEq)]
+pub struct Point {
+    /// The value of each swept coupling, in the order of `Spec::sweep`
+    pub values: Vec<f64>,
+    pub model:  Model
+}
+
+/// A validated spec
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spec {
+    /// The model at the couplings given outside of the sweep
+    pub model:       Model,
+    pub boundary:    Boundary,
+    /// Empty when nothing is swept
+    pub sweep:       Vec<Axis>,
+    /// Every point of the sweep, or just the model when nothing is swept
+    pub points:      Vec<Point>,
+    /// None for all irreducible sectors
+    pub sectors:     Option<Vec<(K, K, Option<u32>)>>,
+    pub solver:      Solver,
+    /// Terms whose expectation values are measured in every eigenstate
+    pub observables: Vec<Term>
+}
+
+impl Spec {
+    /// A spec for a single model without a sweep
+    pub fn from_model(model: Model, sectors: Option<Vec<(K, K, Option<u32>)>>)
+                      -> Spec {
+        Spec { points: vec![Point { values: Vec::new(), model: model.clone() }],
+               model,
+               boundary: Boundary::Periodic,
+               sweep: Vec::new(),
+               sectors,
+               solver: Solver::default(),
+               observables: Vec::new() }
+    }
+
+    /// A model with every term that has a nonzero coupling at some point of
+    /// the sweep. Its sectors are the ones the sweep is solved in.
+    pub fn sector_model(&self) -> Model {
+        let mut terms: Vec<(Term, f64)> = Vec::new();
+        for point in self.points.iter() {
+            for t in point.model.nonzero_terms() {
+                if terms.iter().all(|&(u, _)| u != t) {
+                    terms.push((t, 1.));
+                }
+            }
+        }
+        Model { terms, ..self.model.clone() }
+    }
+
+    pub fn from_toml(s: &str) -> Result<Spec, Error> {
+        let raw = toml::from_str(s).map_err(|e| Error::Syntax(e.to_string()))?;
+        Spec::from_raw(raw)
+    }
+
+    pub fn from_json(s: &str) -> Result<Spec, Error> {
+        let raw = serde_json::from_str(s).map_err(|e| Error::Syntax(e.to_string()))?;
+        Spec::from_raw(raw)
+    }
+
+    /// Reads a spec file, as JSON if its name ends in ".json" and as TOML
+    /// otherwise
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Spec, Error> {
+        let path = path.as_ref();
+        let s = fs::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => Spec::from_json(&s),
+            _ => Spec::from_toml(&s)
+        }
+    }
+
+    fn from_raw(raw: RawSpec) -> Result<Spec, Error> {
+        let (nx, ny) = (Dim(raw.lattice.nx), Dim(raw.lattice.ny));
+        let model = Model { nx, ny, terms: raw.couplings.terms()? };
+        model.check()?;
+
+        let sweep = raw.sweep
+                       .iter()
+                       .map(|(name, axis)| {
+                           Ok(Axis { name:   name.clone(),
+                                     values: axis.values(name)? })
+                       })
+                       .collect::<Result<Vec<_>, Error>>()?;
+        let mut points = Vec::new();
+        let npoints = sweep.iter().map(|a| a.values.len()).product::<usize>();
+        for n in 0..npoints {
+            // the last axis varies fastest
+            let (mut values, mut rest) = (vec![0.; sweep.len()], n);
+            let mut couplings = raw.couplings;
+            for (i, axis) in sweep.iter().enumerate().rev() {
+                values[i] = axis.values[rest % axis.values.len()];
+                rest /= axis.values.len();
+                couplings.set(&axis.name, values[i])?;
+            }
+            points.push(Point { values,
+                                model: Model { terms: couplings.terms()?,
+                                               ..model.clone() } });
+        }
+        if points.is_empty() {
+            return Err(Error::Invalid("a swept coupling has no values".to_string()));
+        }
+        let spec = Spec { model,
+                          boundary: raw.lattice.boundary,
+                          sweep,
+                          points,
+                          sectors: None,
+                          solver: raw.solver,
+                          observables: Vec::new() };
+        let model = spec.sector_model();
+        if model.terms.is_empty() {
+            return Err(Error::Invalid("every coupling is zero".to_string()));
+        }
+
+        let sectors = match (raw.sectors.all, raw.sectors.list.is_empty()) {
+            (true, true) => None,
+            (false, false) => {
+                let mut sectors = Vec::with_capacity(raw.sectors.list.len());
+                for fields in raw.sectors.list.iter() {
+                    let sector = match fields[..] {
+                        [kx, ky] => (K(kx), K(ky), None),
+                        [kx, ky, nup] => (K(kx), K(ky), Some(nup)),
+                        _ => {
+                            return Err(Error::Invalid(format!("a sector is \
+                                                               [kx, ky] or [kx, \
+                                                               ky, nup] but got \
+                                                               {:?}",
+                                                              fields)))
+                        }
+                    };
+                    model.check_sector(sector.0, sector.1, sector.2)?;
+                    sectors.push(sector);
+                }
+                Some(sectors)
+            }
+            _ => {
+                let msg = "sectors needs either all = true or a list of sectors";
+                return Err(Error::Invalid(msg.to_string()));
+            }
+        };
+
+        let observables = raw.observables
+                             .terms
+                             .iter()
+                             .map(|t| t.parse::<Term>())
+                             .collect::<Result<Vec<_>, _>>()?;
+        for &t in observables.iter() {
+            model.check_observable(t)?;
+        }
+        Ok(Spec { sectors, observables, ..spec })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOML: &str = r#"
+        [lattice]
+        nx = 4
+        ny = 3
+
+        [couplings]
+        J_pm = 0.5
+        J_z = 1.0
+        J2 = 0.2
+
+        [sectors]
+        list = [[0, 0, 6], [2, 0]]
+
+        [solver]
+        nev = 3
+
+        [observables]
+        terms = ["ss_z:1", "sss_chi"]
+    "#;
+
+    #[test]
+    fn toml_test() {
+        let toml = TOML.replace(", [2, 0]", ", [2, 0, 5]");
+        let spec = Spec::from_toml(&toml).unwrap();
+        assert_eq!(spec.model.terms,
+                   vec![(Term::SsXy(I(1)), 1.),
+                        (Term::SsZ(I(1)), 1.),
+                        (Term::SsXy(I(2)), 0.4),
+                        (Term::SsZ(I(2)), 0.4)]);
+        assert_eq!(spec.sectors,
+                   Some(vec![(K(0), K(0), Some(6)), (K(2), K(0), Some(5))]));
+        assert_eq!(spec.solver, Solver { nev: 3, ..Solver::default() });
+        assert_eq!(spec.observables, vec![Term::SsZ(I(1)), Term::SssChi]);
+        assert_eq!(spec.points.len(), 1);
+
+        // nup is required since every term conserves total Sz
+        match Spec::from_toml(TOML) {
+            Err(Error::Input(InputError::SzConservation(true))) => (),
+            other => panic!("unexpected {:?}", other)
+        }
+
+        // and for the same reason the observables must conserve it
+        let toml = toml.replace("\"sss_chi\"", "\"ss_ppmm:1\"");
+        match Spec::from_toml(&toml) {
+            Err(Error::Input(InputError::SzConservation(true))) => (),
+            other => panic!("unexpected {:?}", other)
+        }
+    }
+
+    #[test]
+    fn json_test() {
+        let json = r#"{
+            "lattice": {"nx": 3, "ny": 3},
+            "couplings": {"J_pm": 0.5, "J_ppmm": 0.1},
+            "sectors": {"all": true}
+        }"#;
+        let spec = Spec::from_json(json).unwrap();
+        assert_eq!(spec.sectors, None);
+        assert!(!spec.model.conserves_sz());
+        assert_eq!(spec.boundary, Boundary::Periodic);
+    }
+
+    #[test]
+    fn sweep_test() {
+        let toml = TOML.replace("[0, 0, 6]", "[0, 0]")
+                   + "[sweep]\nJ_ppmm = [0.0, 0.1]\n\
+                      J_pmz = { start = -1.0, stop = 1.0, steps = 3 }\n";
+        let spec = Spec::from_toml(&toml).unwrap();
+        // axes are ordered by name
+        assert_eq!(spec.sweep,
+                   vec![Axis { name:   "J_pmz".to_string(),
+                               values: vec![-1., 0., 1.] },
+                        Axis { name:   "J_ppmm".to_string(),
+                               values: vec![0., 0.1] }]);
+        assert_eq!(spec.points.len(), 6);
+        assert_eq!(spec.points[1].values, vec![-1., 0.1]);
+        assert!(spec.points[1].model.terms.contains(&(Term::SsPpmm(I(1)), 0.1)));
+        // J_ppmm and J_pmz break Sz conservation away from the origin, so
+        // sectors do not take nup
+        assert!(spec.points[2].model.conserves_sz());
+        assert!(!spec.points[0].model.conserves_sz());
+        assert!(!spec.sector_model().conserves_sz());
+        assert!(Spec::from_toml(&toml.replace("[0.0, 0.1]", "[]")).is_err());
+        assert!(Spec::from_toml(&toml.replace("J_ppmm = ", "J_pp = ")).is_err());
+        match Spec::from_toml(&toml.replace("steps = 3", "steps = 0")) {
+            Err(Error::Invalid(ref msg)) if msg.contains("J_pmz") => (),
+            _ => panic!("a range with no steps was accepted")
+        }
+    }
+
+    #[test]
+    fn invalid_test() {
+        let cases = [TOML.replace("nx = 4", "nx = 4\nboundary = \"open\""),
+                     TOML.replace("J_pm = 0.5", "J_pn = 0.5"),
+                     TOML.replace("J_pm = 0.5", "J_pm = 0.0"),
+                     TOML.replace("list = [[0, 0, 6], [2, 0]]", "all = false"),
+                     TOML.replace("\"sss_chi\"", "\"chi\""),
+                     TOML.replace("[2, 0]", "[2, 0, 6, 1]")];
+        for case in cases.iter() {
+            assert!(Spec::from_toml(case).is_err(), "accepted {}", case);
+        }
+    }
+}
@@ -0,0 +1,485 @@
+//! A native Python module exposing the momentum sectors and the Lanczos solver
+//! of `triangular_lattice_ext`. Operators come back as `scipy.sparse.csr_matrix`
+//! built from arrays that are converted once into numpy's element type and the
+//! index dtypes scipy uses, then handed to numpy without a further copy. Every
+//! object is freed by the Python garbage collector like any other.
+//!
+//! Build and install it with `pip install rust/triangular_lattice_py`.
+use std::path::PathBuf;
+
+use num_complex::Complex;
+use numpy::{
+    Complex32, Complex64, IntoPyArray, PyArray2, PyArrayMethods, PyReadonlyArray1,
+    PyReadonlyArray2
+};
+use pyo3::{exceptions::PyValueError, prelude::*};
+use triangular_lattice_ext::{
+    common::{self, check_bond, check_momentum, CComplex, CsrMatrix, Dim, InputError,
+             Orientation, I, K},
+    consv,
+    model::Hamiltonian,
+    sector::{self, SectorInfo}
+};
+
+fn input_error(e: InputError) -> PyErr { PyValueError::new_err(e.to_string()) }
+
+fn check_l(l: i32) -> PyResult<I> {
+    check_bond(I(l)).map_err(input_error)?;
+    Ok(I(l))
+}
+
+/// Matrix elements that could be handed to numpy
+trait ToNumpy: Send {
+    type Out: numpy::Element;
+    fn to_numpy(self) -> Self::Out;
+}
+
+impl ToNumpy for CComplex<f64> {
+    type Out = Complex64;
+    fn to_numpy(self) -> Complex64 { Complex64::new(self.re, self.im) }
+}
+
+impl ToNumpy for CComplex<f32> {
+    type Out = Complex32;
+    fn to_numpy(self) -> Complex32 { Complex32::new(self.re, self.im) }
+}
+
+/// Builds a matrix with the GIL released and hands it to a
+/// `scipy.sparse.csr_matrix`
+fn build<E, F>(py: Python<'_>, f: F) -> PyResult<Bound<'_, PyAny>>
+    where E: ToNumpy,
+          F: Send + FnOnce() -> Result<CsrMatrix<E>, InputError>
+{
+    let (shape, (data, indices, indptr)) = py.detach(|| {
+        let mat = f()?;
+        Ok(((mat.nrows, mat.ncols), mat.into_parts()))
+    }).map_err(input_error)?;
+    // index arrays are handed over in the dtypes scipy would otherwise convert
+    // them to
+    let data = data.into_iter().map(E::to_numpy).collect::<Vec<_>>();
+    let indices = indices.into_iter().map(|i| i as i32).collect::<Vec<_>>();
+    let indptr = indptr.into_iter().map(|i| i as i64).collect::<Vec<_>>();
+    let arrays =
+        (data.into_pyarray(py), indices.into_pyarray(py), indptr.into_pyarray(py));
+    py.import("scipy.sparse")?
+      .getattr("csr_matrix")?
+      .call1((arrays, shape))
+}
+
+/// Builds the matrix in single precision if $single is set and in double
+/// precision otherwise
+macro_rules! build {
+    ($py:expr, $single:expr, $mat:expr) => {
+        try_build!($py, $single, Ok($mat))
+    };
+}
+
+/// As `build!` for operators that could fail to build, such as those that do
+/// not conserve total Sz
+macro_rules! try_build {
+    ($py:expr, $single:expr, $mat:expr) => {
+        if $single {
+            build::<CComplex<f32>, _>($py, || $mat)
+        } else {
+            build::<CComplex<f64>, _>($py, || $mat)
+        }
+    };
+}
+
+/// A momentum sector whose Bloch basis is built once and reused for every
+/// operator constructed against it.
+///
+/// Sector(Nx, Ny, kx, ky, nup=None)
+///
+/// nup is the number of spins pointing up, or None if total Sz is not
+/// conserved.
+///
+/// Every operator could be built in single precision with single=True. It
+/// shares the basis and the sparsity pattern of the double precision matrix,
+/// so vectors found with it make good starting points for refining.
+#[pyclass(name = "Sector", module = "triangular_lattice_py", frozen)]
+struct PySector(sector::Sector);
+
+#[pymethods]
+impl PySector {
+    // the arguments are spelled as in the cffi wrapper so keyword calls
+    // written against it keep working
+    #[new]
+    #[pyo3(signature = (Nx, Ny, kx, ky, nup=None))]
+    #[allow(non_snake_case)]
+    fn new(py: Python<'_>, Nx: u32, Ny: u32, kx: u32, ky: u32, nup: Option<u32>)
+           -> PyResult<Self> {
+        let (nx, ny, kx, ky) = (Dim(Nx), Dim(Ny), K(kx), K(ky));
+        let sector = match nup {
+            None => {
+                consv::k::check(nx, ny, kx, ky).map_err(input_error)?;
+                py.detach(|| sector::Sector::k(nx, ny, kx, ky))
+            }
+            Some(nup) => {
+                consv::ks::check(nx, ny, kx, ky, nup).map_err(input_error)?;
+                py.detach(|| sector::Sector::ks(nx, ny, kx, ky, nup))
+            }
+        };
+        Ok(PySector(sector))
+    }
+
+    /// Loads a basis saved by `save`
+    #[staticmethod]
+    fn load(py: Python<'_>, path: PathBuf) -> PyResult<Self> {
+        Ok(PySector(py.detach(|| sector::Sector::load(path))?))
+    }
+
+    /// Saves the basis to path in .npz format
+    fn save(&self, py: Python<'_>, path: PathBuf) -> PyResult<()> {
+        Ok(py.detach(|| self.0.save(path))?)
+    }
+
+    #[getter]
+    fn dim(&self) -> u32 { self.0.dim() }
+
+    /// (nx, ny, kx, ky, nup) of the sector
+    #[getter]
+    fn info(&self) -> (u32, u32, u32, u32, Option<u32>) {
+        let SectorInfo { nx, ny, kx, ky, nup } = self.0.info();
+        (nx.raw_int(), ny.raw_int(), kx.raw_int(), ky.raw_int(), nup)
+    }
+
+    /// The sector at momentum -k, built from this one
+    fn inverse(&self, py: Python<'_>) -> PySector {
+        PySector(py.detach(|| self.0.inverse()))
+    }
+
+    /// The images of the rows of an (n, dim) array of vectors under the
+    /// inversion r -> -r, written in the basis of `inverse`. When the
+    /// Hamiltonian commutes with inversion its eigenvectors at k are carried
+    /// onto those at -k with the same energies.
+    fn invert<'py>(&self, py: Python<'py>, vectors: PyReadonlyArray2<'py, Complex64>)
+                   -> PyResult<Bound<'py, PyArray2<Complex64>>> {
+        let vectors = vectors.as_array();
+        if vectors.ncols() != self.0.dim() as usize {
+            return Err(PyValueError::new_err(format!("vectors of length {} do \
+                                                      not belong to a sector of \
+                                                      dimension {}",
+                                                     vectors.ncols(),
+                                                     self.0.dim())));
+        }
+        let vectors = vectors.rows()
+                             .into_iter()
+                             .map(|v| {
+                                 v.iter().map(|c| Complex::new(c.re, c.im)).collect()
+                             })
+                             .collect::<Vec<Vec<_>>>();
+        let shape = [vectors.len(), self.0.dim() as usize];
+        let images = py.detach(|| self.0.invert(&vectors))
+                       .into_iter()
+                       .flatten()
+                       .map(|c| Complex64::new(c.re, c.im))
+                       .collect::<Vec<_>>();
+        images.into_pyarray(py).reshape(shape)
+    }
+
+    // the sector is freed by the garbage collector. These only keep the
+    // `with` statements written against the cffi wrapper working
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> { slf }
+
+    fn __exit__(&self, _exc_type: &Bound<'_, PyAny>, _exc_value: &Bound<'_, PyAny>,
+                _traceback: &Bound<'_, PyAny>) {
+    }
+
+    #[pyo3(signature = (l, single=false))]
+    fn h_ss_z<'py>(&self, py: Python<'py>, l: i32, single: bool)
+                   -> PyResult<Bound<'py, PyAny>> {
+        let l = check_l(l)?;
+        build!(py, single, self.0.h_ss_z(l))
+    }
+
+    #[pyo3(signature = (l, single=false))]
+    fn h_ss_xy<'py>(&self, py: Python<'py>, l: i32, single: bool)
+                    -> PyResult<Bound<'py, PyAny>> {
+        let l = check_l(l)?;
+        build!(py, single, self.0.h_ss_xy(l))
+    }
+
+    #[pyo3(signature = (l, single=false))]
+    fn h_ss_ppmm<'py>(&self, py: Python<'py>, l: i32, single: bool)
+                      -> PyResult<Bound<'py, PyAny>> {
+        let l = check_l(l)?;
+        try_build!(py, single, self.0.h_ss_ppmm(l))
+    }
+
+    #[pyo3(signature = (l, single=false))]
+    fn h_ss_pmz<'py>(&self, py: Python<'py>, l: i32, single: bool)
+                     -> PyResult<Bound<'py, PyAny>> {
+        let l = check_l(l)?;
+        try_build!(py, single, self.0.h_ss_pmz(l))
+    }
+
+    #[pyo3(signature = (single=false))]
+    fn h_sss_chi<'py>(&self, py: Python<'py>, single: bool)
+                      -> PyResult<Bound<'py, PyAny>> {
+        build!(py, single, self.0.h_sss_chi())
+    }
+
+    #[pyo3(signature = (single=false))]
+    fn h_ring<'py>(&self, py: Python<'py>, single: bool)
+                   -> PyResult<Bound<'py, PyAny>> {
+        build!(py, single, self.0.h_ring())
+    }
+
+    /// jmats: the 3x3 exchange matrices of the three bond directions
+    #[pyo3(signature = (l, jmats, single=false))]
+    fn h_ss_tensor<'py>(&self, py: Python<'py>, l: i32, jmats: [[[f64; 3]; 3]; 3],
+                        single: bool)
+                        -> PyResult<Bound<'py, PyAny>> {
+        let l = check_l(l)?;
+        try_build!(py, single, self.0.h_ss_tensor(l, &jmats))
+    }
+
+    /// dvecs: the D vectors of the three bond directions
+    #[pyo3(signature = (l, dvecs, single=false))]
+    fn h_ss_dm<'py>(&self, py: Python<'py>, l: i32, dvecs: [[f64; 3]; 3],
+                    single: bool)
+                    -> PyResult<Bound<'py, PyAny>> {
+        let l = check_l(l)?;
+        try_build!(py, single, self.0.h_ss_dm(l, &dvecs))
+    }
+
+    #[pyo3(signature = (l, single=false))]
+    fn ss_z<'py>(&self, py: Python<'py>, l: i32, single: bool)
+                 -> PyResult<Bound<'py, PyAny>> {
+        let l = check_l(l)?;
+        build!(py, single, self.0.ss_z(l))
+    }
+
+    #[pyo3(signature = (l, single=false))]
+    fn ss_xy<'py>(&self, py: Python<'py>, l: i32, single: bool)
+                  -> PyResult<Bound<'py, PyAny>> {
+        let l = check_l(l)?;
+        build!(py, single, self.0.ss_xy(l))
+    }
+
+    /// o1, o2: 0 for upright triangles and 1 for inverted ones
+    #[pyo3(signature = (dx, dy, o1, o2, single=false))]
+    fn chi_chi<'py>(&self, py: Python<'py>, dx: i32, dy: i32, o1: u32, o2: u32,
+                    single: bool)
+                    -> PyResult<Bound<'py, PyAny>> {
+        let o1 = Orientation::from_raw(o1).map_err(input_error)?;
+        let o2 = Orientation::from_raw(o2).map_err(input_error)?;
+        build!(py, single, self.0.chi_chi(I(dx), I(dy), o1, o2))
+    }
+
+    #[pyo3(signature = (staggered=false, single=false))]
+    fn chi_order<'py>(&self, py: Python<'py>, staggered: bool, single: bool)
+                      -> PyResult<Bound<'py, PyAny>> {
+        build!(py, single, self.0.chi_order(staggered))
+    }
+}
+
+/// The translation orbits of the lattice, enumerated once and shared by every
+/// momentum sector built from them.
+///
+/// Orbits(Nx, Ny, nup=None)
+#[pyclass(name = "Orbits", module = "triangular_lattice_py", frozen)]
+struct PyOrbits(sector::Orbits);
+
+#[pymethods]
+impl PyOrbits {
+    #[new]
+    #[pyo3(signature = (Nx, Ny, nup=None))]
+    #[allow(non_snake_case)]
+    fn new(py: Python<'_>, Nx: u32, Ny: u32, nup: Option<u32>) -> PyResult<Self> {
+        let (nx, ny) = (Dim(Nx), Dim(Ny));
+        let orbits = match nup {
+            None => {
+                common::check_full_lattice(nx, ny).map_err(input_error)?;
+                py.detach(|| sector::Orbits::new(nx, ny))
+            }
+            Some(nup) => {
+                common::check_lattice(nx, ny).map_err(input_error)?;
+                common::check_nup(nx, ny, nup).map_err(input_error)?;
+                py.detach(|| sector::Orbits::with_nup(nx, ny, nup))
+            }
+        };
+        Ok(PyOrbits(orbits))
+    }
+
+    #[getter]
+    fn norbits(&self) -> usize { self.0.norbits() }
+
+    #[getter(Nx)]
+    fn nx(&self) -> u32 { self.0.lattice().0.raw_int() }
+
+    #[getter(Ny)]
+    fn ny(&self) -> u32 { self.0.lattice().1.raw_int() }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> { slf }
+
+    fn __exit__(&self, _exc_type: &Bound<'_, PyAny>, _exc_value: &Bound<'_, PyAny>,
+                _traceback: &Bound<'_, PyAny>) {
+    }
+
+    /// The Sector with momentum (kx, ky)
+    fn sector(&self, py: Python<'_>, kx: u32, ky: u32) -> PyResult<PySector> {
+        let (nx, ny) = self.0.lattice();
+        check_momentum(nx, ny, K(kx), K(ky)).map_err(input_error)?;
+        Ok(PySector(py.detach(|| self.0.sector(K(kx), K(ky)))))
+    }
+
+    /// Yields ((kx, ky), Sector) for each of the given momenta, or for every
+    /// momentum in the Brillouin zone if momenta is None. Each sector is built
+    /// only when it is reached
+    #[pyo3(signature = (momenta=None))]
+    fn sectors(slf: Bound<'_, Self>, momenta: Option<Vec<(u32, u32)>>)
+               -> PyResult<PySectors> {
+        let orbits = &slf.get().0;
+        let (nx, ny) = orbits.lattice();
+        let momenta = match momenta {
+            Some(momenta) => {
+                momenta.into_iter().map(|(kx, ky)| (K(kx), K(ky))).collect()
+            }
+            None => orbits.momenta()
+        };
+        for &(kx, ky) in momenta.iter() {
+            check_momentum(nx, ny, kx, ky).map_err(input_error)?;
+        }
+        Ok(PySectors { orbits:  slf.unbind(),
+                       momenta: momenta.into_iter() })
+    }
+}
+
+/// The iterator returned by `Orbits.sectors`
+#[pyclass(name = "Sectors", module = "triangular_lattice_py")]
+struct PySectors {
+    orbits:  Py<PyOrbits>,
+    momenta: std::vec::IntoIter<(K, K)>
+}
+
+#[pymethods]
+impl PySectors {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> { slf }
+
+    fn __next__(&mut self, py: Python<'_>) -> Option<((u32, u32), PySector)> {
+        let (kx, ky) = self.momenta.next()?;
+        let orbits = &self.orbits.get().0;
+        let sector = py.detach(|| orbits.sector(kx, ky));
+        Some(((kx.raw_int(), ky.raw_int()), PySector(sector)))
+    }
+}
+
+/// Takes a square scipy.sparse matrix apart into a `Hamiltonian`, checking the
+/// arrays so that a malformed matrix raises instead of panicking
+fn hamiltonian(h: &Bound<'_, PyAny>) -> PyResult<Hamiltonian> {
+    let h = h.call_method0("tocsr")?;
+    let (nrows, ncols): (usize, usize) = h.getattr("shape")?.extract()?;
+    if nrows != ncols || nrows > u32::MAX as usize {
+        return Err(PyValueError::new_err(format!("a {}x{} matrix is not a \
+                                                  Hamiltonian",
+                                                 nrows, ncols)));
+    }
+    let array = |name: &str, dtype: &str| {
+        h.getattr(name)?.call_method1("astype", (dtype,))
+    };
+    let data = array("data", "complex128")?;
+    let data = data.extract::<PyReadonlyArray1<'_, Complex64>>()?;
+    let indices = array("indices", "int64")?;
+    let indices = indices.extract::<PyReadonlyArray1<'_, i64>>()?;
+    let indptr = array("indptr", "int64")?;
+    let indptr = indptr.extract::<PyReadonlyArray1<'_, i64>>()?;
+    let (data, indices, indptr) =
+        (data.as_array(), indices.as_array(), indptr.as_array());
+
+    let nnz = data.len() as i64;
+    let malformed = indptr.len() != nrows + 1
+                    || indices.len() != data.len()
+                    || indptr[0] != 0
+                    || indptr[nrows] != nnz
+                    || indptr.iter().zip(indptr.iter().skip(1)).any(|(a, b)| a > b)
+                    || indices.iter().any(|&c| c < 0 || c >= ncols as i64);
+    if malformed {
+        return Err(PyValueError::new_err("malformed CSR matrix"));
+    }
+    let data = data.iter().map(|c| CComplex { re: c.re, im: c.im }).collect();
+    let indices = indices.iter().map(|&c| c as u32).collect();
+    let indptr = indptr.iter().map(|&p| p as u64).collect();
+    let mat =
+        CsrMatrix::from_parts(data, indices, indptr, nrows as u32, ncols as u32);
+    Ok(Hamiltonian::from_matrix(mat))
+}
+
+/// The lowest nev eigenvalues, in ascending order, of the Hermitian matrix H
+/// by the Lanczos method. H is any scipy.sparse matrix. Iteration stops when
+/// none of the eigenvalues moves by more than tol from one step to the next,
+/// or after max_iter steps.
+#[pyfunction]
+#[pyo3(signature = (H, nev=1, tol=1e-10, max_iter=500))]
+#[allow(non_snake_case)]
+fn lowest_eigenvalues(py: Python<'_>, H: &Bound<'_, PyAny>, nev: usize, tol: f64,
+                      max_iter: usize)
+                      -> PyResult<Vec<f64>> {
+    let h = hamiltonian(H)?;
+    Ok(py.detach(|| h.lowest_eigenvalues(nev, tol, max_iter)))
+}
+
+/// As `lowest_eigenvalues` but also returns the normalized eigenvectors as the
+/// rows of an (nev, dim) array, the layout `Sector.invert` takes. start is an
+/// optional guess for the ground state, such as that of a nearby Hamiltonian.
+#[pyfunction]
+#[pyo3(signature = (H, nev=1, tol=1e-10, max_iter=500, start=None))]
+#[allow(non_snake_case)]
+fn lowest_eigenpairs<'py>(py: Python<'py>, H: &Bound<'py, PyAny>, nev: usize,
+                          tol: f64, max_iter: usize,
+                          start: Option<PyReadonlyArray1<'py, Complex64>>)
+                          -> PyResult<(Vec<f64>, Bound<'py, PyArray2<Complex64>>)> {
+    let h = hamiltonian(H)?;
+    let start = start.map(|start| {
+                          start.as_array()
+                               .iter()
+                               .map(|c| Complex::new(c.re, c.im))
+                               .collect::<Vec<_>>()
+                      });
+    if let Some(ref start) = start {
+        if start.len() != h.dim() {
+            return Err(PyValueError::new_err(format!("a start vector of length \
+                                                      {} does not belong to a \
+                                                      matrix of dimension {}",
+                                                     start.len(),
+                                                     h.dim())));
+        }
+    }
+    let (eigs, vectors) =
+        py.detach(|| h.lowest_eigenpairs(start.as_deref(), nev, tol, max_iter));
+    let shape = [vectors.len(), h.dim()];
+    let vectors = vectors.into_iter()
+                         .flatten()
+                         .map(|c| Complex64::new(c.re, c.im))
+                         .collect::<Vec<_>>();
+    Ok((eigs, vectors.into_pyarray(py).reshape(shape)?))
+}
+
+/// Sets the number of threads used to build bases and matrices. 0 restores the
+/// default of one thread per logical core.
+#[pyfunction]
+fn set_num_threads(n: usize) { common::set_num_threads(n) }
+
+/// One momentum out of each pair k, -k of the lattice as (kx, ky,
+/// multiplicity), the multiplicity being the number of momenta it stands for
+#[pyfunction]
+fn irreducible_momenta(nx: u32, ny: u32) -> PyResult<Vec<(u32, u32, u32)>> {
+    common::check_lattice(Dim(nx), Dim(ny)).map_err(input_error)?;
+    Ok(common::irreducible_momenta(Dim(nx), Dim(ny))
+        .into_iter()
+        .map(|(kx, ky, m)| (kx.raw_int(), ky.raw_int(), m))
+        .collect())
+}
+
+#[pymodule]
+fn triangular_lattice_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PySector>()?;
+    m.add_class::<PyOrbits>()?;
+    m.add_class::<PySectors>()?;
+    m.add_function(wrap_pyfunction!(set_num_threads, m)?)?;
+    m.add_function(wrap_pyfunction!(irreducible_momenta, m)?)?;
+    m.add_function(wrap_pyfunction!(lowest_eigenvalues, m)?)?;
+    m.add_function(wrap_pyfunction!(lowest_eigenpairs, m)?)?;
+    Ok(())
+}